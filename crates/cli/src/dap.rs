@@ -0,0 +1,672 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::mpsc::{self, Receiver, Sender};
+use std::sync::{Arc, Mutex};
+use std::thread::JoinHandle;
+
+use mx::debugger::{
+    statement_lines, Breakpoints, DebugFrontend, Debugger, Pause, PauseReason, Resume,
+};
+use mx::interpreter::{Interpreter, InterpreterValue, RuntimeErrorKind};
+use mx::io::Io;
+use mx::source_file::{AnalyzedSourceFile, UnparsedSourceFile};
+use serde_json::{json, Value};
+
+use crate::{exit_code, EXIT_RUNTIME_ERROR, RUN_STACK_SIZE};
+
+// Programs only have one thread
+const THREAD_ID: u64 = 1;
+// The variables reference of the globals. The locals of frame `i` are `i + 2`, as references must
+// be positive.
+const GLOBALS_REFERENCE: u64 = 1;
+
+/// Reads a message framed by a `Content-Length` header, or `None` at the end of the input.
+fn read_message(input: &mut impl BufRead) -> std::io::Result<Option<Value>> {
+    let mut length = None;
+    loop {
+        let mut header = String::new();
+        if input.read_line(&mut header)? == 0 {
+            return Ok(None);
+        }
+        let header = header.trim_end();
+        if header.is_empty() {
+            break;
+        }
+        if let Some(value) = header.strip_prefix("Content-Length:") {
+            length = value.trim().parse::<usize>().ok();
+        }
+    }
+    let Some(length) = length else {
+        return Err(std::io::Error::new(
+            std::io::ErrorKind::InvalidData,
+            "Message has no Content-Length header",
+        ));
+    };
+    let mut body = vec![0; length];
+    input.read_exact(&mut body)?;
+    serde_json::from_slice(&body)
+        .map(Some)
+        .map_err(std::io::Error::from)
+}
+
+/// Writes messages to the client, numbering them in the order they are sent. The server and the
+/// program thread share it.
+struct Output<W> {
+    writer: Mutex<W>,
+    seq: AtomicU64,
+}
+
+impl<W: Write> Output<W> {
+    fn send(&self, mut message: Value) {
+        message["seq"] = json!(self.seq.fetch_add(1, Ordering::SeqCst) + 1);
+        let body = message.to_string();
+        let mut writer = self.writer.lock().expect("Output lock is poisoned");
+        // Failing to write means the client is gone, which ends the session once its next request
+        // cannot be read
+        let _ = write!(writer, "Content-Length: {}\r\n\r\n{}", body.len(), body);
+        let _ = writer.flush();
+    }
+
+    fn event(&self, event: &str, body: Value) {
+        self.send(json!({ "type": "event", "event": event, "body": body }));
+    }
+
+    fn response(&self, request: &Value, result: Result<Value, String>) {
+        let mut response = json!({
+            "type": "response",
+            "request_seq": request["seq"],
+            "command": request["command"],
+            "success": result.is_ok(),
+        });
+        match result {
+            Ok(body) => response["body"] = body,
+            Err(message) => response["message"] = json!(message),
+        }
+        self.send(response);
+    }
+}
+
+/// Forwards what the program writes to the client as `output` events.
+struct OutputWriter<W> {
+    output: Arc<Output<W>>,
+    category: &'static str,
+}
+
+impl<W: Write> Write for OutputWriter<W> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.output.event(
+            "output",
+            json!({ "category": self.category, "output": String::from_utf8_lossy(buf) }),
+        );
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+/// What the server asks of the program thread.
+enum Command {
+    Resume(Resume),
+    SetBreakpoints(Vec<usize>),
+}
+
+/// The state of the program that the server reads while it handles requests.
+#[derive(Default)]
+struct Session {
+    pause: Mutex<Option<Snapshot>>,
+    pause_requested: AtomicBool,
+}
+
+/// A pause as the server sees it. Values on the heap cannot leave the program thread, so the
+/// variables are converted to their protocol representation before they are handed over.
+#[derive(Clone)]
+struct Snapshot {
+    // Innermost first, with the name, line and locals of each frame
+    frames: Vec<(String, usize, Vec<Value>)>,
+    globals: Vec<Value>,
+}
+
+impl Snapshot {
+    fn new(pause: &Pause) -> Self {
+        let variables = |variables: &[(String, Option<InterpreterValue>)]| {
+            variables
+                .iter()
+                .map(|(name, value)| {
+                    json!({
+                        "name": name,
+                        "value": value.as_ref().map_or("<unassigned>".to_string(), |value| value.to_string()),
+                        "type": value.as_ref().map(|value| value.type_name()),
+                        "variablesReference": 0,
+                    })
+                })
+                .collect()
+        };
+        Self {
+            frames: pause
+                .frames
+                .iter()
+                .map(|frame| (frame.name.clone(), frame.line, variables(&frame.locals)))
+                .collect(),
+            globals: variables(&pause.globals),
+        }
+    }
+
+    /// Finds a variable of the innermost frame, or a global.
+    fn variable(&self, name: &str) -> Option<&Value> {
+        self.frames
+            .first()
+            .into_iter()
+            .flat_map(|(_, _, locals)| locals)
+            .chain(&self.globals)
+            .find(|variable| variable["name"] == name)
+    }
+}
+
+/// Reports pauses to the client and waits for the server to resume the program.
+struct DapFrontend<W> {
+    output: Arc<Output<W>>,
+    session: Arc<Session>,
+    commands: Receiver<Command>,
+    stopping: bool,
+}
+
+impl<W: Write> DapFrontend<W> {
+    fn apply(&mut self, command: Command, breakpoints: &mut Breakpoints) -> Option<Resume> {
+        match command {
+            Command::SetBreakpoints(lines) => {
+                breakpoints.clear();
+                for line in lines {
+                    breakpoints.insert(line);
+                }
+                None
+            }
+            Command::Resume(resume) => Some(resume),
+        }
+    }
+}
+
+impl<W: Write> DebugFrontend for DapFrontend<W> {
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Resume {
+        if self.stopping {
+            return Resume::Stop;
+        }
+        *self.session.pause.lock().expect("Session lock is poisoned") = Some(Snapshot::new(pause));
+        let reason = match pause.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::Request => "pause",
+        };
+        self.output.event(
+            "stopped",
+            json!({ "reason": reason, "threadId": THREAD_ID, "allThreadsStopped": true }),
+        );
+
+        // The server is gone when the channel is closed
+        while let Ok(command) = self.commands.recv() {
+            if let Some(resume) = self.apply(command, breakpoints) {
+                return resume;
+            }
+        }
+        Resume::Stop
+    }
+
+    fn poll(&mut self, breakpoints: &mut Breakpoints) -> bool {
+        while let Ok(command) = self.commands.try_recv() {
+            // Only a request to stop matters while the program runs, which it does by pausing
+            if self.apply(command, breakpoints) == Some(Resume::Stop) {
+                self.stopping = true;
+                return true;
+            }
+        }
+        self.session.pause_requested.swap(false, Ordering::SeqCst)
+    }
+}
+
+/// A launched program, which only starts running once the client is done configuring it.
+struct Program {
+    path: String,
+    file: Option<AnalyzedSourceFile>,
+    statement_lines: BTreeSet<usize>,
+    stop_on_entry: bool,
+}
+
+/// Debugs a program with the Debug Adapter Protocol, reading requests from `input` and writing
+/// responses and events to `output`.
+pub fn serve<W: Write + Send + 'static>(mut input: impl BufRead, output: W) {
+    let output = Arc::new(Output {
+        writer: Mutex::new(output),
+        seq: AtomicU64::new(0),
+    });
+    let mut server = Server {
+        output: output.clone(),
+        session: Arc::new(Session::default()),
+        program: None,
+        breakpoints: vec![],
+        commands: None,
+        thread: None,
+    };
+
+    // A message that cannot be read ends the session like the end of the input does
+    while let Ok(Some(request)) = read_message(&mut input) {
+        let command = request["command"].as_str().unwrap_or_default().to_string();
+        let result = server.handle(&command, &request["arguments"]);
+        let succeeded = result.is_ok();
+        output.response(&request, result);
+
+        match command.as_str() {
+            "initialize" => output.event("initialized", json!({})),
+            "configurationDone" if succeeded => server.start(),
+            "disconnect" => break,
+            _ => {}
+        }
+    }
+
+    server.stop();
+    if let Some(thread) = server.thread.take() {
+        let _ = thread.join();
+    }
+}
+
+struct Server<W> {
+    output: Arc<Output<W>>,
+    session: Arc<Session>,
+    program: Option<Program>,
+    // The lines breakpoints were resolved to
+    breakpoints: Vec<usize>,
+    // Talks to the program thread once it runs
+    commands: Option<Sender<Command>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl<W: Write + Send + 'static> Server<W> {
+    fn handle(&mut self, command: &str, arguments: &Value) -> Result<Value, String> {
+        match command {
+            "initialize" => Ok(json!({
+                "supportsConfigurationDoneRequest": true,
+                "supportsTerminateRequest": true,
+            })),
+            "launch" => self.launch(arguments),
+            "setBreakpoints" => self.set_breakpoints(arguments),
+            "configurationDone" => match self.program {
+                Some(_) => Ok(json!({})),
+                None => Err("No program was launched".to_string()),
+            },
+            "threads" => Ok(json!({ "threads": [{ "id": THREAD_ID, "name": "main" }] })),
+            "stackTrace" => self.stack_trace(),
+            "scopes" => {
+                let frame_id = arguments["frameId"].as_u64().unwrap_or_default();
+                Ok(json!({ "scopes": [
+                    { "name": "Locals", "variablesReference": frame_id + 2, "expensive": false },
+                    { "name": "Globals", "variablesReference": GLOBALS_REFERENCE, "expensive": false },
+                ] }))
+            }
+            "variables" => self.variables(arguments),
+            "evaluate" => self.evaluate(arguments),
+            "continue" => self
+                .resume(Resume::Continue)
+                .map(|_| json!({ "allThreadsContinued": true })),
+            "next" => self.resume(Resume::StepOver),
+            "stepIn" => self.resume(Resume::StepIn),
+            "stepOut" => self.resume(Resume::StepOut),
+            "pause" => {
+                self.session.pause_requested.store(true, Ordering::SeqCst);
+                Ok(json!({}))
+            }
+            "disconnect" | "terminate" => {
+                self.stop();
+                Ok(json!({}))
+            }
+            _ => Err(format!("Unsupported request: {}", command)),
+        }
+    }
+
+    fn launch(&mut self, arguments: &Value) -> Result<Value, String> {
+        let path = arguments["program"]
+            .as_str()
+            .ok_or("Launch needs the path of the program")?;
+        let source = std::fs::read_to_string(path)
+            .map_err(|error| format!("Cannot read {}: {}", path, error))?;
+        let file = UnparsedSourceFile::new(path, &source).parse().analyze();
+        if let Some(diagnostic) = file.file().diagnostics.first() {
+            return Err(format!(
+                "{}:{}: {}",
+                path,
+                diagnostic.range.start.row + 1,
+                diagnostic.kind.message()
+            ));
+        }
+
+        self.program = Some(Program {
+            path: path.to_string(),
+            statement_lines: statement_lines(&file),
+            file: Some(file),
+            stop_on_entry: arguments["stopOnEntry"].as_bool().unwrap_or(false),
+        });
+        Ok(json!({}))
+    }
+
+    fn set_breakpoints(&mut self, arguments: &Value) -> Result<Value, String> {
+        let program = self.program.as_ref().ok_or("No program was launched")?;
+        let requested = arguments["breakpoints"]
+            .as_array()
+            .cloned()
+            .unwrap_or_default();
+
+        // Breakpoints move to the next line with a statement, and are unverified without one
+        let mut breakpoints = vec![];
+        self.breakpoints.clear();
+        for breakpoint in requested {
+            let line = breakpoint["line"].as_u64().unwrap_or_default() as usize;
+            match program.statement_lines.range(line..).next() {
+                Some(&line) => {
+                    self.breakpoints.push(line);
+                    breakpoints.push(json!({ "verified": true, "line": line }));
+                }
+                None => breakpoints.push(json!({ "verified": false, "line": line })),
+            }
+        }
+
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(Command::SetBreakpoints(self.breakpoints.clone()));
+        }
+        Ok(json!({ "breakpoints": breakpoints }))
+    }
+
+    fn stack_trace(&self) -> Result<Value, String> {
+        let pause = self.paused()?;
+        let path = self.program.as_ref().map_or("", |program| &program.path);
+        let name = std::path::Path::new(path)
+            .file_name()
+            .map_or(path.into(), |name| name.to_string_lossy());
+        let frames: Vec<_> = pause
+            .frames
+            .iter()
+            .enumerate()
+            .map(|(id, (frame_name, line, _))| {
+                json!({
+                    "id": id,
+                    "name": frame_name,
+                    "line": line,
+                    "column": 1,
+                    "source": { "name": name, "path": path },
+                })
+            })
+            .collect();
+        Ok(json!({ "stackFrames": frames, "totalFrames": frames.len() }))
+    }
+
+    fn variables(&self, arguments: &Value) -> Result<Value, String> {
+        let pause = self.paused()?;
+        let variables = match arguments["variablesReference"].as_u64() {
+            Some(GLOBALS_REFERENCE) => &pause.globals,
+            Some(reference) => pause
+                .frames
+                .get(reference.saturating_sub(2) as usize)
+                .map(|(_, _, locals)| locals)
+                .ok_or("Unknown variables reference")?,
+            None => return Err("Unknown variables reference".to_string()),
+        };
+        Ok(json!({ "variables": variables }))
+    }
+
+    /// Evaluates the name of a variable, which is the only kind of expression supported.
+    fn evaluate(&self, arguments: &Value) -> Result<Value, String> {
+        let pause = self.paused()?;
+        let name = arguments["expression"].as_str().unwrap_or_default().trim();
+        match pause.variable(name) {
+            Some(variable) => Ok(json!({ "result": variable["value"], "variablesReference": 0 })),
+            None => Err(format!("No variable named {}", name)),
+        }
+    }
+
+    fn paused(&self) -> Result<Snapshot, String> {
+        self.session
+            .pause
+            .lock()
+            .expect("Session lock is poisoned")
+            .clone()
+            .ok_or_else(|| "The program is not paused".to_string())
+    }
+
+    fn resume(&mut self, resume: Resume) -> Result<Value, String> {
+        self.paused()?;
+        *self.session.pause.lock().expect("Session lock is poisoned") = None;
+        if let Some(commands) = &self.commands {
+            let _ = commands.send(Command::Resume(resume));
+        }
+        Ok(json!({}))
+    }
+
+    /// Starts running the launched program on a thread of its own.
+    fn start(&mut self) {
+        let Some(file) = self
+            .program
+            .as_mut()
+            .and_then(|program| program.file.take())
+        else {
+            return;
+        };
+        let stop_on_entry = self
+            .program
+            .as_ref()
+            .is_some_and(|program| program.stop_on_entry);
+        let mut breakpoints = Breakpoints::new();
+        for line in &self.breakpoints {
+            breakpoints.insert(*line);
+        }
+        let (commands, receiver) = mpsc::channel();
+        self.commands = Some(commands);
+
+        let output = self.output.clone();
+        let session = self.session.clone();
+        let thread = std::thread::Builder::new()
+            .stack_size(RUN_STACK_SIZE)
+            .spawn(move || {
+                let frontend = DapFrontend {
+                    output: output.clone(),
+                    session,
+                    commands: receiver,
+                    stopping: false,
+                };
+                let mut debugger = Debugger::new(frontend).with_breakpoints(breakpoints);
+                if !stop_on_entry {
+                    debugger = debugger.without_stop_on_entry();
+                }
+                let io = Io {
+                    stdout: Box::new(OutputWriter {
+                        output: output.clone(),
+                        category: "stdout",
+                    }),
+                    stderr: Box::new(OutputWriter {
+                        output: output.clone(),
+                        category: "stderr",
+                    }),
+                    stdin: Box::new(std::io::empty()),
+                    trace: None,
+                };
+
+                let result = Interpreter::new(&file)
+                    .with_io(io)
+                    .with_debugger(debugger)
+                    .execute();
+                let code = match result.map(exit_code) {
+                    Ok(Ok(code)) => code,
+                    Ok(Err(message)) => {
+                        output.event("output", json!({ "category": "stderr", "output": message }));
+                        EXIT_RUNTIME_ERROR
+                    }
+                    Err(error) => {
+                        if *error.kind != RuntimeErrorKind::Stopped {
+                            output.event(
+                                "output",
+                                json!({ "category": "stderr", "output": error.render(file.file()) }),
+                            );
+                        }
+                        EXIT_RUNTIME_ERROR
+                    }
+                };
+                output.event("exited", json!({ "exitCode": code }));
+                output.event("terminated", json!({}));
+            })
+            .expect("Failed to spawn the program thread");
+        self.thread = Some(thread);
+    }
+
+    /// Stops the program, wherever it is.
+    fn stop(&mut self) {
+        if let Some(commands) = self.commands.take() {
+            let _ = commands.send(Command::Resume(Resume::Stop));
+        }
+    }
+}
+
+#[cfg(all(test, unix))]
+mod tests {
+    use super::*;
+    use std::io::BufReader;
+    use std::os::unix::net::UnixStream;
+
+    const SOURCE: &str = r#"fn double(n: Int32): Int32 {
+    var result = n * 2;
+    return result;
+}
+
+fn main(): Int32 {
+    var a = 1;
+    var b = double(a);
+    println("sum", a + b);
+    return a + b;
+}
+"#;
+
+    /// Talks to the server the way an editor would, keeping the events it has not waited for yet.
+    struct Client {
+        reader: BufReader<UnixStream>,
+        writer: UnixStream,
+        seq: u64,
+        events: Vec<Value>,
+    }
+
+    impl Client {
+        fn request(&mut self, command: &str, arguments: Value) -> Value {
+            self.seq += 1;
+            let body = json!({
+                "seq": self.seq,
+                "type": "request",
+                "command": command,
+                "arguments": arguments,
+            })
+            .to_string();
+            write!(
+                self.writer,
+                "Content-Length: {}\r\n\r\n{}",
+                body.len(),
+                body
+            )
+            .unwrap();
+
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if message["type"] == "response" && message["request_seq"] == self.seq {
+                    return message;
+                }
+                self.events.push(message);
+            }
+        }
+
+        fn event(&mut self, event: &str) -> Value {
+            if let Some(i) = self
+                .events
+                .iter()
+                .position(|message| message["event"] == event)
+            {
+                return self.events.remove(i);
+            }
+            loop {
+                let message = read_message(&mut self.reader).unwrap().unwrap();
+                if message["event"] == event {
+                    return message;
+                }
+                self.events.push(message);
+            }
+        }
+    }
+
+    #[test]
+    fn test_debug_session() {
+        let path = std::env::temp_dir().join(format!("mx-dap-{}.mx", std::process::id()));
+        std::fs::write(&path, SOURCE).unwrap();
+
+        let (server_stream, client_stream) = UnixStream::pair().unwrap();
+        let server = std::thread::spawn(move || {
+            serve(
+                BufReader::new(server_stream.try_clone().unwrap()),
+                server_stream,
+            )
+        });
+        let mut client = Client {
+            reader: BufReader::new(client_stream.try_clone().unwrap()),
+            writer: client_stream,
+            seq: 0,
+            events: vec![],
+        };
+
+        let response = client.request("initialize", json!({ "adapterID": "mx" }));
+        assert_eq!(response["body"]["supportsConfigurationDoneRequest"], true);
+        client.event("initialized");
+        let response = client.request("launch", json!({ "program": path }));
+        assert_eq!(response["success"], true);
+        let response = client.request(
+            "setBreakpoints",
+            json!({ "source": { "path": path }, "breakpoints": [{ "line": 2 }, { "line": 11 }] }),
+        );
+        assert_eq!(
+            response["body"]["breakpoints"],
+            json!([{ "verified": true, "line": 2 }, { "verified": false, "line": 11 }])
+        );
+        client.request("configurationDone", json!({}));
+
+        let stopped = client.event("stopped");
+        assert_eq!(stopped["body"]["reason"], "breakpoint");
+        let response = client.request("stackTrace", json!({ "threadId": THREAD_ID }));
+        let frames: Vec<_> = response["body"]["stackFrames"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|frame| (frame["name"].clone(), frame["line"].clone()))
+            .collect();
+        assert_eq!(
+            frames,
+            vec![(json!("double"), json!(2)), (json!("main"), json!(8))]
+        );
+        let response = client.request("variables", json!({ "variablesReference": 2 }));
+        assert_eq!(
+            response["body"]["variables"],
+            json!([{ "name": "n", "value": "1", "type": "Int32", "variablesReference": 0 }])
+        );
+
+        client.request("next", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.event("stopped")["body"]["reason"], "step");
+        let response = client.request("evaluate", json!({ "expression": "result" }));
+        assert_eq!(response["body"]["result"], "2");
+
+        client.request("continue", json!({ "threadId": THREAD_ID }));
+        assert_eq!(client.event("exited")["body"]["exitCode"], 3);
+        client.event("terminated");
+        let output: String = client
+            .events
+            .iter()
+            .filter(|message| message["event"] == "output")
+            .map(|message| message["body"]["output"].as_str().unwrap().to_string())
+            .collect();
+        assert_eq!(output, "sum 3\n");
+
+        client.request("disconnect", json!({}));
+        server.join().unwrap();
+        std::fs::remove_file(path).unwrap();
+    }
+}
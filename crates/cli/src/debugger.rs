@@ -0,0 +1,255 @@
+use std::collections::BTreeSet;
+use std::io::{BufRead, Write};
+
+use mx::debugger::{statement_lines, Breakpoints, DebugFrontend, Pause, PauseReason, Resume};
+use mx::interpreter::InterpreterValue;
+use mx::source_file::AnalyzedSourceFile;
+
+const HELP: &str = "\
+Commands:
+  step, s            Run to the next statement, entering calls
+  next, n            Run to the next statement of this function
+  finish, f          Run until this function returns
+  continue, c        Run until the next breakpoint
+  break, b [line]    Set a breakpoint, or list them without a line
+  delete, d <line>   Remove a breakpoint
+  print, p <name>    Print a variable
+  locals             Print the variables of this function
+  globals            Print the global variables
+  backtrace, bt      Print the active calls
+  list, l            Print the source around this line
+  quit, q            Stop the program
+  help, h            Print this help";
+
+// The number of lines `list` prints before and after the current one
+const LIST_CONTEXT: usize = 3;
+
+/// Drives `mx run --debug` with commands read from a terminal.
+pub struct ConsoleDebugger<R, W> {
+    source: Vec<String>,
+    statement_lines: BTreeSet<usize>,
+    input: R,
+    output: W,
+}
+
+impl<R: BufRead, W: Write> ConsoleDebugger<R, W> {
+    pub fn new(file: &AnalyzedSourceFile, input: R, output: W) -> Self {
+        Self {
+            source: file.file().src.lines().map(str::to_string).collect(),
+            statement_lines: statement_lines(file),
+            input,
+            output,
+        }
+    }
+
+    /// Reads commands until one of them resumes the program.
+    fn prompt(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> std::io::Result<Resume> {
+        let frame = pause.frames.first();
+        let reason = match pause.reason {
+            PauseReason::Entry => "entry",
+            PauseReason::Breakpoint => "breakpoint",
+            PauseReason::Step => "step",
+            PauseReason::Request => "pause",
+        };
+        writeln!(
+            self.output,
+            "Paused at line {} in {} ({})",
+            pause.line(),
+            frame.map_or("<top level>", |frame| frame.name.as_str()),
+            reason
+        )?;
+        self.write_line(pause.line(), true)?;
+
+        loop {
+            write!(self.output, "(mx) ")?;
+            self.output.flush()?;
+            let mut command = String::new();
+            if self.input.read_line(&mut command)? == 0 {
+                // The terminal is gone, so let the program run to completion
+                return Ok(Resume::Continue);
+            }
+            let mut words = command.split_whitespace();
+            let Some(name) = words.next() else {
+                continue;
+            };
+            let argument = words.next();
+            match name {
+                "step" | "s" => return Ok(Resume::StepIn),
+                "next" | "n" => return Ok(Resume::StepOver),
+                "finish" | "f" => return Ok(Resume::StepOut),
+                "continue" | "c" => return Ok(Resume::Continue),
+                "quit" | "q" => return Ok(Resume::Stop),
+                "break" | "b" => match argument.map(str::parse::<usize>) {
+                    None => {
+                        let lines: Vec<_> =
+                            breakpoints.iter().map(|line| line.to_string()).collect();
+                        if lines.is_empty() {
+                            writeln!(self.output, "No breakpoints")?;
+                        } else {
+                            writeln!(self.output, "Breakpoints at lines {}", lines.join(", "))?;
+                        }
+                    }
+                    Some(Ok(line)) => match self.statement_lines.range(line..).next().copied() {
+                        Some(line) => {
+                            breakpoints.insert(line);
+                            writeln!(self.output, "Breakpoint set at line {}", line)?;
+                        }
+                        None => writeln!(self.output, "No statement at or after line {}", line)?,
+                    },
+                    Some(Err(_)) => writeln!(self.output, "Expected a line number")?,
+                },
+                "delete" | "d" => match argument.map(str::parse::<usize>) {
+                    Some(Ok(line)) if breakpoints.remove(line) => {
+                        writeln!(self.output, "Breakpoint removed from line {}", line)?
+                    }
+                    Some(Ok(line)) => writeln!(self.output, "No breakpoint at line {}", line)?,
+                    _ => writeln!(self.output, "Expected a line number")?,
+                },
+                "print" | "p" => match argument {
+                    Some(variable) => match pause.variable(variable) {
+                        Some(value) => self.write_variable(variable, value)?,
+                        None => writeln!(self.output, "No variable named {}", variable)?,
+                    },
+                    None => writeln!(self.output, "Expected a variable name")?,
+                },
+                "locals" => {
+                    self.write_variables(frame.map_or(&[], |frame| frame.locals.as_slice()))?
+                }
+                "globals" => self.write_variables(&pause.globals)?,
+                "backtrace" | "bt" => {
+                    for (i, frame) in pause.frames.iter().enumerate() {
+                        writeln!(self.output, "#{} {} at line {}", i, frame.name, frame.line)?;
+                    }
+                }
+                "list" | "l" => {
+                    let line = pause.line();
+                    let first = line.saturating_sub(LIST_CONTEXT).max(1);
+                    for number in first..=(line + LIST_CONTEXT).min(self.source.len()) {
+                        self.write_line(number, number == line)?;
+                    }
+                }
+                "help" | "h" => writeln!(self.output, "{}", HELP)?,
+                _ => writeln!(
+                    self.output,
+                    "Unknown command: {}. Type help for a list of commands.",
+                    name
+                )?,
+            }
+        }
+    }
+
+    /// Prints a line of the source, marking the one the program paused at.
+    fn write_line(&mut self, number: usize, current: bool) -> std::io::Result<()> {
+        let Some(text) = self.source.get(number.wrapping_sub(1)) else {
+            return Ok(());
+        };
+        let marker = if current { "->" } else { "  " };
+        writeln!(self.output, "{} {:>4} | {}", marker, number, text)
+    }
+
+    fn write_variable(
+        &mut self,
+        name: &str,
+        value: &Option<InterpreterValue>,
+    ) -> std::io::Result<()> {
+        match value {
+            Some(value) => writeln!(self.output, "{} = {}", name, value),
+            None => writeln!(self.output, "{} is not assigned yet", name),
+        }
+    }
+
+    fn write_variables(
+        &mut self,
+        variables: &[(String, Option<InterpreterValue>)],
+    ) -> std::io::Result<()> {
+        if variables.is_empty() {
+            return writeln!(self.output, "No variables");
+        }
+        for (name, value) in variables {
+            self.write_variable(name, value)?;
+        }
+        Ok(())
+    }
+}
+
+impl<R: BufRead, W: Write> DebugFrontend for ConsoleDebugger<R, W> {
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Resume {
+        // Without a terminal to talk to, let the program run to completion
+        self.prompt(pause, breakpoints).unwrap_or(Resume::Continue)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mx::debugger::Debugger;
+    use mx::interpreter::Interpreter;
+    use mx::io::{Io, SharedBuffer};
+    use mx::source_file::UnparsedSourceFile;
+
+    const SOURCE: &str = r#"fn double(n: Int32): Int32 {
+    var result = n * 2;
+    return result;
+}
+
+fn main(): Int32 {
+    var a = 1;
+    var b = double(a);
+    return a + b;
+}
+"#;
+
+    fn debug(commands: &str) -> String {
+        let analyzed = UnparsedSourceFile::new("test.mx", SOURCE).parse().analyze();
+        let output = SharedBuffer::default();
+        let console = ConsoleDebugger::new(
+            &analyzed,
+            std::io::Cursor::new(commands.as_bytes().to_vec()),
+            output.clone(),
+        );
+        let result = Interpreter::new(&analyzed)
+            .with_io(Io::captured("").0)
+            .with_debugger(Debugger::new(console))
+            .execute();
+        assert_eq!(result, Ok(Some(InterpreterValue::Integer(3))));
+        output.contents()
+    }
+
+    #[test]
+    fn test_breakpoints_and_inspection() {
+        let output = debug("break 2\nbreak\ncontinue\nprint n\nbacktrace\nlocals\nc\n");
+        assert_eq!(
+            output,
+            "Paused at line 7 in main (entry)\n\
+             ->    7 |     var a = 1;\n\
+             (mx) Breakpoint set at line 2\n\
+             (mx) Breakpoints at lines 2\n\
+             (mx) Paused at line 2 in double (breakpoint)\n\
+             ->    2 |     var result = n * 2;\n\
+             (mx) n = 1\n\
+             (mx) #0 double at line 2\n\
+             #1 main at line 8\n\
+             (mx) n = 1\n\
+             (mx) "
+        );
+    }
+
+    #[test]
+    fn test_stepping_commands() {
+        let output = debug("next\nstep\nfinish\nbogus\nc\n");
+        let paused: Vec<_> = output
+            .lines()
+            .filter(|line| line.contains("Paused"))
+            .collect();
+        assert_eq!(
+            paused,
+            vec![
+                "Paused at line 7 in main (entry)",
+                "(mx) Paused at line 8 in main (step)",
+                "(mx) Paused at line 2 in double (step)",
+                "(mx) Paused at line 9 in main (step)",
+            ]
+        );
+        assert!(output.contains("Unknown command: bogus."));
+    }
+}
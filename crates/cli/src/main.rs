@@ -1,12 +1,95 @@
+mod dap;
+mod debugger;
 mod server;
 
-use mx::interpreter::Interpreter;
+use debugger::ConsoleDebugger;
+use mx::debugger::Debugger;
+use mx::interpreter::{Interpreter, InterpreterValue};
+use mx::io::Io;
+use mx::limits::Limits;
 use mx::source_file::UnparsedSourceFile;
+use mx::vm::Vm;
 use server::MXLanguageServer;
 use std::io::Read as _;
 use std::io::*;
+use std::time::Duration;
 use tower_lsp::{LspService, Server};
 
+/// The exit code of `mx run` when its flags are invalid.
+const EXIT_USAGE: i32 = 64;
+/// The exit code of `mx run` when the program has diagnostics.
+const EXIT_DIAGNOSTICS: i32 = 65;
+/// The exit code of `mx run` when the program fails with a runtime error.
+const EXIT_RUNTIME_ERROR: i32 = 70;
+/// The exit codes `mx run` reports its own failures with, which `main` cannot return.
+const RESERVED_EXIT_CODES: [i32; 3] = [EXIT_USAGE, EXIT_DIAGNOSTICS, EXIT_RUNTIME_ERROR];
+/// The stack size of the thread `mx run` runs programs on.
+const RUN_STACK_SIZE: usize = 64 << 20;
+
+/// Converts the value returned by `main` into a process exit code. An integer becomes the code, and
+/// any other value exits successfully. Integers the operating system would truncate and the
+/// reserved codes are an error, since they would be mistaken for other codes.
+fn exit_code(exit_value: Option<InterpreterValue>) -> std::result::Result<i32, String> {
+    match exit_value {
+        Some(InterpreterValue::Integer(value)) => match i32::try_from(value) {
+            Ok(code @ 0..=255) if !RESERVED_EXIT_CODES.contains(&code) => Ok(code),
+            _ => Err(format!(
+                "error: main returned {}, which is not a valid exit code\n",
+                value
+            )),
+        },
+        _ => Ok(0),
+    }
+}
+
+/// The flags accepted by `mx run`.
+struct RunOptions {
+    vm: bool,
+    trace: bool,
+    debug: bool,
+    limits: Limits,
+}
+
+fn parse_run_options(args: &[String]) -> std::result::Result<RunOptions, String> {
+    let mut options = RunOptions {
+        vm: false,
+        trace: false,
+        debug: false,
+        limits: Limits::default(),
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = |name: &str| -> std::result::Result<u64, String> {
+            let value = args
+                .next()
+                .ok_or_else(|| format!("{} expects a value", name))?;
+            value
+                .parse()
+                .map_err(|_| format!("{} expects a number, found '{}'", name, value))
+        };
+        match arg.as_str() {
+            // Runs the program on the bytecode VM instead of walking the MXIR
+            "--vm" => options.vm = true,
+            // Dumps the MXIR and traces the execution to standard error
+            "--trace" => options.trace = true,
+            // Pauses the program and reads debugger commands from the terminal
+            "--debug" => options.debug = true,
+            "--fuel" => options.limits.fuel = Some(value("--fuel")?),
+            "--max-call-depth" => {
+                options.limits.max_call_depth = value("--max-call-depth")? as usize
+            }
+            "--timeout" => {
+                options.limits.timeout = Some(Duration::from_millis(value("--timeout")?))
+            }
+            _ => return Err(format!("Unknown flag: {}", arg)),
+        }
+    }
+    if options.debug && options.vm {
+        return Err("--debug cannot be combined with --vm".to_string());
+    }
+    Ok(options)
+}
+
 #[tokio::main]
 async fn main() {
     // Check first arg to get the command
@@ -24,6 +107,10 @@ async fn main() {
             let (service, socket) = LspService::new(MXLanguageServer::new);
             Server::new(stdin, stdout, socket).serve(service).await;
         }
+        "dap" => {
+            let stdin = std::io::stdin();
+            dap::serve(stdin.lock(), std::io::stdout());
+        }
         "compile" => {
             // Read all of stdin into a string
             let mut input = String::new();
@@ -37,6 +124,17 @@ async fn main() {
             println!("{}", c_file.c());
         }
         "run" => {
+            let options = match parse_run_options(&args[2..]) {
+                Ok(options) => options,
+                Err(message) => {
+                    eprintln!("{}", message);
+                    eprintln!(
+                        "Usage: {} run [--vm] [--trace] [--debug] [--fuel <steps>] [--max-call-depth <calls>] [--timeout <milliseconds>]",
+                        args[0]
+                    );
+                    std::process::exit(EXIT_USAGE);
+                }
+            };
             let mut input = String::new();
             stdin()
                 .read_to_string(&mut input)
@@ -46,13 +144,74 @@ async fn main() {
             let analyzed_file = parsed_src_file.analyze();
             if !analyzed_file.file().diagnostics.is_empty() {
                 eprintln!("Diagnostics: {:#?}", analyzed_file.file().diagnostics);
-                return;
+                std::process::exit(EXIT_DIAGNOSTICS);
             }
-            for inst in &analyzed_file.mxir().0 {
-                println!("{:?}", inst);
+            if options.trace {
+                for inst in &analyzed_file.mxir().0 {
+                    eprintln!("{:?}", inst);
+                }
+            }
+            // The program is read from standard input, so the debugger talks to the terminal
+            let terminal = if options.debug {
+                match std::fs::OpenOptions::new()
+                    .read(true)
+                    .write(true)
+                    .open("/dev/tty")
+                {
+                    Ok(terminal) => Some(terminal),
+                    Err(error) => {
+                        eprintln!("--debug needs a terminal to read commands from: {}", error);
+                        std::process::exit(EXIT_USAGE);
+                    }
+                }
+            } else {
+                None
+            };
+            // A large stack spares the recursive interpreter from switching to new stack segments
+            let result = std::thread::scope(|scope| {
+                std::thread::Builder::new()
+                    .stack_size(RUN_STACK_SIZE)
+                    .spawn_scoped(scope, || {
+                        let mut io = Io::std();
+                        if options.trace {
+                            io.trace = Some(Box::new(stderr()));
+                        }
+                        let result = if options.vm {
+                            Vm::new(&analyzed_file)
+                                .with_limits(options.limits)
+                                .with_io(io)
+                                .execute()
+                        } else {
+                            let mut interpreter = Interpreter::new(&analyzed_file)
+                                .with_limits(options.limits)
+                                .with_io(io);
+                            if let Some(terminal) = terminal {
+                                let input = BufReader::new(
+                                    terminal.try_clone().expect("Failed to open the terminal"),
+                                );
+                                let console = ConsoleDebugger::new(&analyzed_file, input, terminal);
+                                interpreter = interpreter.with_debugger(Debugger::new(console));
+                            }
+                            interpreter.execute()
+                        };
+                        // Values on the heap cannot leave the thread, so only the exit code does
+                        result.map(exit_code)
+                    })
+                    .expect("Failed to spawn the program thread")
+                    .join()
+                    .expect("The program thread panicked")
+            });
+            match result {
+                Ok(Ok(code)) => std::process::exit(code),
+                Ok(Err(message)) => {
+                    eprint!("{}", message);
+                    std::process::exit(EXIT_RUNTIME_ERROR);
+                }
+                Err(error) => {
+                    eprint!("{}", error.render(analyzed_file.file()));
+                    std::process::exit(EXIT_RUNTIME_ERROR);
+                }
             }
-            let mut interpreter = Interpreter::new(&analyzed_file);
-            interpreter.execute();
         }
         "version" => {
             let version = env!("CARGO_PKG_VERSION");
@@ -64,3 +223,23 @@ async fn main() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_exit_code() {
+        use InterpreterValue::{Integer, String};
+
+        assert_eq!(exit_code(Some(Integer(0))), Ok(0));
+        assert_eq!(exit_code(Some(Integer(3))), Ok(3));
+        assert_eq!(exit_code(Some(Integer(255))), Ok(255));
+        assert_eq!(exit_code(Some(String("done".to_string()))), Ok(0));
+        assert_eq!(exit_code(None), Ok(0));
+        // Codes the process could not report unchanged or that mx run reports failures with
+        for value in [-1, 256, 64, 65, 70] {
+            assert!(exit_code(Some(Integer(value))).is_err(), "{}", value);
+        }
+    }
+}
@@ -0,0 +1,94 @@
+//! Compares the tree-walking interpreter with the bytecode VM.
+//!
+//! Run with `cargo bench -p mx --bench execution`.
+
+use std::time::{Duration, Instant};
+
+use mx::interpreter::Interpreter;
+use mx::source_file::{AnalyzedSourceFile, UnparsedSourceFile};
+use mx::vm::Vm;
+
+const ITERATIONS: u32 = 10;
+
+const FIB: &str = r#"
+    fn fib(n: Int32): Int32 {
+        if n < 2 {
+            return n;
+        }
+        return fib(n - 1) + fib(n - 2);
+    }
+
+    fn main(): Int32 {
+        return fib(20);
+    }
+"#;
+
+const LOOPS: &str = r#"
+    fn main(): Int32 {
+        var total = 0;
+        loop i in 0 to 200 {
+            loop j in 0 to 200 {
+                if i == j {
+                    continue;
+                }
+                total = total + i * j - i / (j + 1);
+            }
+        }
+        return total;
+    }
+"#;
+
+const COLLECTIONS: &str = r#"
+    fn main(): Int32 {
+        var values = [0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        loop i in 0 to 5000 {
+            var slot = i - i / 10 * 10;
+            values[slot] = values[slot] + 1;
+        }
+        var total = 0;
+        loop value in values {
+            total = total + value;
+        }
+        return total;
+    }
+"#;
+
+fn time(mut run: impl FnMut()) -> Duration {
+    let start = Instant::now();
+    for _ in 0..ITERATIONS {
+        run();
+    }
+    start.elapsed() / ITERATIONS
+}
+
+fn bench(name: &str, source: &str) {
+    let analyzed: AnalyzedSourceFile = UnparsedSourceFile::new(name, source).parse().analyze();
+    assert!(
+        analyzed.file().diagnostics.is_empty(),
+        "{}: {:?}",
+        name,
+        analyzed.file().diagnostics
+    );
+    let expected = Interpreter::new(&analyzed).execute();
+    assert_eq!(Vm::new(&analyzed).execute(), expected, "{}", name);
+
+    let interpreter = time(|| {
+        Interpreter::new(&analyzed).execute().unwrap();
+    });
+    let vm = time(|| {
+        Vm::new(&analyzed).execute().unwrap();
+    });
+    println!(
+        "{:<12} interpreter {:>10.2?}  vm {:>10.2?}  ({:.1}x)",
+        name,
+        interpreter,
+        vm,
+        interpreter.as_secs_f64() / vm.as_secs_f64()
+    );
+}
+
+fn main() {
+    bench("fib", FIB);
+    bench("loops", LOOPS);
+    bench("collections", COLLECTIONS);
+}
@@ -17,7 +17,7 @@ impl Ast {
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Hash)]
 pub struct AstNodeRef(pub u32);
 
 #[derive(Debug, Clone)]
@@ -0,0 +1,216 @@
+use std::io::{BufRead, Write};
+use std::sync::Arc;
+
+use crate::interpreter::{InterpreterValue, RuntimeErrorKind};
+use crate::io::Io;
+use crate::types::Type;
+
+/// The result of calling a builtin function: the value of the call, if any.
+pub type BuiltinResult = Result<Option<InterpreterValue>, RuntimeErrorKind>;
+
+/// The native implementation of a builtin function. It receives the streams of the program and
+/// the evaluated arguments of a call, and returns the value of the call, if any.
+pub type NativeFn = fn(&mut Io, &[InterpreterValue]) -> BuiltinResult;
+
+// The signature shared by builtin and host functions
+type BuiltinClosure = dyn Fn(&mut Io, &[InterpreterValue]) -> BuiltinResult + Send + Sync;
+
+/// A function implemented in Rust that programs can call, either a builtin or a function
+/// registered by the host.
+#[derive(Clone)]
+pub struct BuiltinFn(Arc<BuiltinClosure>);
+
+impl BuiltinFn {
+    pub fn new(
+        f: impl Fn(&mut Io, &[InterpreterValue]) -> BuiltinResult + Send + Sync + 'static,
+    ) -> Self {
+        Self(Arc::new(f))
+    }
+
+    /// Calls the function with the evaluated arguments of a call.
+    pub fn call(&self, io: &mut Io, args: &[InterpreterValue]) -> BuiltinResult {
+        (self.0)(io, args)
+    }
+}
+
+impl std::fmt::Debug for BuiltinFn {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.write_str("BuiltinFn")
+    }
+}
+
+/// Describes a builtin function made available to every program.
+pub struct Builtin {
+    pub name: &'static str,
+    pub return_type: Type,
+    pub f: NativeFn,
+}
+
+/// The builtin functions, in the order in which they are declared.
+pub const BUILTINS: &[Builtin] = &[
+    Builtin {
+        name: "print",
+        return_type: Type::Void,
+        f: print,
+    },
+    Builtin {
+        name: "println",
+        return_type: Type::Void,
+        f: println,
+    },
+    Builtin {
+        name: "eprint",
+        return_type: Type::Void,
+        f: eprint,
+    },
+    Builtin {
+        name: "eprintln",
+        return_type: Type::Void,
+        f: eprintln,
+    },
+    Builtin {
+        name: "readln",
+        return_type: Type::String,
+        f: readln,
+    },
+];
+
+/// Writes the arguments to standard output, separated by spaces.
+pub fn print(
+    io: &mut Io,
+    args: &[InterpreterValue],
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    write_args(&mut io.stdout, args, "")
+}
+
+/// Writes the arguments to standard output, separated by spaces and followed by a line break.
+pub fn println(
+    io: &mut Io,
+    args: &[InterpreterValue],
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    write_args(&mut io.stdout, args, "\n")
+}
+
+/// Writes the arguments to standard error, separated by spaces.
+pub fn eprint(
+    io: &mut Io,
+    args: &[InterpreterValue],
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    write_args(&mut io.stderr, args, "")
+}
+
+/// Writes the arguments to standard error, separated by spaces and followed by a line break.
+pub fn eprintln(
+    io: &mut Io,
+    args: &[InterpreterValue],
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    write_args(&mut io.stderr, args, "\n")
+}
+
+/// Reads a line from standard input, without its line break. At the end of the input it returns an
+/// empty string.
+pub fn readln(
+    io: &mut Io,
+    _args: &[InterpreterValue],
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    let mut line = String::new();
+    io.stdin
+        .read_line(&mut line)
+        .map_err(|error| RuntimeErrorKind::Io(error.to_string()))?;
+    let len = line.trim_end_matches(['\n', '\r']).len();
+    line.truncate(len);
+    Ok(Some(InterpreterValue::String(line)))
+}
+
+/// Formats values the way the print builtins do.
+pub fn format_args(args: &[InterpreterValue]) -> String {
+    args.iter()
+        .map(|arg| arg.to_string())
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn write_args(
+    writer: &mut dyn Write,
+    args: &[InterpreterValue],
+    terminator: &str,
+) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+    write!(writer, "{}{}", format_args(args), terminator)
+        .and_then(|_| writer.flush())
+        .map_err(|error| RuntimeErrorKind::Io(error.to_string()))?;
+    Ok(None)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::heap::Shared;
+    use crate::interpreter::{MapKey, StructValue};
+
+    #[test]
+    fn test_format_args() {
+        let args = [
+            InterpreterValue::String("total:".to_string()),
+            InterpreterValue::Integer(42),
+            InterpreterValue::Boolean(true),
+        ];
+        assert_eq!(format_args(&args), "total: 42 true");
+        assert_eq!(format_args(&[]), "");
+    }
+
+    #[test]
+    fn test_format_nested_values() {
+        let list = InterpreterValue::List(Shared::new(vec![
+            InterpreterValue::Integer(1),
+            InterpreterValue::String("two".to_string()),
+        ]));
+        assert_eq!(format_args(&[list]), "[1, \"two\"]");
+
+        let map = InterpreterValue::Map(Shared::new(BTreeMap::from([(
+            MapKey::String("retries".to_string()),
+            InterpreterValue::Integer(3),
+        )])));
+        assert_eq!(format_args(&[map]), "map{\"retries\": 3}");
+
+        let person = InterpreterValue::Struct(Shared::new(StructValue {
+            name: "Person".to_string(),
+            fields: vec![
+                (
+                    "name".to_string(),
+                    InterpreterValue::String("Bob".to_string()),
+                ),
+                ("age".to_string(), InterpreterValue::Integer(42)),
+            ],
+        }));
+        assert_eq!(format_args(&[person]), "Person { name: \"Bob\", age: 42 }");
+    }
+
+    #[test]
+    fn test_print_builtins_write_to_io() {
+        let (mut io, output) = Io::captured("");
+        let args = [
+            InterpreterValue::String("x =".to_string()),
+            InterpreterValue::Integer(1),
+        ];
+        assert_eq!(println(&mut io, &args), Ok(None));
+        assert_eq!(print(&mut io, &args), Ok(None));
+        assert_eq!(eprintln(&mut io, &args), Ok(None));
+        assert_eq!(output.stdout.contents(), "x = 1\nx = 1");
+        assert_eq!(output.stderr.contents(), "x = 1\n");
+    }
+
+    #[test]
+    fn test_readln() {
+        let (mut io, _) = Io::captured("first\r\nsecond\nlast");
+        let read = |io: &mut Io| match readln(io, &[]) {
+            Ok(Some(InterpreterValue::String(line))) => line,
+            result => panic!("Unexpected result: {:?}", result),
+        };
+        assert_eq!(read(&mut io), "first");
+        assert_eq!(read(&mut io), "second");
+        assert_eq!(read(&mut io), "last");
+        assert_eq!(read(&mut io), "");
+    }
+}
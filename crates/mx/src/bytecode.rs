@@ -0,0 +1,773 @@
+use std::collections::{HashMap, VecDeque};
+
+use crate::ast::AstNodeRef;
+use crate::builtins::BuiltinFn;
+use crate::interpreter::{InterpreterValue, RuntimeErrorKind};
+use crate::mxir::{BinaryOp, MxirNode, MxirNodeData, MxirNodeRef, UnaryOp};
+use crate::source_file::AnalyzedSourceFile;
+
+/// The index of the function holding the top-level code of the source file. Its slots are the
+/// globals.
+pub const TOP_LEVEL: u32 = 0;
+
+/// Where a variable is stored. Outer variables belong to the function `depth` levels up the chain
+/// of lexically enclosing functions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Place {
+    Local(u32),
+    Outer { depth: u32, slot: u32 },
+    Global(u32),
+}
+
+/// An instruction of the stack machine. Every expression and statement leaves exactly one entry
+/// on the stack, which is empty for statements and calls that do not produce a value.
+#[derive(Debug, Clone)]
+pub enum Op {
+    Constant(u32),
+    Void,
+    Pop,
+    /// Fails if the top of the stack is empty.
+    Require,
+    Load(Place),
+    /// Pops a value into a variable. An empty value leaves it uninitialized.
+    Declare(Place),
+    /// Assigns the top of the stack to a variable, leaving it on the stack.
+    Store(Place),
+    Jump(u32),
+    /// Pops the condition of an `if` and jumps unless it holds.
+    JumpIfFalse(u32),
+    /// Jumps, keeping the left operand as the result, if it alone decides an `and` or `or`.
+    ShortCircuit(BinaryOp, u32),
+    Binary(BinaryOp),
+    Unary(UnaryOp),
+    List(u32),
+    /// Builds a map from as many key and value pairs.
+    Map(u32),
+    /// Fails if the top of the stack cannot be used as a map key.
+    CheckKey,
+    KeyAccess {
+        map: MxirNodeRef,
+    },
+    Index {
+        object: MxirNodeRef,
+        index: MxirNodeRef,
+    },
+    Field {
+        name: u32,
+        object: MxirNodeRef,
+    },
+    /// Builds a struct of the given shape from the values of its fields.
+    Struct {
+        shape: u32,
+        len: u32,
+    },
+    /// Fails if the top of the stack is not an integer.
+    CheckRangeBound,
+    Range {
+        start: bool,
+        end: bool,
+    },
+    Stringify,
+    /// Pops a struct and sets its field to the value below it.
+    SetField {
+        name: u32,
+        object: MxirNodeRef,
+    },
+    /// Pops a list and an index and sets the element to the value below them.
+    SetIndex {
+        object: MxirNodeRef,
+        index: MxirNodeRef,
+    },
+    /// Pops a map and a key and sets the key to the value below them.
+    SetKey {
+        map: MxirNodeRef,
+    },
+    Call {
+        function: u32,
+        argc: u32,
+    },
+    CallBuiltin {
+        f: BuiltinFn,
+        argc: u32,
+    },
+    Return,
+    /// Leaves the current function with a `break` or `continue` that is not inside a loop.
+    EscapeLoop,
+    /// Pops an iterable and starts iterating over it.
+    IterStart,
+    /// Stores the next value of the innermost iteration in a slot, or jumps once it is exhausted.
+    IterNext {
+        slot: u32,
+        exit: u32,
+    },
+    IterEnd,
+    Fail(Box<RuntimeErrorKind>),
+}
+
+impl Op {
+    /// The number of entries the instruction adds to the stack, when it falls through.
+    fn stack_effect(&self) -> isize {
+        match self {
+            Op::Constant(_) | Op::Void | Op::Load(_) => 1,
+            Op::Pop | Op::Declare(_) | Op::JumpIfFalse(_) | Op::Binary(_) => -1,
+            Op::KeyAccess { .. } | Op::Index { .. } | Op::Return | Op::IterStart => -1,
+            Op::SetField { .. } => -1,
+            Op::SetIndex { .. } | Op::SetKey { .. } => -2,
+            Op::List(len) => 1 - *len as isize,
+            Op::Map(len) => 1 - 2 * *len as isize,
+            Op::Range { start, end } => 1 - *start as isize - *end as isize,
+            Op::Struct { len, .. } => 1 - *len as isize,
+            Op::Call { argc, .. } | Op::CallBuiltin { argc, .. } => 1 - *argc as isize,
+            _ => 0,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Function {
+    pub name: String,
+    /// The function this one is nested in, or `None` if it is declared at the top level.
+    pub enclosing: Option<u32>,
+    pub arity: u32,
+    /// The name of the variable stored in each slot of a call's frame.
+    pub slot_names: Vec<String>,
+    pub code: Vec<Op>,
+    /// The node each instruction was compiled from, at which its runtime errors are reported.
+    pub spans: Vec<MxirNodeRef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct StructShape {
+    pub name: String,
+    pub fields: Vec<String>,
+}
+
+/// A compiled program. The top-level code is the function at [`TOP_LEVEL`].
+#[derive(Debug, Clone, Default)]
+pub struct Program {
+    pub functions: Vec<Function>,
+    pub constants: Vec<InterpreterValue>,
+    /// The field names referred to by instructions.
+    pub names: Vec<String>,
+    pub shapes: Vec<StructShape>,
+}
+
+/// Compiles the MXIR of a source file. Only the functions reachable from the top-level code are
+/// compiled.
+pub fn compile(file: &AnalyzedSourceFile) -> Program {
+    Compiler::new(file).compile()
+}
+
+struct Scope {
+    function: u32,
+    names: HashMap<String, u32>,
+}
+
+struct Loop {
+    // The instruction `continue` jumps to
+    start: u32,
+    // The jumps to the end of the loop, patched once it is known
+    exits: Vec<usize>,
+    height: usize,
+}
+
+#[derive(Default)]
+struct FnState {
+    index: u32,
+    // The scopes whose variables are visible, outermost first
+    scopes: Vec<usize>,
+    loops: Vec<Loop>,
+    // The number of stack entries the code emitted so far leaves in the current frame
+    height: usize,
+}
+
+struct Compiler<'a> {
+    file: &'a AnalyzedSourceFile,
+    program: Program,
+    scopes: Vec<Scope>,
+    fn_indices: HashMap<MxirNodeRef, u32>,
+    queue: VecDeque<MxirNodeRef>,
+    // The scopes visible at the declaration of each function, keyed by the declaration
+    declaring_scopes: HashMap<AstNodeRef, Vec<usize>>,
+    state: FnState,
+}
+
+impl<'a> Compiler<'a> {
+    fn new(file: &'a AnalyzedSourceFile) -> Self {
+        Self {
+            file,
+            program: Program::default(),
+            scopes: vec![],
+            fn_indices: HashMap::new(),
+            queue: VecDeque::new(),
+            declaring_scopes: HashMap::new(),
+            state: FnState::default(),
+        }
+    }
+
+    fn compile(mut self) -> Program {
+        self.program.functions.push(Function {
+            name: "<top level>".to_string(),
+            enclosing: None,
+            arity: 0,
+            slot_names: vec![],
+            code: vec![],
+            spans: vec![],
+        });
+        self.state = FnState {
+            index: TOP_LEVEL,
+            scopes: vec![self.new_scope(TOP_LEVEL)],
+            ..FnState::default()
+        };
+
+        // The source file declares the globals and ends with the call to the entry point
+        let source_file_ref = MxirNodeRef(0);
+        match self.node(source_file_ref).map(|node| node.data) {
+            Some(MxirNodeData::SourceFile(source_file)) => {
+                self.compile_body(&source_file.0);
+            }
+            Some(_) => self.fail(source_file_ref, RuntimeErrorKind::MissingBody),
+            None => self.fail(
+                source_file_ref,
+                RuntimeErrorKind::MissingNode(source_file_ref),
+            ),
+        }
+        self.emit(Op::Return, source_file_ref);
+
+        while let Some(fn_decl_ref) = self.queue.pop_front() {
+            self.compile_fn(fn_decl_ref);
+        }
+
+        self.program
+    }
+
+    fn compile_fn(&mut self, fn_decl_ref: MxirNodeRef) {
+        let Some(MxirNode {
+            ast_node,
+            data: MxirNodeData::FnDecl(fn_decl),
+            ..
+        }) = self.node(fn_decl_ref)
+        else {
+            return;
+        };
+        let index = self.fn_indices[&fn_decl_ref];
+
+        // The body sees the scopes the function was declared in, not those of the caller
+        let mut scopes = self
+            .declaring_scopes
+            .get(&ast_node)
+            .cloned()
+            .unwrap_or_else(|| vec![0]);
+        scopes.push(self.new_scope(index));
+        self.state = FnState {
+            index,
+            scopes,
+            ..FnState::default()
+        };
+
+        for param in &fn_decl.params {
+            self.declare(&param.name);
+        }
+        self.compile_node(fn_decl.body);
+        self.emit(Op::Return, fn_decl_ref);
+    }
+
+    /// Compiles a node into code that leaves its value on the stack.
+    fn compile_node(&mut self, node_ref: MxirNodeRef) {
+        let Some(node) = self.node(node_ref) else {
+            return self.fail(node_ref, RuntimeErrorKind::MissingNode(node_ref));
+        };
+
+        match node.data {
+            MxirNodeData::SourceFile(block) => self.compile_body(&block.0),
+            MxirNodeData::Nop(msg) => {
+                if msg == "fn_decl" {
+                    self.declaring_scopes
+                        .insert(node.ast_node, self.state.scopes.clone());
+                }
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::IntLiteral(int_literal) => {
+                self.constant(InterpreterValue::Integer(int_literal.value), node_ref)
+            }
+            MxirNodeData::FloatLiteral(float_literal) => {
+                self.constant(InterpreterValue::Float(float_literal.value), node_ref)
+            }
+            MxirNodeData::BoolLiteral(bool_literal) => {
+                self.constant(InterpreterValue::Boolean(bool_literal.value), node_ref)
+            }
+            MxirNodeData::StringLiteral(string_literal) => {
+                self.constant(InterpreterValue::String(string_literal.value), node_ref)
+            }
+            MxirNodeData::VarDecl(var_decl) => {
+                match var_decl.value {
+                    Some(value_ref) => self.compile_node(value_ref),
+                    None => {
+                        self.emit(Op::Void, node_ref);
+                    }
+                }
+                let slot = self.declare(&var_decl.name);
+                self.emit(Op::Declare(Place::Local(slot)), node_ref);
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::VarExpr(var_expr) => match self.resolve(&var_expr.name) {
+                Some(place) => {
+                    self.emit(Op::Load(place), node_ref);
+                }
+                None => self.fail(node_ref, RuntimeErrorKind::UnboundVariable(var_expr.name)),
+            },
+            MxirNodeData::Assign(lhs, rhs) => {
+                self.compile_value(rhs);
+                self.compile_assign(lhs);
+            }
+            MxirNodeData::ExprStmt(expr_ref) => {
+                self.compile_node(expr_ref);
+                self.emit(Op::Pop, node_ref);
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::Return(ret) => {
+                match ret.0 {
+                    Some(expr_ref) => self.compile_node(expr_ref),
+                    None => {
+                        self.emit(Op::Void, node_ref);
+                    }
+                }
+                self.emit(Op::Return, node_ref);
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::Break => self.compile_loop_control(node_ref, true),
+            MxirNodeData::Continue => self.compile_loop_control(node_ref, false),
+            MxirNodeData::Loop(loop_stmt) => {
+                if let Some(body_ref) = loop_stmt.0 {
+                    let start = self.label();
+                    self.state.loops.push(Loop {
+                        start,
+                        exits: vec![],
+                        height: self.state.height,
+                    });
+                    self.compile_node(body_ref);
+                    self.emit(Op::Pop, node_ref);
+                    self.emit(Op::Jump(start), node_ref);
+                    self.end_loop();
+                }
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::Iterate(iterate) => {
+                self.compile_node(iterate.iterable);
+                self.emit(Op::IterStart, iterate.iterable);
+
+                // The binding lives in a scope that encloses the body
+                self.push_scope();
+                let slot = self.declare(&iterate.binding);
+                let start = self.label();
+                let next = self.emit(Op::IterNext { slot, exit: 0 }, node_ref);
+                self.state.loops.push(Loop {
+                    start,
+                    exits: vec![next],
+                    height: self.state.height,
+                });
+                self.compile_node(iterate.body);
+                self.emit(Op::Pop, node_ref);
+                self.emit(Op::Jump(start), node_ref);
+                self.end_loop();
+                self.emit(Op::IterEnd, node_ref);
+                self.pop_scope();
+
+                self.emit(Op::Void, node_ref);
+            }
+            MxirNodeData::If(if_stmt) => {
+                self.compile_node(if_stmt.condition);
+                let to_else = self.emit(Op::JumpIfFalse(0), node_ref);
+                self.compile_node(if_stmt.then_branch);
+                let to_end = self.emit(Op::Jump(0), node_ref);
+
+                // Only one of the branches leaves a value
+                self.state.height -= 1;
+                self.patch(to_else);
+                match if_stmt.else_branch {
+                    Some(else_branch) => self.compile_node(else_branch),
+                    None => {
+                        self.emit(Op::Void, node_ref);
+                    }
+                }
+                self.patch(to_end);
+            }
+            MxirNodeData::Block(block) => {
+                self.push_scope();
+                self.compile_body(&block.0);
+                self.pop_scope();
+            }
+            MxirNodeData::CallExpr(call_expr) => {
+                for &arg_ref in &call_expr.args {
+                    self.compile_value(arg_ref);
+                }
+                let argc = call_expr.args.len() as u32;
+
+                match self.node(call_expr.fn_decl_ref).map(|node| node.data) {
+                    Some(MxirNodeData::FnDecl(_)) => {
+                        let function = self.function_index(call_expr.fn_decl_ref);
+                        self.emit(Op::Call { function, argc }, node_ref);
+                    }
+                    Some(MxirNodeData::BuiltinFnDecl(builtin_fn_decl)) => {
+                        let f = builtin_fn_decl.fn_.clone();
+                        self.emit(Op::CallBuiltin { f, argc }, node_ref);
+                    }
+                    found => {
+                        for _ in 0..argc {
+                            self.emit(Op::Pop, node_ref);
+                        }
+                        match found {
+                            Some(_) => self.fail(node_ref, RuntimeErrorKind::NotCallable),
+                            None => self.fail(
+                                call_expr.fn_decl_ref,
+                                RuntimeErrorKind::MissingNode(call_expr.fn_decl_ref),
+                            ),
+                        }
+                    }
+                }
+            }
+            MxirNodeData::Binary(binary) => {
+                self.compile_node(binary.lhs);
+                let short_circuit = matches!(binary.op, BinaryOp::And | BinaryOp::Or)
+                    .then(|| self.emit(Op::ShortCircuit(binary.op, 0), node_ref));
+                self.compile_node(binary.rhs);
+                self.emit(Op::Binary(binary.op), node_ref);
+                if let Some(short_circuit) = short_circuit {
+                    self.patch(short_circuit);
+                }
+            }
+            MxirNodeData::Unary(unary) => {
+                self.compile_node(unary.operand);
+                self.emit(Op::Unary(unary.op), node_ref);
+            }
+            MxirNodeData::StructLiteral(struct_literal) => {
+                for field in &struct_literal.fields {
+                    self.compile_value(field.value);
+                }
+                let fields: Vec<_> = struct_literal
+                    .fields
+                    .into_iter()
+                    .map(|field| field.name)
+                    .collect();
+                let len = fields.len() as u32;
+                self.program.shapes.push(StructShape {
+                    name: struct_literal.name,
+                    fields,
+                });
+                let shape = self.program.shapes.len() as u32 - 1;
+                self.emit(Op::Struct { shape, len }, node_ref);
+            }
+            MxirNodeData::FieldAccess(field_access) => {
+                self.compile_node(field_access.object);
+                let name = self.name(field_access.field);
+                let object = field_access.object;
+                self.emit(Op::Field { name, object }, node_ref);
+            }
+            MxirNodeData::ListLiteral(list_literal) => {
+                for &element_ref in &list_literal.0 {
+                    self.compile_value(element_ref);
+                }
+                self.emit(Op::List(list_literal.0.len() as u32), node_ref);
+            }
+            MxirNodeData::Index(index) => {
+                self.compile_node(index.object);
+                self.compile_node(index.index);
+                let (object, index) = (index.object, index.index);
+                self.emit(Op::Index { object, index }, node_ref);
+            }
+            MxirNodeData::MapLiteral(map_literal) => {
+                for entry in &map_literal.0 {
+                    self.compile_node(entry.key);
+                    self.emit(Op::CheckKey, entry.key);
+                    self.compile_value(entry.value);
+                }
+                self.emit(Op::Map(map_literal.0.len() as u32), node_ref);
+            }
+            MxirNodeData::KeyAccess(key_access) => {
+                self.compile_node(key_access.map);
+                self.compile_node(key_access.key);
+                self.emit(Op::CheckKey, key_access.key);
+                let map = key_access.map;
+                self.emit(Op::KeyAccess { map }, node_ref);
+            }
+            MxirNodeData::Range(range) => {
+                for bound_ref in [range.start, range.end].into_iter().flatten() {
+                    self.compile_node(bound_ref);
+                    self.emit(Op::CheckRangeBound, bound_ref);
+                }
+                let (start, end) = (range.start.is_some(), range.end.is_some());
+                self.emit(Op::Range { start, end }, node_ref);
+            }
+            MxirNodeData::Stringify(value_ref) => {
+                self.compile_node(value_ref);
+                self.emit(Op::Stringify, value_ref);
+            }
+            _ => self.fail(node_ref, RuntimeErrorKind::UnsupportedNode),
+        }
+    }
+
+    /// Compiles an expression whose value is required, such as an argument.
+    fn compile_value(&mut self, node_ref: MxirNodeRef) {
+        self.compile_node(node_ref);
+        if self.may_be_empty(node_ref) {
+            self.emit(Op::Require, node_ref);
+        }
+    }
+
+    /// Compiles the store of the value on top of the stack into the place denoted by `target_ref`,
+    /// leaving the value on the stack. Like the interpreter, a field, element or key is set on its
+    /// container in place.
+    fn compile_assign(&mut self, target_ref: MxirNodeRef) {
+        let Some(target) = self.node(target_ref) else {
+            return self.fail_in_place(target_ref, RuntimeErrorKind::MissingNode(target_ref));
+        };
+
+        match target.data {
+            MxirNodeData::VarExpr(var_expr) => match self.resolve(&var_expr.name) {
+                Some(place) => {
+                    self.emit(Op::Store(place), target_ref);
+                }
+                None => {
+                    self.fail_in_place(target_ref, RuntimeErrorKind::UnboundVariable(var_expr.name))
+                }
+            },
+            MxirNodeData::FieldAccess(field_access) => {
+                self.compile_node(field_access.object);
+                let name = self.name(field_access.field);
+                let object = field_access.object;
+                self.emit(Op::SetField { name, object }, target_ref);
+            }
+            MxirNodeData::Index(index) => {
+                self.compile_node(index.object);
+                self.compile_node(index.index);
+                let (object, index) = (index.object, index.index);
+                self.emit(Op::SetIndex { object, index }, target_ref);
+            }
+            MxirNodeData::KeyAccess(key_access) => {
+                self.compile_node(key_access.map);
+                self.compile_node(key_access.key);
+                self.emit(Op::CheckKey, key_access.key);
+                let map = key_access.map;
+                self.emit(Op::SetKey { map }, target_ref);
+            }
+            _ => self.fail_in_place(target_ref, RuntimeErrorKind::InvalidAssignmentTarget),
+        }
+    }
+
+    /// Compiles the statements of a block, whose value is that of its last statement.
+    fn compile_body(&mut self, stmts: &[MxirNodeRef]) {
+        let Some((last, init)) = stmts.split_last() else {
+            self.emit(Op::Void, MxirNodeRef(0));
+            return;
+        };
+        for &stmt_ref in init {
+            self.compile_node(stmt_ref);
+            self.emit(Op::Pop, stmt_ref);
+        }
+        self.compile_node(*last);
+    }
+
+    fn compile_loop_control(&mut self, node_ref: MxirNodeRef, is_break: bool) {
+        let height = self.state.height;
+        match self.state.loops.last() {
+            Some(innermost) => {
+                // Drop whatever the enclosing expressions left on the stack since the loop began
+                let (start, loop_height) = (innermost.start, innermost.height);
+                for _ in loop_height..height {
+                    self.emit(Op::Pop, node_ref);
+                }
+                if is_break {
+                    let exit = self.emit(Op::Jump(0), node_ref);
+                    if let Some(innermost) = self.state.loops.last_mut() {
+                        innermost.exits.push(exit);
+                    }
+                } else {
+                    self.emit(Op::Jump(start), node_ref);
+                }
+                self.state.height = height;
+            }
+            None => {
+                self.emit(Op::EscapeLoop, node_ref);
+            }
+        }
+        self.emit(Op::Void, node_ref);
+    }
+
+    fn end_loop(&mut self) {
+        if let Some(innermost) = self.state.loops.pop() {
+            for exit in innermost.exits {
+                self.patch(exit);
+            }
+        }
+    }
+
+    /// Emits code that fails with a runtime error, and stands in for a value.
+    fn fail(&mut self, node_ref: MxirNodeRef, kind: RuntimeErrorKind) {
+        self.fail_in_place(node_ref, kind);
+        self.emit(Op::Void, node_ref);
+    }
+
+    fn fail_in_place(&mut self, node_ref: MxirNodeRef, kind: RuntimeErrorKind) {
+        self.emit(Op::Fail(Box::new(kind)), node_ref);
+    }
+
+    fn constant(&mut self, value: InterpreterValue, node_ref: MxirNodeRef) {
+        self.program.constants.push(value);
+        let index = self.program.constants.len() as u32 - 1;
+        self.emit(Op::Constant(index), node_ref);
+    }
+
+    fn name(&mut self, name: String) -> u32 {
+        let index = match self.program.names.iter().position(|known| *known == name) {
+            Some(index) => index,
+            None => {
+                self.program.names.push(name);
+                self.program.names.len() - 1
+            }
+        };
+        index as u32
+    }
+
+    fn emit(&mut self, op: Op, span: MxirNodeRef) -> usize {
+        self.state.height = self.state.height.wrapping_add_signed(op.stack_effect());
+        let function = &mut self.program.functions[self.state.index as usize];
+        function.code.push(op);
+        function.spans.push(span);
+        function.code.len() - 1
+    }
+
+    fn label(&self) -> u32 {
+        self.program.functions[self.state.index as usize].code.len() as u32
+    }
+
+    /// Points the jump at `at` to the next instruction.
+    fn patch(&mut self, at: usize) {
+        let label = self.label();
+        match &mut self.program.functions[self.state.index as usize].code[at] {
+            Op::Jump(target) | Op::JumpIfFalse(target) | Op::ShortCircuit(_, target) => {
+                *target = label
+            }
+            Op::IterNext { exit, .. } => *exit = label,
+            _ => {}
+        }
+    }
+
+    /// Returns the index of the function declared by `fn_decl_ref`, queueing it for compilation
+    /// the first time it is called.
+    fn function_index(&mut self, fn_decl_ref: MxirNodeRef) -> u32 {
+        if let Some(index) = self.fn_indices.get(&fn_decl_ref) {
+            return *index;
+        }
+        let Some(MxirNodeData::FnDecl(fn_decl)) = self.node(fn_decl_ref).map(|node| node.data)
+        else {
+            return TOP_LEVEL;
+        };
+
+        let index = self.program.functions.len() as u32;
+        self.fn_indices.insert(fn_decl_ref, index);
+        self.program.functions.push(Function {
+            name: fn_decl.name,
+            enclosing: None,
+            arity: fn_decl.params.len() as u32,
+            slot_names: vec![],
+            code: vec![],
+            spans: vec![],
+        });
+        let enclosing = fn_decl
+            .enclosing
+            .map(|enclosing| self.function_index(enclosing));
+        self.program.functions[index as usize].enclosing = enclosing;
+        self.queue.push_back(fn_decl_ref);
+
+        index
+    }
+
+    fn new_scope(&mut self, function: u32) -> usize {
+        self.scopes.push(Scope {
+            function,
+            names: HashMap::new(),
+        });
+        self.scopes.len() - 1
+    }
+
+    fn push_scope(&mut self) {
+        let scope = self.new_scope(self.state.index);
+        self.state.scopes.push(scope);
+    }
+
+    fn pop_scope(&mut self) {
+        self.state.scopes.pop();
+    }
+
+    /// Allocates a slot for a variable of the innermost scope.
+    fn declare(&mut self, name: &str) -> u32 {
+        let function = &mut self.program.functions[self.state.index as usize];
+        function.slot_names.push(name.to_string());
+        let slot = function.slot_names.len() as u32 - 1;
+        if let Some(scope) = self
+            .state
+            .scopes
+            .last()
+            .and_then(|scope| self.scopes.get_mut(*scope))
+        {
+            scope.names.insert(name.to_string(), slot);
+        }
+        slot
+    }
+
+    fn resolve(&self, name: &str) -> Option<Place> {
+        let scope = self
+            .state
+            .scopes
+            .iter()
+            .rev()
+            .map(|scope| &self.scopes[*scope])
+            .find(|scope| scope.names.contains_key(name))?;
+        let slot = scope.names[name];
+
+        if scope.function == self.state.index {
+            return Some(Place::Local(slot));
+        }
+        if scope.function == TOP_LEVEL {
+            return Some(Place::Global(slot));
+        }
+
+        // Count the frames between the current function and the one the variable belongs to
+        let mut function = self.state.index;
+        let mut depth = 0;
+        while function != scope.function {
+            function = self.program.functions[function as usize].enclosing?;
+            depth += 1;
+        }
+        Some(Place::Outer { depth, slot })
+    }
+
+    /// Returns false if the node always produces a value when it completes.
+    fn may_be_empty(&self, node_ref: MxirNodeRef) -> bool {
+        !matches!(
+            self.node(node_ref).map(|node| node.data),
+            Some(
+                MxirNodeData::IntLiteral(_)
+                    | MxirNodeData::FloatLiteral(_)
+                    | MxirNodeData::BoolLiteral(_)
+                    | MxirNodeData::StringLiteral(_)
+                    | MxirNodeData::VarExpr(_)
+                    | MxirNodeData::Assign(..)
+                    | MxirNodeData::Binary(_)
+                    | MxirNodeData::Unary(_)
+                    | MxirNodeData::StructLiteral(_)
+                    | MxirNodeData::FieldAccess(_)
+                    | MxirNodeData::ListLiteral(_)
+                    | MxirNodeData::Index(_)
+                    | MxirNodeData::MapLiteral(_)
+                    | MxirNodeData::KeyAccess(_)
+                    | MxirNodeData::Range(_)
+                    | MxirNodeData::Stringify(_)
+            )
+        )
+    }
+
+    fn node(&self, node_ref: MxirNodeRef) -> Option<MxirNode> {
+        self.file.mxir().0.get(node_ref.0 as usize).cloned()
+    }
+}
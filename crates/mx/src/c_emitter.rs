@@ -2,7 +2,10 @@ use std::fmt::Write;
 
 use crate::{
     diag::Diagnostic,
-    mxir::{MxirBlock, MxirFnDecl, MxirNode, MxirNodeData, MxirNodeRef, MxirVarDecl},
+    mxir::{
+        BinaryOp, MxirBinary, MxirBlock, MxirFnDecl, MxirNode, MxirNodeData, MxirNodeRef,
+        MxirUnary, MxirVarDecl, UnaryOp,
+    },
     source_file::AnalyzedSourceFile,
 };
 
@@ -64,12 +67,21 @@ impl<'a> CEmitter<'a> {
             MxirNodeData::IntLiteral(ref int_literal) => {
                 self.emit_inline(format!("{}", int_literal.value));
             }
+            MxirNodeData::FloatLiteral(ref float_literal) => {
+                self.emit_inline(format!("{:?}", float_literal.value));
+            }
             MxirNodeData::StringLiteral(ref string_literal) => {
-                self.emit_inline(string_literal.value.to_string());
+                self.emit_inline(c_string_literal(&string_literal.value));
             }
             MxirNodeData::VarExpr(ref var_expr) => {
                 self.emit_inline(var_expr.name.to_string());
             }
+            MxirNodeData::Binary(ref binary) => {
+                self.emit_binary(binary);
+            }
+            MxirNodeData::Unary(ref unary) => {
+                self.emit_unary(unary);
+            }
             MxirNodeData::Return(ref ret) => {
                 self.emit_line("return ".to_string());
                 if let Some(ret_ref) = ret.0 {
@@ -98,16 +110,39 @@ impl<'a> CEmitter<'a> {
 
     fn emit_fn_decl(&mut self, fn_decl: &MxirFnDecl) {
         self.emit_line(format!("void {}(", fn_decl.name));
-        // for (i, param) in fn_decl.params.iter().enumerate() {
-        //     if i > 0 {
-        //         self.emit_code(format!(", "));
-        //     }
-        //     self.emit_code(format!("{}", param.name));
-        // }
+        for (i, param) in fn_decl.params.iter().enumerate() {
+            if i > 0 {
+                self.emit_inline(", ".to_string());
+            }
+            self.emit_inline(format!("int {}", param.name));
+        }
         self.emit_inline(") ".to_string());
         self.emit_node(fn_decl.body);
     }
 
+    fn emit_binary(&mut self, binary: &MxirBinary) {
+        let op = match binary.op {
+            BinaryOp::And => "&&",
+            BinaryOp::Or => "||",
+            op => op.token(),
+        };
+        self.emit_inline("(".to_string());
+        self.emit_node(binary.lhs);
+        self.emit_inline(format!(" {} ", op));
+        self.emit_node(binary.rhs);
+        self.emit_inline(")".to_string());
+    }
+
+    fn emit_unary(&mut self, unary: &MxirUnary) {
+        let op = match unary.op {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "!",
+        };
+        self.emit_inline(format!("({}", op));
+        self.emit_node(unary.operand);
+        self.emit_inline(")".to_string());
+    }
+
     fn emit_block(&mut self, block: &MxirBlock) {
         self.emit_line("{\n".to_string());
         self.indent();
@@ -145,3 +180,21 @@ impl<'a> CEmitter<'a> {
         self.level -= 1;
     }
 }
+
+/// Quotes a string as a C string literal, escaping anything that is not printable ASCII.
+fn c_string_literal(value: &str) -> String {
+    let mut literal = String::from("\"");
+    for byte in value.bytes() {
+        match byte {
+            b'"' => literal.push_str("\\\""),
+            b'\\' => literal.push_str("\\\\"),
+            b'\n' => literal.push_str("\\n"),
+            b'\t' => literal.push_str("\\t"),
+            b'\r' => literal.push_str("\\r"),
+            b' '..=b'~' => literal.push(byte as char),
+            _ => literal.push_str(&format!("\\{:03o}", byte)),
+        }
+    }
+    literal.push('"');
+    literal
+}
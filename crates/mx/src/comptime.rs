@@ -1,4 +1,12 @@
-use crate::{ast::AstNodeRef, position::Range, symbol_table::SymbolTableSet};
+use crate::{
+    ast::AstNodeRef,
+    builtins::BuiltinFn,
+    interpreter::{self, InterpreterValue, RuntimeErrorKind},
+    mxir::{BinaryOp, UnaryOp},
+    position::Range,
+    symbol_table::{SymbolTableRef, SymbolTableSet},
+    types::{FnType, StructType, Type},
+};
 
 #[derive(Debug, Clone)]
 pub enum ComptimeValue {
@@ -11,13 +19,140 @@ pub enum ComptimeValue {
     ComptimeFloat(f64),
     ComptimeString(String),
     ComptimeBool(bool),
-    Type,
+    ComptimeRange(Option<i128>, Option<i128>),
+    Map(Vec<(ComptimeValue, ComptimeValue)>),
+    // A struct instance, with the values of its fields in declaration order
+    Struct(Box<StructType>, Vec<ComptimeValue>),
+    Type(Type),
+}
+
+impl ComptimeValue {
+    /// Returns the runtime type denoted by this value when it is used in a type position.
+    pub fn as_type(&self) -> Option<Type> {
+        match self {
+            ComptimeValue::Type(ty) => Some(ty.clone()),
+            ComptimeValue::FnProto(proto) => proto.fn_type().map(|ty| Type::Fn(Box::new(ty))),
+            _ => None,
+        }
+    }
+
+    /// Returns the type of the value itself when it is used in an expression position.
+    pub fn value_type(&self) -> Option<Type> {
+        match self {
+            ComptimeValue::ComptimeInt(_) => Some(Type::Int32),
+            ComptimeValue::ComptimeFloat(_) => Some(Type::Float),
+            ComptimeValue::ComptimeString(_) => Some(Type::String),
+            ComptimeValue::ComptimeBool(_) => Some(Type::Bool),
+            ComptimeValue::ComptimeRange(..) => Some(Type::Range),
+            // The first entry determines the key and value types of the map
+            ComptimeValue::Map(entries) => {
+                let (key, value) = entries.first()?;
+                Some(Type::Map(
+                    Box::new(key.value_type()?),
+                    Box::new(value.value_type()?),
+                ))
+            }
+            ComptimeValue::Struct(struct_type, _) => Some(Type::Struct(struct_type.clone())),
+            ComptimeValue::VarDecl(var_decl) => var_decl.ty.as_ref().and_then(|ty| ty.as_type()),
+            ComptimeValue::FnDecl(fn_decl) => {
+                fn_decl.proto.fn_type().map(|ty| Type::Fn(Box::new(ty)))
+            }
+            _ => None,
+        }
+    }
+
+    /// Returns whether this is a value computed at compile time, such as an integer or a string,
+    /// rather than a declaration or a type.
+    pub fn is_constant(&self) -> bool {
+        matches!(
+            self,
+            ComptimeValue::ComptimeInt(_)
+                | ComptimeValue::ComptimeFloat(_)
+                | ComptimeValue::ComptimeString(_)
+                | ComptimeValue::ComptimeBool(_)
+                | ComptimeValue::ComptimeRange(..)
+                | ComptimeValue::Map(_)
+                | ComptimeValue::Struct(..)
+        )
+    }
+}
+
+/// The reasons an operator applied at compile time fails to produce a value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ComptimeError {
+    DivisionByZero,
+    IntegerOverflow,
+    InvalidOperands,
+}
+
+/// Applies a binary operator to two compile-time values. The operator is applied by the
+/// interpreter, so it has the same semantics as at runtime.
+pub fn apply_binary_op(
+    op: BinaryOp,
+    lhs: &ComptimeValue,
+    rhs: &ComptimeValue,
+) -> Result<ComptimeValue, ComptimeError> {
+    let (Some(lhs), Some(rhs)) = (lhs.to_runtime_value(), rhs.to_runtime_value()) else {
+        return Err(ComptimeError::InvalidOperands);
+    };
+    interpreter::apply_binary_op(op, lhs, rhs)
+        .map_err(ComptimeError::from)
+        .and_then(ComptimeValue::from_runtime_value)
+}
+
+/// Applies a unary operator to a compile-time value, with the same semantics as at runtime.
+pub fn apply_unary_op(
+    op: UnaryOp,
+    operand: &ComptimeValue,
+) -> Result<ComptimeValue, ComptimeError> {
+    let Some(operand) = operand.to_runtime_value() else {
+        return Err(ComptimeError::InvalidOperands);
+    };
+    interpreter::apply_unary_op(op, operand)
+        .map_err(ComptimeError::from)
+        .and_then(ComptimeValue::from_runtime_value)
+}
+
+impl From<RuntimeErrorKind> for ComptimeError {
+    fn from(kind: RuntimeErrorKind) -> Self {
+        match kind {
+            RuntimeErrorKind::DivisionByZero => ComptimeError::DivisionByZero,
+            RuntimeErrorKind::IntegerOverflow => ComptimeError::IntegerOverflow,
+            _ => ComptimeError::InvalidOperands,
+        }
+    }
+}
+
+impl ComptimeValue {
+    /// Converts a constant into the value it has at runtime. Other values have no runtime
+    /// counterpart.
+    fn to_runtime_value(&self) -> Option<InterpreterValue> {
+        match self {
+            ComptimeValue::ComptimeInt(value) => Some(InterpreterValue::Integer(*value)),
+            ComptimeValue::ComptimeFloat(value) => Some(InterpreterValue::Float(*value)),
+            ComptimeValue::ComptimeBool(value) => Some(InterpreterValue::Boolean(*value)),
+            ComptimeValue::ComptimeString(value) => Some(InterpreterValue::String(value.clone())),
+            _ => None,
+        }
+    }
+
+    fn from_runtime_value(value: InterpreterValue) -> Result<Self, ComptimeError> {
+        match value {
+            InterpreterValue::Integer(value) => Ok(ComptimeValue::ComptimeInt(value)),
+            InterpreterValue::Float(value) => Ok(ComptimeValue::ComptimeFloat(value)),
+            InterpreterValue::Boolean(value) => Ok(ComptimeValue::ComptimeBool(value)),
+            InterpreterValue::String(value) => Ok(ComptimeValue::ComptimeString(value)),
+            _ => Err(ComptimeError::InvalidOperands),
+        }
+    }
 }
 
 #[derive(Debug, Clone)]
 pub struct BuiltinFnDecl {
     pub proto: FnProto,
-    pub f: fn() -> (),
+    pub f: BuiltinFn,
+    // Whether the function accepts any number of arguments of any type, ignoring its parameters
+    pub variadic: bool,
 }
 
 #[derive(Debug, Clone)]
@@ -35,8 +170,25 @@ pub struct FnProto {
     pub return_type: ComptimeValue,
 }
 
+impl FnProto {
+    /// Returns the function type of this prototype, if all of its types are known.
+    pub fn fn_type(&self) -> Option<FnType> {
+        let params = self
+            .params
+            .iter()
+            .map(|param| param.ty.as_type())
+            .collect::<Option<Vec<_>>>()?;
+        let return_type = self.return_type.as_type()?;
+        Some(FnType {
+            params,
+            return_type,
+        })
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct ParamDecl {
+    pub node_ref: AstNodeRef,
     pub name: String,
     pub ty: ComptimeValue,
 }
@@ -80,6 +232,18 @@ impl ComptimeEnv {
         self.0.pop_table();
     }
 
+    /// Returns the stack of scopes that are currently open, from the outermost scope inwards.
+    pub fn scopes(&self) -> Vec<SymbolTableRef> {
+        self.0.stack.clone()
+    }
+
+    /// Replaces the stack of open scopes, returning the previous one so it can be restored. This
+    /// lets a function body be analyzed in the scope it was declared in rather than the scope of
+    /// its first caller.
+    pub fn replace_scopes(&mut self, scopes: Vec<SymbolTableRef>) -> Vec<SymbolTableRef> {
+        std::mem::replace(&mut self.0.stack, scopes)
+    }
+
     pub fn get(&self, name: &str) -> Option<&ComptimeBinding> {
         self.0.get(name)
     }
@@ -91,7 +255,8 @@ impl ComptimeEnv {
     pub fn declare_builtin_fn(
         &mut self,
         proto: FnProto,
-        f: fn() -> (),
+        f: BuiltinFn,
+        variadic: bool,
     ) -> Result<(), &'static str> {
         let Some(name) = proto.name.clone() else {
             return Err("Function name is missing");
@@ -106,7 +271,7 @@ impl ComptimeEnv {
             ComptimeBinding {
                 node_ref: AstNodeRef(0),
                 ty: None,
-                value: ComptimeValue::BuiltinFnDecl(Box::new(BuiltinFnDecl { proto, f })),
+                value: ComptimeValue::BuiltinFnDecl(Box::new(BuiltinFnDecl { proto, f, variadic })),
             },
         );
 
@@ -166,6 +331,27 @@ impl ComptimeEnv {
         Ok(())
     }
 
+    pub fn declare_struct(
+        &mut self,
+        node_ref: AstNodeRef,
+        struct_type: StructType,
+    ) -> Result<(), &'static str> {
+        if self.0.get(struct_type.name.as_str()).is_some() {
+            return Err("Duplicate declaration");
+        }
+
+        self.0.insert(
+            struct_type.name.clone().as_str(),
+            ComptimeBinding {
+                node_ref,
+                ty: None,
+                value: ComptimeValue::Type(Type::Struct(Box::new(struct_type))),
+            },
+        );
+
+        Ok(())
+    }
+
     pub fn declare_var(
         &mut self,
         node_ref: AstNodeRef,
@@ -199,6 +385,7 @@ mod tests {
     use super::*;
     use crate::ast::AstNodeRef;
     use crate::position::{Point, Range};
+    use crate::types::StructField;
 
     fn make_range(start: usize, end: usize) -> Range {
         Range {
@@ -242,7 +429,7 @@ mod tests {
 
         let fn_node_ref = AstNodeRef(1);
         let body_ref = AstNodeRef(2);
-        let return_type = ComptimeValue::Type;
+        let return_type = ComptimeValue::Type(Type::Int32);
 
         // Create a FnProto struct with the required fields
         let proto = FnProto {
@@ -262,7 +449,10 @@ mod tests {
             // Check that the body_ref is the same we passed
             assert!(matches!(fn_decl.body_ref, AstNodeRef(2)));
             // Check the return type is Type
-            assert!(matches!(fn_decl.proto.return_type, ComptimeValue::Type));
+            assert!(matches!(
+                fn_decl.proto.return_type,
+                ComptimeValue::Type(Type::Int32)
+            ));
         } else {
             panic!("Expected FnDecl");
         }
@@ -274,7 +464,7 @@ mod tests {
         env.push_scope(make_range(0, 100));
 
         let const_node_ref = AstNodeRef(3);
-        let const_type = Some(ComptimeValue::Type);
+        let const_type = Some(ComptimeValue::Type(Type::Int32));
         let const_value = ComptimeValue::ComptimeInt(42);
 
         let _ = env.declare_const(const_node_ref, "MY_CONST", const_type, const_value);
@@ -284,7 +474,7 @@ mod tests {
 
         // Check the type is Type
         if let Some(ty) = &binding.ty {
-            assert!(matches!(ty, ComptimeValue::Type));
+            assert!(matches!(ty, ComptimeValue::Type(Type::Int32)));
         } else {
             panic!("Expected Some type");
         }
@@ -304,7 +494,7 @@ mod tests {
 
     //     let var_node_ref = AstNodeRef(4);
     //     let var_value_ref = Some(AstNodeRef(5));
-    //     let var_type = Some(ComptimeValue::Type);
+    //     let var_type = Some(ComptimeValue::Type(Type::Int32));
 
     //     env.declare_var(var_node_ref, "my_var", var_type, var_value_ref);
 
@@ -313,7 +503,7 @@ mod tests {
 
     //     // Check the type
     //     if let Some(ty) = &binding.ty {
-    //         assert!(matches!(ty, ComptimeValue::Type));
+    //         assert!(matches!(ty, ComptimeValue::Type(Type::Int32)));
     //     } else {
     //         panic!("Expected Some type");
     //     }
@@ -324,7 +514,7 @@ mod tests {
     //         assert!(matches!(var_decl.value_ref, Some(AstNodeRef(5))));
 
     //         if let Some(ty) = &var_decl.ty {
-    //             assert!(matches!(ty, ComptimeValue::Type));
+    //             assert!(matches!(ty, ComptimeValue::Type(Type::Int32)));
     //         } else {
     //             panic!("Expected Some type in VarDecl");
     //         }
@@ -414,7 +604,7 @@ mod tests {
             name: Some("test_fn".to_string()),
             comptime_params: vec![],
             params: vec![],
-            return_type: ComptimeValue::Type,
+            return_type: ComptimeValue::Type(Type::Int32),
         };
 
         let fn_decl = ComptimeValue::FnDecl(Box::new(FnDecl {
@@ -428,7 +618,7 @@ mod tests {
         let float_val = ComptimeValue::ComptimeFloat(42.0);
         let string_val = ComptimeValue::ComptimeString("hello".to_string());
         let bool_val = ComptimeValue::ComptimeBool(true);
-        let type_val = ComptimeValue::Type;
+        let type_val = ComptimeValue::Type(Type::Int32);
 
         // Use pattern matching to check variant types
         assert!(matches!(fn_decl, ComptimeValue::FnDecl(_)));
@@ -436,7 +626,7 @@ mod tests {
         assert!(matches!(float_val, ComptimeValue::ComptimeFloat(_)));
         assert!(matches!(string_val, ComptimeValue::ComptimeString(_)));
         assert!(matches!(bool_val, ComptimeValue::ComptimeBool(true)));
-        assert!(matches!(type_val, ComptimeValue::Type));
+        assert!(matches!(type_val, ComptimeValue::Type(Type::Int32)));
 
         // For float, need more specific check
         if let ComptimeValue::ComptimeFloat(val) = float_val {
@@ -449,6 +639,115 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_declare_struct() {
+        let mut env = ComptimeEnv::new();
+        env.push_scope(make_range(0, 100));
+
+        let struct_type = StructType {
+            name: "Person".to_string(),
+            fields: vec![StructField {
+                name: "name".to_string(),
+                ty: Type::String,
+            }],
+        };
+
+        env.declare_struct(AstNodeRef(1), struct_type.clone())
+            .unwrap();
+        assert!(env.declare_struct(AstNodeRef(2), struct_type).is_err());
+
+        let binding = env.get("Person").unwrap();
+        let Some(Type::Struct(struct_type)) = binding.value.as_type() else {
+            panic!("Expected struct type");
+        };
+        assert_eq!(
+            struct_type.field("name").map(|f| &f.ty),
+            Some(&Type::String)
+        );
+    }
+
+    #[test]
+    fn test_replace_scopes() {
+        let mut env = ComptimeEnv::new();
+        env.push_scope(make_range(0, 100));
+        env.declare_const(AstNodeRef(1), "GLOBAL", None, ComptimeValue::ComptimeInt(1))
+            .unwrap();
+        let declaring_scopes = env.scopes();
+
+        env.push_scope(make_range(10, 20));
+        env.declare_const(AstNodeRef(2), "LOCAL", None, ComptimeValue::ComptimeInt(2))
+            .unwrap();
+
+        // Entering the declaring scopes hides the bindings of the current ones
+        let current_scopes = env.replace_scopes(declaring_scopes);
+        env.push_scope(make_range(30, 40));
+        assert!(env.lookup("GLOBAL").is_some());
+        assert!(env.lookup("LOCAL").is_none());
+        env.pop_scope();
+
+        env.replace_scopes(current_scopes);
+        assert!(env.lookup("LOCAL").is_some());
+    }
+
+    #[test]
+    fn test_map_value_type() {
+        let map = ComptimeValue::Map(vec![(
+            ComptimeValue::ComptimeString("retries".to_string()),
+            ComptimeValue::ComptimeInt(3),
+        )]);
+        assert_eq!(
+            map.value_type(),
+            Some(Type::Map(Box::new(Type::String), Box::new(Type::Int32)))
+        );
+
+        // The types of an empty map cannot be inferred
+        assert_eq!(ComptimeValue::Map(vec![]).value_type(), None);
+    }
+
+    #[test]
+    fn test_struct_value_type() {
+        let struct_type = StructType {
+            name: "Point".to_string(),
+            fields: vec![],
+        };
+        let instance = ComptimeValue::Struct(Box::new(struct_type.clone()), vec![]);
+        assert!(instance.is_constant());
+        assert_eq!(
+            instance.value_type(),
+            Some(Type::Struct(Box::new(struct_type)))
+        );
+    }
+
+    #[test]
+    fn test_fn_proto_type() {
+        let proto = FnProto {
+            name: Some("test_fn".to_string()),
+            comptime_params: vec![],
+            params: vec![ParamDecl {
+                node_ref: AstNodeRef(1),
+                name: "x".to_string(),
+                ty: ComptimeValue::Type(Type::Int32),
+            }],
+            return_type: ComptimeValue::Type(Type::Bool),
+        };
+
+        let ty = ComptimeValue::FnProto(Box::new(proto.clone())).as_type();
+        assert_eq!(
+            ty,
+            Some(Type::Fn(Box::new(FnType {
+                params: vec![Type::Int32],
+                return_type: Type::Bool,
+            })))
+        );
+
+        // Prototypes with non-type annotations have no function type
+        let untyped = FnProto {
+            return_type: ComptimeValue::ComptimeInt(0),
+            ..proto
+        };
+        assert!(untyped.fn_type().is_none());
+    }
+
     #[test]
     fn test_no_cyclic_scopes() {
         let mut env = ComptimeEnv::new();
@@ -508,4 +807,72 @@ mod tests {
             panic!("Expected ComptimeInt");
         }
     }
+
+    #[test]
+    fn test_apply_binary_op() {
+        use ComptimeValue::{ComptimeBool, ComptimeFloat, ComptimeInt, ComptimeString};
+
+        let eval = |op, lhs, rhs| apply_binary_op(op, &lhs, &rhs);
+        assert!(matches!(
+            eval(BinaryOp::Mul, ComptimeInt(6), ComptimeInt(7)),
+            Ok(ComptimeInt(42))
+        ));
+        assert!(matches!(
+            eval(BinaryOp::Div, ComptimeInt(1), ComptimeFloat(2.0)),
+            Ok(ComptimeFloat(value)) if value == 0.5
+        ));
+        assert!(matches!(
+            eval(BinaryOp::Lte, ComptimeInt(3), ComptimeInt(3)),
+            Ok(ComptimeBool(true))
+        ));
+        assert!(matches!(
+            eval(BinaryOp::Or, ComptimeBool(false), ComptimeBool(true)),
+            Ok(ComptimeBool(true))
+        ));
+        assert!(matches!(
+            eval(
+                BinaryOp::Add,
+                ComptimeString("mx".to_string()),
+                ComptimeString("!".to_string())
+            ),
+            Ok(ComptimeString(value)) if value == "mx!"
+        ));
+
+        assert_eq!(
+            eval(BinaryOp::Div, ComptimeInt(1), ComptimeInt(0)).unwrap_err(),
+            ComptimeError::DivisionByZero
+        );
+        assert_eq!(
+            eval(BinaryOp::Add, ComptimeInt(i32::MAX.into()), ComptimeInt(1)).unwrap_err(),
+            ComptimeError::IntegerOverflow
+        );
+        assert_eq!(
+            eval(BinaryOp::Shl, ComptimeInt(1), ComptimeInt(-1)).unwrap_err(),
+            ComptimeError::IntegerOverflow
+        );
+        assert_eq!(
+            eval(BinaryOp::And, ComptimeInt(1), ComptimeBool(true)).unwrap_err(),
+            ComptimeError::InvalidOperands
+        );
+    }
+
+    #[test]
+    fn test_apply_unary_op() {
+        assert!(matches!(
+            apply_unary_op(UnaryOp::Neg, &ComptimeValue::ComptimeInt(5)),
+            Ok(ComptimeValue::ComptimeInt(-5))
+        ));
+        assert!(matches!(
+            apply_unary_op(UnaryOp::Not, &ComptimeValue::ComptimeBool(false)),
+            Ok(ComptimeValue::ComptimeBool(true))
+        ));
+        assert_eq!(
+            apply_unary_op(UnaryOp::Neg, &ComptimeValue::ComptimeInt(i32::MIN.into())).unwrap_err(),
+            ComptimeError::IntegerOverflow
+        );
+        assert_eq!(
+            apply_unary_op(UnaryOp::Not, &ComptimeValue::ComptimeInt(1)).unwrap_err(),
+            ComptimeError::InvalidOperands
+        );
+    }
 }
@@ -0,0 +1,465 @@
+use std::collections::{BTreeSet, HashMap};
+
+use crate::interpreter::InterpreterValue;
+use crate::mxir::{MxirNodeData, MxirNodeRef};
+use crate::source_file::AnalyzedSourceFile;
+
+/// How a paused program continues.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Resume {
+    /// Runs until the next breakpoint.
+    Continue,
+    /// Pauses at the next statement, entering the functions it calls.
+    StepIn,
+    /// Pauses at the next statement of the current function, or of its caller once it returns.
+    StepOver,
+    /// Pauses once the current function returns to its caller.
+    StepOut,
+    /// Ends the program with a runtime error.
+    Stop,
+}
+
+/// Why a program paused.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PauseReason {
+    /// The program is about to run its first statement.
+    Entry,
+    Breakpoint,
+    Step,
+    /// The frontend asked for the program to pause.
+    Request,
+}
+
+/// A function call, or the top level of the program, as it was when the program paused.
+#[derive(Debug, Clone, PartialEq)]
+pub struct StackFrame {
+    pub name: String,
+    /// The line of the statement the frame is executing, starting at 1.
+    pub line: usize,
+    /// The variables of the frame by name. Variables are `None` until they are assigned.
+    pub locals: Vec<(String, Option<InterpreterValue>)>,
+}
+
+/// The state of a paused program.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pause {
+    pub reason: PauseReason,
+    /// The active calls, innermost first.
+    pub frames: Vec<StackFrame>,
+    pub globals: Vec<(String, Option<InterpreterValue>)>,
+}
+
+impl Pause {
+    /// The line the program paused at, starting at 1.
+    pub fn line(&self) -> usize {
+        self.frames.first().map_or(0, |frame| frame.line)
+    }
+
+    /// Looks a variable up the way the paused statement would, preferring locals over globals.
+    pub fn variable(&self, name: &str) -> Option<&Option<InterpreterValue>> {
+        self.frames
+            .first()
+            .into_iter()
+            .flat_map(|frame| &frame.locals)
+            .chain(&self.globals)
+            .find(|(variable, _)| variable == name)
+            .map(|(_, value)| value)
+    }
+}
+
+/// Drives a debugging session: it is told when the program pauses, and decides how it continues.
+/// The program does not run while [`paused`](Self::paused) blocks.
+pub trait DebugFrontend {
+    fn paused(&mut self, pause: &Pause, breakpoints: &mut Breakpoints) -> Resume;
+
+    /// Called before every statement while the program runs, to update the breakpoints or to
+    /// pause the program by returning true. Frontends that only act while the program is paused
+    /// do not need it.
+    fn poll(&mut self, _breakpoints: &mut Breakpoints) -> bool {
+        false
+    }
+}
+
+/// The source lines the program pauses at, starting at 1.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct Breakpoints(BTreeSet<usize>);
+
+impl Breakpoints {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns false if there already was a breakpoint on the line.
+    pub fn insert(&mut self, line: usize) -> bool {
+        self.0.insert(line)
+    }
+
+    /// Returns false if there was no breakpoint on the line.
+    pub fn remove(&mut self, line: usize) -> bool {
+        self.0.remove(&line)
+    }
+
+    pub fn contains(&self, line: usize) -> bool {
+        self.0.contains(&line)
+    }
+
+    pub fn clear(&mut self) {
+        self.0.clear();
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = usize> + '_ {
+        self.0.iter().copied()
+    }
+}
+
+/// Returns true for the nodes the debugger pauses at.
+pub(crate) fn is_statement(data: &MxirNodeData) -> bool {
+    matches!(
+        data,
+        MxirNodeData::VarDecl(_)
+            | MxirNodeData::ExprStmt(_)
+            | MxirNodeData::Assign(..)
+            | MxirNodeData::Return(_)
+            | MxirNodeData::Loop(_)
+            | MxirNodeData::Iterate(_)
+            | MxirNodeData::If(_)
+            | MxirNodeData::Break
+            | MxirNodeData::Continue
+    )
+}
+
+/// The lines of a program that a statement starts on, starting at 1. Breakpoints on other lines
+/// are never hit. Functions that are never called have no statements.
+pub fn statement_lines(file: &AnalyzedSourceFile) -> BTreeSet<usize> {
+    file.mxir()
+        .0
+        .iter()
+        .filter(|node| is_statement(&node.data))
+        .filter_map(|node| file.ast().0.get(node.ast_node.0 as usize))
+        .map(|ast_node| ast_node.range.start.row + 1)
+        .collect()
+}
+
+/// Moves a breakpoint requested on `line` to the first line at or after it that has a statement.
+pub fn resolve_breakpoint(file: &AnalyzedSourceFile, line: usize) -> Option<usize> {
+    statement_lines(file).range(line..).next().copied()
+}
+
+/// Merges the scopes of a frame into its variables, letting inner scopes shadow outer ones.
+pub(crate) fn scope_variables(
+    scopes: &[HashMap<String, Option<InterpreterValue>>],
+) -> Vec<(String, Option<InterpreterValue>)> {
+    let mut variables: HashMap<&String, &Option<InterpreterValue>> = HashMap::new();
+    for scope in scopes {
+        variables.extend(scope);
+    }
+    let mut variables: Vec<_> = variables
+        .into_iter()
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    variables.sort_by(|(a, _), (b, _)| a.cmp(b));
+    variables
+}
+
+/// Pauses a program run by the interpreter at breakpoints and steps, and hands it to a
+/// [`DebugFrontend`] while it is paused.
+pub struct Debugger {
+    frontend: Box<dyn DebugFrontend>,
+    breakpoints: Breakpoints,
+    resume: Resume,
+    // The call depth the program was at when it last resumed
+    resume_depth: usize,
+    // The call depth and line the program last paused at, and the statements on that line it has
+    // run since, until it moves on to another line or runs one of them again
+    origin: Option<(usize, usize, Vec<MxirNodeRef>)>,
+    entered: bool,
+}
+
+impl Debugger {
+    /// Starts a session that pauses before the first statement of the program.
+    pub fn new(frontend: impl DebugFrontend + 'static) -> Self {
+        Self {
+            frontend: Box::new(frontend),
+            breakpoints: Breakpoints::new(),
+            resume: Resume::StepIn,
+            resume_depth: 0,
+            origin: None,
+            entered: false,
+        }
+    }
+
+    /// Runs the program until its first breakpoint instead of pausing at its first statement.
+    pub fn without_stop_on_entry(mut self) -> Self {
+        self.resume = Resume::Continue;
+        self
+    }
+
+    pub fn with_breakpoints(mut self, breakpoints: Breakpoints) -> Self {
+        self.breakpoints = breakpoints;
+        self
+    }
+
+    /// Decides whether to pause at `statement`, which is on `line`, while `depth` calls are
+    /// active.
+    pub(crate) fn should_pause(
+        &mut self,
+        depth: usize,
+        line: usize,
+        statement: MxirNodeRef,
+    ) -> Option<PauseReason> {
+        if self.frontend.poll(&mut self.breakpoints) {
+            self.entered = true;
+            return Some(PauseReason::Request);
+        }
+
+        // Stepping moves on to the next line, and a breakpoint pauses once per visit of its line.
+        // Running a statement of the line again, as a loop does, starts another visit.
+        if let Some((origin_depth, origin_line, visited)) = &mut self.origin {
+            if (*origin_depth, *origin_line) == (depth, line) && !visited.contains(&statement) {
+                visited.push(statement);
+                return None;
+            }
+        }
+        self.origin = None;
+
+        if !self.entered {
+            self.entered = true;
+            if self.resume == Resume::StepIn {
+                return Some(PauseReason::Entry);
+            }
+        }
+        if self.breakpoints.contains(line) {
+            return Some(PauseReason::Breakpoint);
+        }
+        let step = match self.resume {
+            Resume::StepIn => true,
+            Resume::StepOver => depth <= self.resume_depth,
+            Resume::StepOut => depth < self.resume_depth,
+            Resume::Continue | Resume::Stop => false,
+        };
+        step.then_some(PauseReason::Step)
+    }
+
+    /// Hands the program paused at `statement` to the frontend and returns how it continues.
+    pub(crate) fn pause(&mut self, depth: usize, statement: MxirNodeRef, pause: &Pause) -> Resume {
+        self.resume = self.frontend.paused(pause, &mut self.breakpoints);
+        self.resume_depth = depth;
+        self.origin = Some((depth, pause.line(), vec![statement]));
+        self.resume
+    }
+}
+
+impl std::fmt::Debug for Debugger {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Debugger")
+            .field("breakpoints", &self.breakpoints)
+            .field("resume", &self.resume)
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::{Interpreter, RuntimeErrorKind};
+    use crate::io::Io;
+    use crate::source_file::UnparsedSourceFile;
+    use std::cell::RefCell;
+    use std::rc::Rc;
+
+    const SOURCE: &str = r#"fn double(n: Int32): Int32 {
+    var result = n * 2;
+    return result;
+}
+
+fn main(): Int32 {
+    var a = 1;
+    var b = double(a);
+    var c = double(b);
+    return a + b + c;
+}
+"#;
+
+    /// Resumes with the scripted actions in order and records where the program paused.
+    struct Scripted {
+        actions: Vec<Resume>,
+        pauses: Rc<RefCell<Vec<Pause>>>,
+    }
+
+    impl DebugFrontend for Scripted {
+        fn paused(&mut self, pause: &Pause, _: &mut Breakpoints) -> Resume {
+            self.pauses.borrow_mut().push(pause.clone());
+            if self.actions.is_empty() {
+                Resume::Continue
+            } else {
+                self.actions.remove(0)
+            }
+        }
+    }
+
+    fn debug(debugger: impl FnOnce(Scripted) -> Debugger, actions: Vec<Resume>) -> Vec<Pause> {
+        let analyzed = UnparsedSourceFile::new("test.mx", SOURCE).parse().analyze();
+        assert!(analyzed.file().diagnostics.is_empty());
+
+        let pauses = Rc::new(RefCell::new(vec![]));
+        let frontend = Scripted {
+            actions,
+            pauses: pauses.clone(),
+        };
+        let result = Interpreter::new(&analyzed)
+            .with_io(Io::captured("").0)
+            .with_debugger(debugger(frontend))
+            .execute();
+        assert_eq!(result, Ok(Some(InterpreterValue::Integer(7))));
+        pauses.take()
+    }
+
+    fn lines(pauses: &[Pause]) -> Vec<(PauseReason, usize)> {
+        pauses
+            .iter()
+            .map(|pause| (pause.reason, pause.line()))
+            .collect()
+    }
+
+    #[test]
+    fn test_statement_lines() {
+        let analyzed = UnparsedSourceFile::new("test.mx", SOURCE).parse().analyze();
+        assert_eq!(
+            statement_lines(&analyzed).into_iter().collect::<Vec<_>>(),
+            vec![2, 3, 7, 8, 9, 10]
+        );
+        assert_eq!(resolve_breakpoint(&analyzed, 4), Some(7));
+        assert_eq!(resolve_breakpoint(&analyzed, 11), None);
+    }
+
+    #[test]
+    fn test_stepping() {
+        use PauseReason::{Entry, Step};
+        use Resume::{StepIn, StepOut, StepOver};
+
+        let pauses = debug(
+            Debugger::new,
+            vec![StepOver, StepIn, StepIn, StepOut, StepOver],
+        );
+        assert_eq!(
+            lines(&pauses),
+            vec![
+                (Entry, 7),
+                (Step, 8),
+                (Step, 2),
+                (Step, 3),
+                (Step, 9),
+                (Step, 10)
+            ]
+        );
+    }
+
+    #[test]
+    fn test_breakpoints_and_frames() {
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.insert(3);
+        let pauses = debug(
+            |frontend| {
+                Debugger::new(frontend)
+                    .without_stop_on_entry()
+                    .with_breakpoints(breakpoints)
+            },
+            vec![],
+        );
+        assert_eq!(
+            lines(&pauses),
+            vec![(PauseReason::Breakpoint, 3), (PauseReason::Breakpoint, 3)]
+        );
+
+        let frames = &pauses[1].frames;
+        let backtrace: Vec<_> = frames
+            .iter()
+            .map(|frame| (frame.name.as_str(), frame.line))
+            .collect();
+        assert_eq!(backtrace, vec![("double", 3), ("main", 9)]);
+        assert_eq!(
+            frames[0].locals,
+            vec![
+                ("n".to_string(), Some(InterpreterValue::Integer(2))),
+                ("result".to_string(), Some(InterpreterValue::Integer(4)))
+            ]
+        );
+        assert_eq!(
+            pauses[1].variable("a"),
+            None,
+            "Locals of the caller are not in scope"
+        );
+    }
+
+    #[test]
+    fn test_breakpoints_in_loops() {
+        let source = r#"fn main(): Int32 {
+    var total = 0;
+    loop i in 0 to 3 {
+        total = total + i;
+    }
+    loop i in 0 to 3 { total = total + 1; }
+    return total;
+}
+"#;
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        assert!(analyzed.file().diagnostics.is_empty());
+
+        let mut breakpoints = Breakpoints::new();
+        breakpoints.insert(4);
+        breakpoints.insert(6);
+        let pauses = Rc::new(RefCell::new(vec![]));
+        let frontend = Scripted {
+            actions: vec![],
+            pauses: pauses.clone(),
+        };
+        let debugger = Debugger::new(frontend)
+            .without_stop_on_entry()
+            .with_breakpoints(breakpoints);
+        let result = Interpreter::new(&analyzed)
+            .with_debugger(debugger)
+            .execute();
+        assert_eq!(result, Ok(Some(InterpreterValue::Integer(6))));
+
+        // Every iteration pauses, whether the body has a line of its own or not
+        let pauses = pauses.take();
+        assert_eq!(
+            lines(&pauses),
+            vec![
+                (PauseReason::Breakpoint, 4),
+                (PauseReason::Breakpoint, 4),
+                (PauseReason::Breakpoint, 4),
+                (PauseReason::Breakpoint, 6),
+                (PauseReason::Breakpoint, 6),
+                (PauseReason::Breakpoint, 6)
+            ]
+        );
+        let counters: Vec<_> = pauses[..3]
+            .iter()
+            .map(|pause| pause.variable("i").cloned())
+            .collect();
+        assert_eq!(
+            counters,
+            vec![
+                Some(Some(InterpreterValue::Integer(0))),
+                Some(Some(InterpreterValue::Integer(1))),
+                Some(Some(InterpreterValue::Integer(2)))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_stop() {
+        let analyzed = UnparsedSourceFile::new("test.mx", SOURCE).parse().analyze();
+        let frontend = Scripted {
+            actions: vec![Resume::Stop],
+            pauses: Rc::new(RefCell::new(vec![])),
+        };
+        let error = Interpreter::new(&analyzed)
+            .with_debugger(Debugger::new(frontend))
+            .execute()
+            .unwrap_err();
+        assert_eq!(*error.kind, RuntimeErrorKind::Stopped);
+        assert_eq!(error.range.start.row, 6);
+    }
+}
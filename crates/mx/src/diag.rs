@@ -1,4 +1,4 @@
-use crate::position::Range;
+use crate::{position::Range, types::Type};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct Diagnostic {
@@ -12,10 +12,41 @@ pub enum DiagnosticKind {
     MissingEntrypointFunction,
     MissingFunctionName,
     DuplicateDefinition,
+    HostFnShadowsBuiltin(String),
     DuplicateParamName,
     InvalidFunctionCall,
     IncorrectArgumentCount,
     SymbolNotFound(String),
+    UnknownOperator(String),
+    InvalidOperandTypes(String, Type, Type),
+    InvalidOperandType(String, Type),
+    IntegerLiteralOutOfRange(String),
+    MismatchedAssignmentType(Type, Type),
+    MismatchedReturnType(Type, Type),
+    MismatchedArgumentType(Type, Type),
+    NonBoolCondition(Type),
+    ExpectedType,
+    UnknownField(String, String),
+    MissingField(String, String),
+    DuplicateField(String),
+    InvalidAssignmentTarget,
+    MissingOperatorMethod(String, String, Option<String>),
+    MismatchedElementType(Type, Type),
+    NotIndexable(Type),
+    NonIntegerIndex(Type),
+    MismatchedKeyType(Type, Type),
+    UnhashableKeyType(Type),
+    NonIntegerRangeBound(Type),
+    NegativeRangeBound(i128),
+    ReversedRangeBounds(i128, i128),
+    NotIterable(Type),
+    InvalidEscapeSequence(String),
+    NotStringConvertible(Type),
+    ExpectedComptimeValue,
+    ComptimeDivisionByZero,
+    ComptimeIntegerOverflow,
+    ComptimeCallDepthExceeded(usize),
+    ComptimeStepLimitExceeded(usize),
     SyntaxError,
     SyntaxErrorExpectedToken(String),
 }
@@ -26,10 +57,102 @@ impl DiagnosticKind {
             DiagnosticKind::MissingEntrypointFunction => "Missing entrypoint function".to_string(),
             DiagnosticKind::MissingFunctionName => "Missing function name".to_string(),
             DiagnosticKind::DuplicateDefinition => "Duplicate definition".to_string(),
+            DiagnosticKind::HostFnShadowsBuiltin(name) => {
+                format!("Host function {} has the name of a builtin", name)
+            }
             DiagnosticKind::DuplicateParamName => "Duplicate parameter name".to_string(),
             DiagnosticKind::InvalidFunctionCall => "Invalid function call".to_string(),
             DiagnosticKind::IncorrectArgumentCount => "Incorrect argument count".to_string(),
             DiagnosticKind::SymbolNotFound(symbol) => format!("Symbol not found: {}", symbol),
+            DiagnosticKind::UnknownOperator(operator) => format!("Unknown operator: {}", operator),
+            DiagnosticKind::InvalidOperandTypes(operator, lhs, rhs) => format!(
+                "Operator '{}' cannot be applied to {} and {}",
+                operator, lhs, rhs
+            ),
+            DiagnosticKind::InvalidOperandType(operator, operand) => {
+                format!("Operator '{}' cannot be applied to {}", operator, operand)
+            }
+            DiagnosticKind::IntegerLiteralOutOfRange(literal) => {
+                format!("Integer literal {} does not fit in Int32", literal)
+            }
+            DiagnosticKind::MismatchedAssignmentType(expected, found) => format!(
+                "Mismatched types in assignment: expected {}, found {}",
+                expected, found
+            ),
+            DiagnosticKind::MismatchedReturnType(expected, found) => format!(
+                "Mismatched return type: expected {}, found {}",
+                expected, found
+            ),
+            DiagnosticKind::MismatchedArgumentType(expected, found) => format!(
+                "Mismatched argument type: expected {}, found {}",
+                expected, found
+            ),
+            DiagnosticKind::NonBoolCondition(found) => {
+                format!("Condition must be Bool, found {}", found)
+            }
+            DiagnosticKind::ExpectedType => "Expected a type".to_string(),
+            DiagnosticKind::UnknownField(ty, field) => {
+                format!("Type {} has no field named {}", ty, field)
+            }
+            DiagnosticKind::MissingField(ty, field) => {
+                format!("Missing field {} in instance of {}", field, ty)
+            }
+            DiagnosticKind::DuplicateField(field) => format!("Duplicate field: {}", field),
+            DiagnosticKind::InvalidAssignmentTarget => "Invalid assignment target".to_string(),
+            DiagnosticKind::MissingOperatorMethod(operator, ty, Some(method)) => format!(
+                "Operator '{}' cannot be applied to {}: no method named {}",
+                operator, ty, method
+            ),
+            DiagnosticKind::MismatchedElementType(expected, found) => format!(
+                "Mismatched element type: expected {}, found {}",
+                expected, found
+            ),
+            DiagnosticKind::NotIndexable(ty) => format!("Type {} cannot be indexed", ty),
+            DiagnosticKind::NonIntegerIndex(ty) => {
+                format!("Index must be Int32, found {}", ty)
+            }
+            DiagnosticKind::MissingOperatorMethod(operator, ty, None) => format!(
+                "Operator '{}' cannot be applied to {} and cannot be overloaded",
+                operator, ty
+            ),
+            DiagnosticKind::MismatchedKeyType(expected, found) => format!(
+                "Mismatched key type: expected {}, found {}",
+                expected, found
+            ),
+            DiagnosticKind::UnhashableKeyType(ty) => {
+                format!("Type {} cannot be used as a map key", ty)
+            }
+            DiagnosticKind::NonIntegerRangeBound(ty) => {
+                format!("Range bounds must be Int32, found {}", ty)
+            }
+            DiagnosticKind::NegativeRangeBound(bound) => {
+                format!("Range bound {} must not be negative", bound)
+            }
+            DiagnosticKind::ReversedRangeBounds(start, end) => {
+                format!("Range start {} is greater than its end {}", start, end)
+            }
+            DiagnosticKind::NotIterable(ty) => format!("Type {} cannot be iterated over", ty),
+            DiagnosticKind::InvalidEscapeSequence(sequence) => {
+                format!("Invalid escape sequence: {}", sequence)
+            }
+            DiagnosticKind::NotStringConvertible(ty) => {
+                format!("Type {} cannot be converted to a string", ty)
+            }
+            DiagnosticKind::ExpectedComptimeValue => {
+                "Expression cannot be evaluated at compile time".to_string()
+            }
+            DiagnosticKind::ComptimeDivisionByZero => {
+                "Division by zero in compile-time expression".to_string()
+            }
+            DiagnosticKind::ComptimeIntegerOverflow => {
+                "Integer overflow in compile-time expression".to_string()
+            }
+            DiagnosticKind::ComptimeCallDepthExceeded(depth) => {
+                format!("Compile-time evaluation exceeded {} nested calls", depth)
+            }
+            DiagnosticKind::ComptimeStepLimitExceeded(steps) => {
+                format!("Compile-time evaluation exceeded {} steps", steps)
+            }
             DiagnosticKind::SyntaxError => "Syntax error".to_string(),
             DiagnosticKind::SyntaxErrorExpectedToken(token) => {
                 format!("Syntax error, expected token: {}", token)
@@ -0,0 +1,502 @@
+use std::collections::HashMap;
+use std::fmt::Display;
+
+use crate::diag::Diagnostic;
+use crate::heap::{Heap, HeapUsage};
+use crate::host::{HostFns, HostValue, IntoHostFn};
+use crate::interpreter::{Interpreter, InterpreterValue, MapKey, RuntimeError};
+use crate::io::Io;
+use crate::limits::Limits;
+use crate::mxir::MxirNodeData;
+use crate::source_file::{AnalyzedSourceFile, UnparsedSourceFile};
+use crate::types::Type;
+
+/// Compiles MX scripts for a Rust application to call into. Scripts are libraries of functions
+/// and globals, so unlike programs run by `mx run` they do not need a `main` function.
+///
+/// ```ignore
+/// let mut engine = Engine::new();
+/// engine.register("shout", |text: String| text.to_uppercase());
+/// let mut script = engine.compile("greet.mx", source)?;
+/// let greeting: String = script.call("greet", ("Ada".to_string(),))?;
+/// ```
+#[derive(Debug, Clone, Default)]
+pub struct Engine {
+    host_fns: HostFns,
+    limits: Limits,
+}
+
+impl Engine {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Makes a Rust closure callable from the scripts compiled afterwards. See
+    /// [`HostFns::register`].
+    pub fn register<Args>(&mut self, name: &str, f: impl IntoHostFn<Args>) -> &mut Self {
+        self.host_fns.register(name, f);
+        self
+    }
+
+    /// Bounds the resources each call into a script may use.
+    pub fn set_limits(&mut self, limits: Limits) -> &mut Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Analyzes a script and returns its diagnostics, without running it.
+    pub fn diagnostics(&self, path: &str, source: &str) -> Vec<Diagnostic> {
+        self.analyze(path, source).file().diagnostics.clone()
+    }
+
+    /// Compiles a script and initializes its globals.
+    pub fn compile(&self, path: &str, source: &str) -> Result<Script, EngineError> {
+        let file = self.analyze(path, source);
+        if !file.file().diagnostics.is_empty() {
+            return Err(EngineError::Diagnostics(file.file().diagnostics.clone()));
+        }
+
+        let mut interpreter = Interpreter::new(&file).with_limits(self.limits);
+        interpreter.execute().map_err(EngineError::Runtime)?;
+        let globals = interpreter.globals().clone();
+        let heap = std::mem::take(interpreter.heap_mut());
+        let io = interpreter.into_io();
+
+        Ok(Script {
+            file,
+            limits: self.limits,
+            io,
+            heap,
+            globals,
+        })
+    }
+
+    fn analyze(&self, path: &str, source: &str) -> AnalyzedSourceFile {
+        UnparsedSourceFile::new(path, source)
+            .parse()
+            .analyze_library(self.host_fns.clone())
+    }
+}
+
+/// A compiled script. Its globals keep their values from one call to the next.
+pub struct Script {
+    file: AnalyzedSourceFile,
+    limits: Limits,
+    io: Io,
+    // The heap outlives each call, as the globals may refer to values on it
+    heap: Heap,
+    globals: HashMap<String, Option<InterpreterValue>>,
+}
+
+impl Script {
+    pub fn file(&self) -> &AnalyzedSourceFile {
+        &self.file
+    }
+
+    /// Replaces the streams the script reads from and writes to.
+    pub fn set_io(&mut self, io: Io) {
+        self.io = io;
+    }
+
+    /// How much of the heap the values of the script use.
+    pub fn heap_usage(&self) -> HeapUsage {
+        self.heap.usage()
+    }
+
+    /// Calls a top-level function of the script and converts its result to a Rust value.
+    pub fn call<R: FromResult>(
+        &mut self,
+        name: &str,
+        args: impl IntoArgs,
+    ) -> Result<R, EngineError> {
+        let args = args.into_args();
+        let interpreter = Interpreter::new(&self.file);
+        let Some(fn_decl_ref) = interpreter.fn_decl(name) else {
+            return Err(EngineError::UnknownFunction(name.to_string()));
+        };
+        if let Some(MxirNodeData::FnDecl(fn_decl)) = self
+            .file
+            .mxir()
+            .0
+            .get(fn_decl_ref.0 as usize)
+            .map(|node| &node.data)
+        {
+            if fn_decl.params.len() != args.len() {
+                return Err(EngineError::IncorrectArgumentCount(
+                    name.to_string(),
+                    fn_decl.params.len(),
+                    args.len(),
+                ));
+            }
+            for (index, (param, arg)) in fn_decl.params.iter().zip(&args).enumerate() {
+                if let Some(expected) = param.ty.as_type() {
+                    if !has_type(arg, &expected) {
+                        return Err(EngineError::MismatchedArgumentType(
+                            name.to_string(),
+                            index,
+                            expected.to_string(),
+                            arg.type_name(),
+                        ));
+                    }
+                }
+            }
+        }
+
+        let mut interpreter = interpreter
+            .with_limits(self.limits)
+            .with_io(std::mem::take(&mut self.io))
+            .with_heap(std::mem::take(&mut self.heap))
+            .with_globals(std::mem::take(&mut self.globals));
+        let result = interpreter.call(fn_decl_ref, args);
+        self.globals = interpreter.globals().clone();
+        self.heap = std::mem::take(interpreter.heap_mut());
+        self.io = interpreter.into_io();
+
+        convert(result.map_err(EngineError::Runtime)?)
+    }
+
+    /// Reads a global variable of the script.
+    pub fn global<R: FromResult>(&self, name: &str) -> Result<R, EngineError> {
+        match self.globals.get(name) {
+            Some(value) => convert(value.clone()),
+            None => Err(EngineError::UnknownGlobal(name.to_string())),
+        }
+    }
+
+    /// Assigns a global variable of the script. The value must have the type of the global.
+    pub fn set_global(&mut self, name: &str, value: impl HostValue) -> Result<(), EngineError> {
+        let value = value.into_value();
+        let declared_type = self.global_type(name);
+        let Some(global) = self.globals.get_mut(name) else {
+            return Err(EngineError::UnknownGlobal(name.to_string()));
+        };
+        if let Some(expected) = declared_type {
+            if !has_type(&value, &expected) {
+                return Err(EngineError::MismatchedGlobalType(
+                    name.to_string(),
+                    expected.to_string(),
+                    value.type_name(),
+                ));
+            }
+        }
+        *global = Some(value);
+        Ok(())
+    }
+
+    /// The type a global is declared with, or inferred from its initializer.
+    fn global_type(&self, name: &str) -> Option<Type> {
+        let mxir = self.file.mxir();
+        let stmts = mxir.0.iter().find_map(|node| match &node.data {
+            MxirNodeData::SourceFile(block) => Some(&block.0),
+            _ => None,
+        })?;
+        stmts
+            .iter()
+            .find_map(|stmt_ref| match &mxir.0.get(stmt_ref.0 as usize)?.data {
+                MxirNodeData::VarDecl(var_decl) if var_decl.name == name => {
+                    var_decl.ty.as_ref()?.as_type()
+                }
+                _ => None,
+            })
+    }
+
+    /// Renders a runtime error raised by the script with the source line it points at.
+    pub fn render_error(&self, error: &RuntimeError) -> String {
+        error.render(self.file.file())
+    }
+}
+
+/// Returns whether a value passed in by the host belongs to `ty`, including the elements of lists
+/// and maps.
+fn has_type(value: &InterpreterValue, ty: &Type) -> bool {
+    match (value, ty) {
+        (InterpreterValue::Integer(value), Type::Int32) => i32::try_from(*value).is_ok(),
+        (InterpreterValue::Float(_), Type::Float)
+        | (InterpreterValue::Boolean(_), Type::Bool)
+        | (InterpreterValue::String(_), Type::String)
+        | (InterpreterValue::Range(_), Type::Range) => true,
+        (InterpreterValue::List(list), Type::List(element_type)) => list
+            .borrow()
+            .iter()
+            .all(|element| has_type(element, element_type)),
+        (InterpreterValue::Map(map), Type::Map(key_type, value_type)) => {
+            map.borrow().iter().all(|(key, value)| {
+                let key_matches = matches!(
+                    (key, key_type.as_ref()),
+                    (MapKey::Integer(_), Type::Int32)
+                        | (MapKey::Boolean(_), Type::Bool)
+                        | (MapKey::String(_), Type::String)
+                );
+                key_matches && has_type(value, value_type)
+            })
+        }
+        (InterpreterValue::Struct(struct_value), Type::Struct(struct_type)) => {
+            struct_value.borrow().name == struct_type.name
+        }
+        _ => false,
+    }
+}
+
+fn convert<R: FromResult>(value: Option<InterpreterValue>) -> Result<R, EngineError> {
+    let found = value
+        .as_ref()
+        .map_or_else(|| "Void".to_string(), InterpreterValue::type_name);
+    R::from_result(value)
+        .ok_or_else(|| EngineError::UnexpectedResult(found, std::any::type_name::<R>()))
+}
+
+/// The ways in which compiling or calling into a script can fail.
+#[derive(Debug, Clone, PartialEq)]
+pub enum EngineError {
+    Diagnostics(Vec<Diagnostic>),
+    Runtime(RuntimeError),
+    UnknownFunction(String),
+    // The name of the function, the number of parameters and the number of arguments
+    IncorrectArgumentCount(String, usize, usize),
+    // The name of the function, the index of the argument, the type of its parameter and the type
+    // of the argument
+    MismatchedArgumentType(String, usize, String, String),
+    UnknownGlobal(String),
+    // The name of the global, its type and the type of the new value
+    MismatchedGlobalType(String, String, String),
+    // The type of the value and the Rust type it was converted to
+    UnexpectedResult(String, &'static str),
+}
+
+impl Display for EngineError {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            EngineError::Diagnostics(diagnostics) => {
+                write!(f, "Script has {} diagnostics", diagnostics.len())
+            }
+            EngineError::Runtime(error) => write!(f, "{}", error.kind.message()),
+            EngineError::UnknownFunction(name) => write!(f, "Function {} is not declared", name),
+            EngineError::IncorrectArgumentCount(name, expected, found) => write!(
+                f,
+                "Function {} expects {} arguments, found {}",
+                name, expected, found
+            ),
+            EngineError::MismatchedArgumentType(name, index, expected, found) => write!(
+                f,
+                "Argument {} of function {} has type {}, found {}",
+                index, name, expected, found
+            ),
+            EngineError::UnknownGlobal(name) => write!(f, "Global {} is not declared", name),
+            EngineError::MismatchedGlobalType(name, expected, found) => {
+                write!(f, "Global {} has type {}, found {}", name, expected, found)
+            }
+            EngineError::UnexpectedResult(found, expected) => {
+                write!(
+                    f,
+                    "Value of type {} cannot be converted to {}",
+                    found, expected
+                )
+            }
+        }
+    }
+}
+
+impl std::error::Error for EngineError {}
+
+/// The arguments of a call into a script: a tuple of [`HostValue`]s, or values that are already
+/// converted.
+pub trait IntoArgs {
+    fn into_args(self) -> Vec<InterpreterValue>;
+}
+
+impl IntoArgs for Vec<InterpreterValue> {
+    fn into_args(self) -> Vec<InterpreterValue> {
+        self
+    }
+}
+
+macro_rules! impl_into_args {
+    ($($param:ident),*) => {
+        impl<$($param: HostValue,)*> IntoArgs for ($($param,)*) {
+            #[allow(non_snake_case)]
+            fn into_args(self) -> Vec<InterpreterValue> {
+                let ($($param,)*) = self;
+                vec![$($param.into_value()),*]
+            }
+        }
+    };
+}
+
+impl_into_args!();
+impl_into_args!(A);
+impl_into_args!(A, B);
+impl_into_args!(A, B, C);
+impl_into_args!(A, B, C, D);
+impl_into_args!(A, B, C, D, E);
+impl_into_args!(A, B, C, D, E, G);
+
+/// A Rust type the result of a call, or the value of a global, can be converted to. Besides
+/// [`HostValue`]s, `()` accepts a call that returns nothing, and `Option<InterpreterValue>`
+/// accepts anything without converting it.
+pub trait FromResult: Sized {
+    fn from_result(value: Option<InterpreterValue>) -> Option<Self>;
+}
+
+impl<T: HostValue> FromResult for T {
+    fn from_result(value: Option<InterpreterValue>) -> Option<Self> {
+        value.and_then(T::from_value)
+    }
+}
+
+impl FromResult for () {
+    fn from_result(value: Option<InterpreterValue>) -> Option<Self> {
+        match value {
+            None => Some(()),
+            Some(_) => None,
+        }
+    }
+}
+
+impl FromResult for Option<InterpreterValue> {
+    fn from_result(value: Option<InterpreterValue>) -> Option<Self> {
+        Some(value)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::RuntimeErrorKind;
+    use crate::limits::{Limit, DEFAULT_MAX_CALL_DEPTH};
+
+    const SOURCE: &str = r#"
+        var visits = 0;
+        var greeting = "Hello";
+        var limit: Int32;
+
+        fn greet(name: String): String {
+            visits = visits + 1;
+            var loud = shout(name);
+            return "${greeting}, ${loud}!";
+        }
+
+        fn divide(a: Int32, b: Int32): Int32 {
+            return a / b;
+        }
+    "#;
+
+    fn compile() -> Script {
+        let mut engine = Engine::new();
+        engine.register("shout", |text: String| text.to_uppercase());
+        engine.compile("script.mx", SOURCE).unwrap()
+    }
+
+    #[test]
+    fn test_call_functions() {
+        let mut script = compile();
+        let greeting: String = script.call("greet", ("Ada".to_string(),)).unwrap();
+        assert_eq!(greeting, "Hello, ADA!");
+        assert_eq!(script.call::<i32>("divide", (7, 2)), Ok(3));
+
+        assert_eq!(
+            script.call::<i32>("missing", ()),
+            Err(EngineError::UnknownFunction("missing".to_string()))
+        );
+        assert_eq!(
+            script.call::<i32>("divide", (1,)),
+            Err(EngineError::IncorrectArgumentCount(
+                "divide".to_string(),
+                2,
+                1
+            ))
+        );
+        assert_eq!(
+            script.call::<i32>("divide", (1, "two".to_string())),
+            Err(EngineError::MismatchedArgumentType(
+                "divide".to_string(),
+                1,
+                "Int32".to_string(),
+                "String".to_string()
+            ))
+        );
+        assert!(matches!(
+            script.call::<bool>("divide", (4, 2)),
+            Err(EngineError::UnexpectedResult(_, "bool"))
+        ));
+        match script.call::<i32>("divide", (1, 0)) {
+            Err(EngineError::Runtime(error)) => {
+                assert_eq!(*error.kind, RuntimeErrorKind::DivisionByZero);
+                assert_eq!(error.call_stack, vec!["divide"]);
+            }
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_globals_persist_between_calls() {
+        let mut script = compile();
+        assert_eq!(script.global::<i32>("visits"), Ok(0));
+
+        script.set_global("greeting", "Hi".to_string()).unwrap();
+        let _: String = script.call("greet", ("Bob".to_string(),)).unwrap();
+        let greeting: String = script.call("greet", ("Eve".to_string(),)).unwrap();
+        assert_eq!(greeting, "Hi, EVE!");
+        assert_eq!(script.global::<i32>("visits"), Ok(2));
+
+        assert_eq!(
+            script.set_global("visits", true),
+            Err(EngineError::MismatchedGlobalType(
+                "visits".to_string(),
+                "Int32".to_string(),
+                "Bool".to_string()
+            ))
+        );
+        // Globals without a value yet are checked against their declared type
+        assert_eq!(
+            script.set_global("limit", "ten".to_string()),
+            Err(EngineError::MismatchedGlobalType(
+                "limit".to_string(),
+                "Int32".to_string(),
+                "String".to_string()
+            ))
+        );
+        assert_eq!(script.set_global("limit", 10), Ok(()));
+        assert_eq!(
+            script.global::<i32>("missing"),
+            Err(EngineError::UnknownGlobal("missing".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_deep_recursion_hits_call_depth() {
+        let source = r#"
+            fn down(n: Int32): Int32 {
+                return down(n + 1);
+            }
+        "#;
+        // The calls take far more than the small stack of this thread, which the interpreter
+        // grows rather than overflows
+        let result = std::thread::Builder::new()
+            .stack_size(512 << 10)
+            .spawn(move || {
+                let mut script = Engine::new().compile("down.mx", source).unwrap();
+                script.call::<i32>("down", (0,))
+            })
+            .unwrap()
+            .join()
+            .unwrap();
+        match result {
+            Err(EngineError::Runtime(error)) => assert_eq!(
+                *error.kind,
+                RuntimeErrorKind::LimitExceeded(Limit::CallDepth(DEFAULT_MAX_CALL_DEPTH))
+            ),
+            result => panic!("Unexpected result: {:?}", result),
+        }
+    }
+
+    #[test]
+    fn test_diagnostics() {
+        let engine = Engine::new();
+        let source = "fn broken(): Int32 { return missing; }";
+        assert!(!engine.diagnostics("broken.mx", source).is_empty());
+        assert!(matches!(
+            engine.compile("broken.mx", source),
+            Err(EngineError::Diagnostics(_))
+        ));
+    }
+}
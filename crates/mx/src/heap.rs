@@ -0,0 +1,424 @@
+use std::cell::{Ref, RefCell, RefMut};
+use std::collections::{BTreeMap, HashMap};
+use std::rc::{Rc, Weak};
+
+use crate::interpreter::{InterpreterValue, MapKey, StructValue};
+
+// Collecting cycles visits every value on the heap, so it only happens once the heap has doubled
+// in size since the last collection, and never below this many values
+const MIN_COLLECTION_THRESHOLD: usize = 1024;
+
+thread_local! {
+    // The values, or pairs of values, that are being formatted or compared further up the stack
+    static VISITING: RefCell<Vec<(usize, usize)>> = const { RefCell::new(Vec::new()) };
+}
+
+/// A value on the heap, such as a list, map or struct. Cloning it makes another reference to the
+/// same value, so a change made through one reference is seen through all of them.
+pub struct Shared<T>(Rc<RefCell<T>>);
+
+impl<T> Shared<T> {
+    /// Puts a value on the heap without tracking it. It is freed once the last reference to it is
+    /// dropped, which never happens if it refers to itself, so values created by a program are
+    /// allocated by its [`Heap`] instead.
+    pub fn new(value: T) -> Self {
+        Self(Rc::new(RefCell::new(value)))
+    }
+
+    pub fn borrow(&self) -> Ref<'_, T> {
+        self.0.borrow()
+    }
+
+    pub fn borrow_mut(&self) -> RefMut<'_, T> {
+        self.0.borrow_mut()
+    }
+
+    /// Returns true if both are references to the same value.
+    pub fn ptr_eq(&self, other: &Self) -> bool {
+        Rc::ptr_eq(&self.0, &other.0)
+    }
+
+    /// Runs `f` on the value, unless the value is already being visited further up the stack
+    /// because it contains itself.
+    pub(crate) fn visit<R>(&self, f: impl FnOnce(&T) -> R) -> Option<R> {
+        let _visit = Visit::enter((self.address(), 0))?;
+        Some(f(&self.borrow()))
+    }
+
+    fn address(&self) -> usize {
+        Rc::as_ptr(&self.0) as *const () as usize
+    }
+}
+
+impl<T> Clone for Shared<T> {
+    fn clone(&self) -> Self {
+        Self(Rc::clone(&self.0))
+    }
+}
+
+impl<T: PartialEq> PartialEq for Shared<T> {
+    fn eq(&self, other: &Self) -> bool {
+        if self.ptr_eq(other) {
+            return true;
+        }
+        // Two cyclic values are equal if nothing but the cycle is left to compare
+        match Visit::enter((self.address(), other.address())) {
+            Some(_visit) => *self.borrow() == *other.borrow(),
+            None => true,
+        }
+    }
+}
+
+impl<T: std::fmt::Debug> std::fmt::Debug for Shared<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.visit(|value| value.fmt(f)) {
+            Some(result) => result,
+            None => f.write_str("..."),
+        }
+    }
+}
+
+/// Marks a value as being visited until it is dropped.
+struct Visit((usize, usize));
+
+impl Visit {
+    fn enter(key: (usize, usize)) -> Option<Self> {
+        VISITING.with(|visiting| {
+            let mut visiting = visiting.borrow_mut();
+            if visiting.contains(&key) {
+                return None;
+            }
+            visiting.push(key);
+            Some(Self(key))
+        })
+    }
+}
+
+impl Drop for Visit {
+    fn drop(&mut self) {
+        VISITING.with(|visiting| {
+            let mut visiting = visiting.borrow_mut();
+            if let Some(i) = visiting.iter().rposition(|key| *key == self.0) {
+                visiting.remove(i);
+            }
+        });
+    }
+}
+
+/// A value that can live on the heap and refer to other values on it.
+pub trait Trace {
+    /// Calls `f` with the address of every value on the heap that this one refers to directly.
+    fn trace(&self, f: &mut dyn FnMut(usize));
+
+    /// Drops the references this value holds, breaking the cycles it is part of.
+    fn clear(&mut self);
+}
+
+fn trace_value(value: &InterpreterValue, f: &mut dyn FnMut(usize)) {
+    match value {
+        InterpreterValue::List(list) => f(list.address()),
+        InterpreterValue::Map(map) => f(map.address()),
+        InterpreterValue::Struct(struct_value) => f(struct_value.address()),
+        _ => {}
+    }
+}
+
+impl Trace for Vec<InterpreterValue> {
+    fn trace(&self, f: &mut dyn FnMut(usize)) {
+        self.iter().for_each(|value| trace_value(value, f));
+    }
+
+    fn clear(&mut self) {
+        Vec::clear(self);
+    }
+}
+
+impl Trace for BTreeMap<MapKey, InterpreterValue> {
+    fn trace(&self, f: &mut dyn FnMut(usize)) {
+        self.values().for_each(|value| trace_value(value, f));
+    }
+
+    fn clear(&mut self) {
+        BTreeMap::clear(self);
+    }
+}
+
+impl Trace for StructValue {
+    fn trace(&self, f: &mut dyn FnMut(usize)) {
+        self.fields
+            .iter()
+            .for_each(|(_, value)| trace_value(value, f));
+    }
+
+    fn clear(&mut self) {
+        self.fields.clear();
+    }
+}
+
+/// The view the collector has of a value on the heap, whatever its type.
+trait Object {
+    fn address(&self) -> usize;
+
+    /// Traces the value, or returns false if it cannot be read because it is being changed.
+    fn trace(&self, f: &mut dyn FnMut(usize)) -> bool;
+
+    fn clear(&self);
+}
+
+impl<T: Trace> Object for RefCell<T> {
+    fn address(&self) -> usize {
+        self as *const Self as *const () as usize
+    }
+
+    fn trace(&self, f: &mut dyn FnMut(usize)) -> bool {
+        match self.try_borrow() {
+            Ok(value) => {
+                value.trace(f);
+                true
+            }
+            Err(_) => false,
+        }
+    }
+
+    fn clear(&self) {
+        if let Ok(mut value) = self.try_borrow_mut() {
+            value.clear();
+        }
+    }
+}
+
+/// How many values a [`Heap`] holds and has freed.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct HeapUsage {
+    /// The values that are still alive.
+    pub live: usize,
+    /// The values allocated since the heap was created.
+    pub allocated: usize,
+    /// The values freed by collecting cycles. Values that are not part of a cycle are freed as
+    /// soon as nothing refers to them, and are not counted.
+    pub collected: usize,
+}
+
+/// Allocates the lists, maps and structs of a program. Values are reference counted, and the heap
+/// keeps track of them so that it can also free the cycles that reference counting cannot.
+pub struct Heap {
+    objects: Vec<Weak<dyn Object>>,
+    // The number of values at which the next collection happens
+    threshold: usize,
+    allocated: usize,
+    collected: usize,
+}
+
+impl Heap {
+    pub fn new() -> Self {
+        Self {
+            objects: vec![],
+            threshold: MIN_COLLECTION_THRESHOLD,
+            allocated: 0,
+            collected: 0,
+        }
+    }
+
+    /// Puts a value on the heap. Cycles are collected first if the heap has grown enough since the
+    /// last collection.
+    pub fn alloc<T: Trace + 'static>(&mut self, value: T) -> Shared<T> {
+        if self.objects.len() >= self.threshold {
+            self.collect();
+            self.threshold = (self.objects.len() * 2).max(MIN_COLLECTION_THRESHOLD);
+        }
+
+        let shared = Shared::new(value);
+        let object: Weak<RefCell<T>> = Rc::downgrade(&shared.0);
+        self.objects.push(object);
+        self.allocated += 1;
+        shared
+    }
+
+    /// Frees the values that are only kept alive by cycles of references between them, and
+    /// returns how many were freed. A value that anything besides the heap refers to, such as a
+    /// variable or a value held by the host, is kept along with everything it refers to.
+    pub fn collect(&mut self) -> usize {
+        let objects: Vec<Rc<dyn Object>> = self.objects.iter().filter_map(Weak::upgrade).collect();
+        let indices: HashMap<usize, usize> = objects
+            .iter()
+            .enumerate()
+            .map(|(i, object)| (object.address(), i))
+            .collect();
+
+        // Count the references each value receives from other values on the heap
+        let mut internal = vec![0; objects.len()];
+        let mut edges = vec![vec![]; objects.len()];
+        let mut reachable = vec![false; objects.len()];
+        for (i, object) in objects.iter().enumerate() {
+            let traced = object.trace(&mut |address| {
+                if let Some(&j) = indices.get(&address) {
+                    internal[j] += 1;
+                    edges[i].push(j);
+                }
+            });
+            // A value that is being changed is in use
+            reachable[i] |= !traced;
+        }
+
+        // A value with more references than that, besides the one upgraded above, is referred to
+        // from outside of the heap, and so is everything it refers to
+        let mut pending: Vec<usize> = (0..objects.len())
+            .filter(|&i| reachable[i] || Rc::strong_count(&objects[i]) - 1 > internal[i])
+            .collect();
+        for &i in &pending {
+            reachable[i] = true;
+        }
+        while let Some(i) = pending.pop() {
+            for &j in &edges[i] {
+                if !reachable[j] {
+                    reachable[j] = true;
+                    pending.push(j);
+                }
+            }
+        }
+
+        // Clearing the unreachable values breaks their cycles, so they are freed once the
+        // references upgraded above are dropped
+        let mut freed = 0;
+        self.objects.clear();
+        for (object, reachable) in objects.iter().zip(reachable) {
+            if reachable {
+                self.objects.push(Rc::downgrade(object));
+            } else {
+                object.clear();
+                freed += 1;
+            }
+        }
+        self.collected += freed;
+        freed
+    }
+
+    pub fn usage(&self) -> HeapUsage {
+        HeapUsage {
+            live: self
+                .objects
+                .iter()
+                .filter(|object| object.strong_count() > 0)
+                .count(),
+            allocated: self.allocated,
+            collected: self.collected,
+        }
+    }
+}
+
+impl Default for Heap {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for Heap {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Heap")
+            .field("usage", &self.usage())
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn node(heap: &mut Heap, name: &str) -> Shared<StructValue> {
+        heap.alloc(StructValue {
+            name: "Node".to_string(),
+            fields: vec![(
+                "name".to_string(),
+                InterpreterValue::String(name.to_string()),
+            )],
+        })
+    }
+
+    fn link(from: &Shared<StructValue>, to: &Shared<StructValue>) {
+        from.borrow_mut()
+            .fields
+            .push(("next".to_string(), InterpreterValue::Struct(to.clone())));
+    }
+
+    #[test]
+    fn test_shared_references() {
+        let mut heap = Heap::new();
+        let list = heap.alloc(vec![InterpreterValue::Integer(1)]);
+        let alias = list.clone();
+        alias.borrow_mut().push(InterpreterValue::Integer(2));
+        assert!(list.ptr_eq(&alias));
+        assert_eq!(list.borrow().len(), 2);
+
+        // Values are compared by their contents
+        let copy = heap.alloc(list.borrow().clone());
+        assert!(!list.ptr_eq(&copy));
+        assert_eq!(list, copy);
+
+        drop((list, alias));
+        assert_eq!(
+            heap.usage(),
+            HeapUsage {
+                live: 1,
+                allocated: 2,
+                collected: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_collect_cycles() {
+        let mut heap = Heap::new();
+        let a = node(&mut heap, "a");
+        let b = node(&mut heap, "b");
+        let c = node(&mut heap, "c");
+        link(&a, &b);
+        link(&b, &a);
+        link(&c, &c);
+
+        // Values that are still referred to are kept, along with everything they refer to
+        assert_eq!(heap.collect(), 0);
+        drop(b);
+        assert_eq!(heap.collect(), 0);
+        assert_eq!(heap.usage().live, 3);
+
+        drop((a, c));
+        assert_eq!(heap.usage().live, 3);
+        assert_eq!(heap.collect(), 3);
+        assert_eq!(
+            heap.usage(),
+            HeapUsage {
+                live: 0,
+                allocated: 3,
+                collected: 3
+            }
+        );
+    }
+
+    #[test]
+    fn test_collect_while_allocating() {
+        let mut heap = Heap::new();
+        for _ in 0..MIN_COLLECTION_THRESHOLD * 4 {
+            let a = node(&mut heap, "a");
+            link(&a, &a);
+        }
+        let usage = heap.usage();
+        assert!(usage.live <= MIN_COLLECTION_THRESHOLD);
+        assert_eq!(usage.live + usage.collected, usage.allocated);
+    }
+
+    #[test]
+    fn test_cyclic_values_are_formatted_and_compared() {
+        let mut heap = Heap::new();
+        let list = heap.alloc(vec![]);
+        list.borrow_mut().push(InterpreterValue::List(list.clone()));
+        let value = InterpreterValue::List(list.clone());
+        assert_eq!(value.to_string(), "[[...]]");
+        assert_eq!(format!("{:?}", list), "[List(...)]");
+
+        let other = heap.alloc(vec![]);
+        other
+            .borrow_mut()
+            .push(InterpreterValue::List(other.clone()));
+        assert_eq!(value, InterpreterValue::List(other));
+    }
+}
@@ -0,0 +1,359 @@
+use std::fmt::Display;
+
+use crate::ast::AstNodeRef;
+use crate::builtins::BuiltinFn;
+use crate::comptime::{ComptimeValue, FnProto, ParamDecl};
+use crate::heap::Shared;
+use crate::interpreter::{InterpreterValue, RuntimeErrorKind};
+use crate::types::Type;
+
+/// A Rust function registered by the host, along with the prototype programs see.
+#[derive(Debug, Clone)]
+pub struct HostFn {
+    pub proto: FnProto,
+    pub f: BuiltinFn,
+}
+
+/// The functions a host makes available to the programs it runs, in addition to the builtins.
+#[derive(Debug, Clone, Default)]
+pub struct HostFns(Vec<HostFn>);
+
+impl HostFns {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Registers a Rust closure under `name`. Its parameter and return types determine the
+    /// prototype of the function, and arguments and results are converted to and from
+    /// [`InterpreterValue`]s when it is called. Registering a name again replaces the function, and
+    /// a name taken by a builtin is reported as a diagnostic of the programs analyzed with it.
+    ///
+    /// ```ignore
+    /// host_fns.register("shout", |text: String| -> String { text.to_uppercase() });
+    /// ```
+    pub fn register<Args>(&mut self, name: &str, f: impl IntoHostFn<Args>) -> &mut Self {
+        let params = f
+            .param_types()
+            .into_iter()
+            .enumerate()
+            .map(|(index, ty)| ParamDecl {
+                node_ref: AstNodeRef(0),
+                name: format!("arg{}", index),
+                ty: ComptimeValue::Type(ty),
+            })
+            .collect();
+        let host_fn = HostFn {
+            proto: FnProto {
+                name: Some(name.to_string()),
+                comptime_params: vec![],
+                params,
+                return_type: ComptimeValue::Type(f.return_type()),
+            },
+            f: f.into_builtin_fn(),
+        };
+
+        self.0
+            .retain(|existing| existing.proto.name.as_deref() != Some(name));
+        self.0.push(host_fn);
+        self
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &HostFn> {
+        self.0.iter()
+    }
+}
+
+/// A Rust type that can be passed to and returned from host functions.
+pub trait HostValue: Sized {
+    /// The type of the value in programs.
+    fn mx_type() -> Type;
+    fn from_value(value: InterpreterValue) -> Option<Self>;
+    fn into_value(self) -> InterpreterValue;
+}
+
+impl HostValue for i32 {
+    fn mx_type() -> Type {
+        Type::Int32
+    }
+
+    fn from_value(value: InterpreterValue) -> Option<Self> {
+        match value {
+            InterpreterValue::Integer(value) => i32::try_from(value).ok(),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> InterpreterValue {
+        InterpreterValue::Integer(self.into())
+    }
+}
+
+impl HostValue for f64 {
+    fn mx_type() -> Type {
+        Type::Float
+    }
+
+    fn from_value(value: InterpreterValue) -> Option<Self> {
+        match value {
+            InterpreterValue::Float(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> InterpreterValue {
+        InterpreterValue::Float(self)
+    }
+}
+
+impl HostValue for bool {
+    fn mx_type() -> Type {
+        Type::Bool
+    }
+
+    fn from_value(value: InterpreterValue) -> Option<Self> {
+        match value {
+            InterpreterValue::Boolean(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> InterpreterValue {
+        InterpreterValue::Boolean(self)
+    }
+}
+
+impl HostValue for String {
+    fn mx_type() -> Type {
+        Type::String
+    }
+
+    fn from_value(value: InterpreterValue) -> Option<Self> {
+        match value {
+            InterpreterValue::String(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> InterpreterValue {
+        InterpreterValue::String(self)
+    }
+}
+
+impl<T: HostValue> HostValue for Vec<T> {
+    fn mx_type() -> Type {
+        Type::List(Box::new(T::mx_type()))
+    }
+
+    fn from_value(value: InterpreterValue) -> Option<Self> {
+        match value {
+            InterpreterValue::List(list) => {
+                list.borrow().iter().cloned().map(T::from_value).collect()
+            }
+            _ => None,
+        }
+    }
+
+    fn into_value(self) -> InterpreterValue {
+        InterpreterValue::List(Shared::new(self.into_iter().map(T::into_value).collect()))
+    }
+}
+
+/// A Rust type that host functions can return. Besides values, a function can return nothing, or
+/// a `Result` whose error becomes a runtime error.
+pub trait HostReturn {
+    fn return_type() -> Type;
+    fn into_result(self) -> Result<Option<InterpreterValue>, RuntimeErrorKind>;
+}
+
+impl<T: HostValue> HostReturn for T {
+    fn return_type() -> Type {
+        T::mx_type()
+    }
+
+    fn into_result(self) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+        Ok(Some(self.into_value()))
+    }
+}
+
+impl HostReturn for () {
+    fn return_type() -> Type {
+        Type::Void
+    }
+
+    fn into_result(self) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+        Ok(None)
+    }
+}
+
+impl<T: HostReturn, E: Display> HostReturn for Result<T, E> {
+    fn return_type() -> Type {
+        T::return_type()
+    }
+
+    fn into_result(self) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+        self.map_err(|error| RuntimeErrorKind::Host(error.to_string()))?
+            .into_result()
+    }
+}
+
+/// A Rust closure that can be registered as a host function. It is implemented for thread-safe
+/// closures of up to six parameters whose parameters are [`HostValue`]s and whose result is a
+/// [`HostReturn`].
+pub trait IntoHostFn<Args> {
+    fn param_types(&self) -> Vec<Type>;
+    fn return_type(&self) -> Type;
+    fn into_builtin_fn(self) -> BuiltinFn;
+}
+
+/// Converts the argument at `index` to the type of its parameter.
+fn from_arg<T: HostValue>(args: &[InterpreterValue], index: usize) -> Result<T, RuntimeErrorKind> {
+    let value = args
+        .get(index)
+        .cloned()
+        .ok_or(RuntimeErrorKind::MissingValue)?;
+    let found = value.type_name();
+    T::from_value(value).ok_or_else(|| {
+        RuntimeErrorKind::InvalidHostArgument(index, T::mx_type().to_string(), found)
+    })
+}
+
+macro_rules! impl_into_host_fn {
+    ($($param:ident),*) => {
+        impl<F, R, $($param,)*> IntoHostFn<($($param,)*)> for F
+        where
+            F: Fn($($param),*) -> R + Send + Sync + 'static,
+            R: HostReturn,
+            $($param: HostValue,)*
+        {
+            fn param_types(&self) -> Vec<Type> {
+                vec![$($param::mx_type()),*]
+            }
+
+            fn return_type(&self) -> Type {
+                R::return_type()
+            }
+
+            #[allow(non_snake_case, unused_mut, unused_variables, unused_assignments)]
+            fn into_builtin_fn(self) -> BuiltinFn {
+                BuiltinFn::new(move |_, args| {
+                    let mut index = 0;
+                    $(
+                        let $param = from_arg::<$param>(args, index)?;
+                        index += 1;
+                    )*
+                    self($($param),*).into_result()
+                })
+            }
+        }
+    };
+}
+
+impl_into_host_fn!();
+impl_into_host_fn!(A);
+impl_into_host_fn!(A, B);
+impl_into_host_fn!(A, B, C);
+impl_into_host_fn!(A, B, C, D);
+impl_into_host_fn!(A, B, C, D, E);
+impl_into_host_fn!(A, B, C, D, E, G);
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::io::Io;
+
+    fn call(
+        host_fns: &HostFns,
+        name: &str,
+        args: &[InterpreterValue],
+    ) -> Result<Option<InterpreterValue>, RuntimeErrorKind> {
+        let host_fn = host_fns
+            .iter()
+            .find(|host_fn| host_fn.proto.name.as_deref() == Some(name))
+            .expect("Host function is not registered");
+        host_fn.f.call(&mut Io::captured("").0, args)
+    }
+
+    #[test]
+    fn test_register_derives_proto() {
+        let mut host_fns = HostFns::new();
+        host_fns.register("repeat", |text: String, times: i32| -> String {
+            text.repeat(times as usize)
+        });
+        let host_fn = host_fns.iter().next().unwrap();
+        let param_types: Vec<_> = host_fn
+            .proto
+            .params
+            .iter()
+            .map(|param| param.ty.as_type())
+            .collect();
+        assert_eq!(param_types, vec![Some(Type::String), Some(Type::Int32)]);
+        assert_eq!(host_fn.proto.return_type.as_type(), Some(Type::String));
+    }
+
+    #[test]
+    fn test_marshal_arguments_and_results() {
+        let mut host_fns = HostFns::new();
+        host_fns
+            .register("repeat", |text: String, times: i32| {
+                text.repeat(times as usize)
+            })
+            .register("sum", |values: Vec<i32>| values.iter().sum::<i32>())
+            .register("nothing", || {});
+
+        assert_eq!(
+            call(
+                &host_fns,
+                "repeat",
+                &[
+                    InterpreterValue::String("ab".to_string()),
+                    InterpreterValue::Integer(3)
+                ]
+            ),
+            Ok(Some(InterpreterValue::String("ababab".to_string())))
+        );
+        assert_eq!(
+            call(
+                &host_fns,
+                "sum",
+                &[InterpreterValue::List(Shared::new(vec![
+                    InterpreterValue::Integer(1),
+                    InterpreterValue::Integer(2)
+                ]))]
+            ),
+            Ok(Some(InterpreterValue::Integer(3)))
+        );
+        assert_eq!(call(&host_fns, "nothing", &[]), Ok(None));
+        assert_eq!(
+            call(&host_fns, "repeat", &[InterpreterValue::Boolean(true)]),
+            Err(RuntimeErrorKind::InvalidHostArgument(
+                0,
+                "String".to_string(),
+                "Bool".to_string()
+            ))
+        );
+    }
+
+    #[test]
+    fn test_errors_and_replacement() {
+        let mut host_fns = HostFns::new();
+        host_fns
+            .register("check", |_: i32| -> Result<(), String> { Ok(()) })
+            .register("check", |value: i32| -> Result<i32, String> {
+                if value < 0 {
+                    Err(format!("{} is negative", value))
+                } else {
+                    Ok(value)
+                }
+            });
+
+        assert_eq!(host_fns.iter().count(), 1);
+        assert_eq!(
+            call(&host_fns, "check", &[InterpreterValue::Integer(-1)]),
+            Err(RuntimeErrorKind::Host("-1 is negative".to_string()))
+        );
+        assert_eq!(
+            call(&host_fns, "check", &[InterpreterValue::Integer(2)]),
+            Ok(Some(InterpreterValue::Integer(2)))
+        );
+    }
+}
@@ -1,101 +1,532 @@
-use std::collections::HashMap;
-use std::env::Vars;
+use std::collections::{BTreeMap, HashMap};
+use std::fmt::Display;
 
 use crate::ast::AstNodeRef;
+use crate::debugger::{is_statement, scope_variables, Debugger, Pause, Resume, StackFrame};
+use crate::heap::{Heap, Shared};
+use crate::io::Io;
+use crate::limits::{Budget, Limit, Limits};
 use crate::mxir::{
-    MxirCallExpr, MxirIf, MxirLoop, MxirNode, MxirNodeData, MxirNodeRef, MxirReturn, MxirVarDecl,
-    MxirVarExpr,
+    BinaryOp, MxirBinary, MxirCallExpr, MxirFieldAccess, MxirFnDecl, MxirIf, MxirIndex,
+    MxirIterate, MxirKeyAccess, MxirListLiteral, MxirLoop, MxirMapLiteral, MxirNode, MxirNodeData,
+    MxirNodeRef, MxirRange, MxirReturn, MxirStructLiteral, MxirUnary, MxirVarDecl, MxirVarExpr,
+    UnaryOp,
 };
-use crate::source_file::AnalyzedSourceFile;
+use crate::position::{Point, Range};
+use crate::source_file::{AnalyzedSourceFile, SourceFile};
 
+// Calls recurse on the stack of the host. When less than this much of it is left, the callee runs
+// on a new stack segment of the size below instead, so no call depth overflows the host's stack.
+const STACK_RED_ZONE: usize = 256 << 10;
+const STACK_SEGMENT_SIZE: usize = 4 << 20;
+
+/// A value of a running program. Lists, maps and structs live on the heap, so copying one of them
+/// copies a reference to it, and a change made through one variable is seen through all of them.
 #[derive(Debug, Clone, PartialEq)]
 pub enum InterpreterValue {
     Integer(i128),
     Float(f64),
     Boolean(bool),
     String(String),
+    List(Shared<Vec<InterpreterValue>>),
+    Map(Shared<BTreeMap<MapKey, InterpreterValue>>),
+    Range(RangeValue),
+    Struct(Shared<StructValue>),
+}
+
+/// A half-open range of integers. A missing start counts from zero, and a missing end is
+/// unbounded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RangeValue {
+    pub start: Option<i128>,
+    pub end: Option<i128>,
+}
+
+impl RangeValue {
+    /// Resolves the range against a sequence of length `len`, where a missing end means the end
+    /// of the sequence.
+    pub fn resolve(&self, len: usize) -> Result<std::ops::Range<usize>, RuntimeErrorKind> {
+        let start = self.start.unwrap_or(0);
+        let end = self.end.unwrap_or(len as i128);
+        match (usize::try_from(start), usize::try_from(end)) {
+            (Ok(start), Ok(end)) if start <= end && end <= len => Ok(start..end),
+            _ => Err(RuntimeErrorKind::SliceOutOfBounds(self.to_string(), len)),
+        }
+    }
+}
+
+impl Display for RangeValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.start {
+            Some(start) => write!(f, "{} to ", start)?,
+            None => write!(f, "_ to ")?,
+        }
+        match self.end {
+            Some(end) => write!(f, "{}", end),
+            None => write!(f, "_"),
+        }
+    }
+}
+
+/// A value that can be used as a map key. Only the hashable types are representable, which keeps
+/// floats and aggregates out of key positions.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub enum MapKey {
+    Integer(i128),
+    Boolean(bool),
+    String(String),
+}
+
+impl MapKey {
+    pub fn from_value(value: InterpreterValue) -> Result<Self, InterpreterValue> {
+        match value {
+            InterpreterValue::Integer(value) => Ok(MapKey::Integer(value)),
+            InterpreterValue::Boolean(value) => Ok(MapKey::Boolean(value)),
+            InterpreterValue::String(value) => Ok(MapKey::String(value)),
+            value => Err(value),
+        }
+    }
+}
+
+impl Display for MapKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            MapKey::Integer(value) => write!(f, "{}", value),
+            MapKey::Boolean(value) => write!(f, "{}", value),
+            MapKey::String(value) => write!(f, "{:?}", value),
+        }
+    }
+}
+
+impl InterpreterValue {
+    pub fn type_name(&self) -> String {
+        match self {
+            InterpreterValue::Integer(_) => "Int32".to_string(),
+            InterpreterValue::Float(_) => "Float".to_string(),
+            InterpreterValue::Boolean(_) => "Bool".to_string(),
+            InterpreterValue::String(_) => "String".to_string(),
+            InterpreterValue::List(_) => "List".to_string(),
+            InterpreterValue::Map(_) => "Map".to_string(),
+            InterpreterValue::Range(_) => "Range".to_string(),
+            InterpreterValue::Struct(struct_value) => struct_value.borrow().name.clone(),
+        }
+    }
+
+    /// Formats the value as it appears nested inside another value, where strings are quoted.
+    fn fmt_nested(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpreterValue::String(value) => write!(f, "{:?}", value),
+            value => write!(f, "{}", value),
+        }
+    }
+}
+
+impl Display for InterpreterValue {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            InterpreterValue::Integer(value) => write!(f, "{}", value),
+            // Debug keeps the fractional part of whole numbers, so 2.0 is not printed as 2
+            InterpreterValue::Float(value) => write!(f, "{:?}", value),
+            InterpreterValue::Boolean(value) => write!(f, "{}", value),
+            InterpreterValue::String(value) => write!(f, "{}", value),
+            // A value that contains itself is elided where it appears again
+            InterpreterValue::List(list) => {
+                let result = list.visit(|elements| {
+                    write!(f, "[")?;
+                    for (i, element) in elements.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        element.fmt_nested(f)?;
+                    }
+                    write!(f, "]")
+                });
+                result.unwrap_or_else(|| write!(f, "[...]"))
+            }
+            InterpreterValue::Map(map) => {
+                let result = map.visit(|entries| {
+                    write!(f, "map{{")?;
+                    for (i, (key, value)) in entries.iter().enumerate() {
+                        if i > 0 {
+                            write!(f, ", ")?;
+                        }
+                        write!(f, "{}: ", key)?;
+                        value.fmt_nested(f)?;
+                    }
+                    write!(f, "}}")
+                });
+                result.unwrap_or_else(|| write!(f, "map{{...}}"))
+            }
+            InterpreterValue::Range(range) => write!(f, "{}", range),
+            InterpreterValue::Struct(shared) => {
+                let result = shared.visit(|struct_value| {
+                    write!(f, "{} {{", struct_value.name)?;
+                    for (i, (name, value)) in struct_value.fields.iter().enumerate() {
+                        write!(f, "{} {}: ", if i > 0 { "," } else { "" }, name)?;
+                        value.fmt_nested(f)?;
+                    }
+                    write!(f, " }}")
+                });
+                result.unwrap_or_else(|| write!(f, "{} {{ ... }}", shared.borrow().name))
+            }
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct StructValue {
+    pub name: String,
+    pub fields: Vec<(String, InterpreterValue)>,
+}
+
+impl StructValue {
+    pub fn field(&self, name: &str) -> Option<&InterpreterValue> {
+        self.fields
+            .iter()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value)
+    }
+
+    pub fn field_mut(&mut self, name: &str) -> Option<&mut InterpreterValue> {
+        self.fields
+            .iter_mut()
+            .find(|(field_name, _)| field_name == name)
+            .map(|(_, value)| value)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct RuntimeError {
+    pub range: Range,
+    pub kind: Box<RuntimeErrorKind>,
+    /// The names of the functions that were being called when the error occurred, innermost first.
+    pub call_stack: Vec<String>,
+}
+
+impl RuntimeError {
+    /// Formats the error together with the line of `file` it occurred on and the call stack.
+    pub fn render(&self, file: &SourceFile) -> String {
+        let line_number = self.range.start.row + 1;
+        let gutter = " ".repeat(line_number.to_string().len());
+        let mut out = format!(
+            "error: {}\n{}--> {}:{}:{}\n",
+            self.kind.message(),
+            gutter,
+            file.path,
+            line_number,
+            self.range.start.col + 1
+        );
+
+        if let Some(line) = file.src.lines().nth(self.range.start.row) {
+            // Columns are byte offsets, while the underline is measured in characters
+            let start = self.range.start.col.min(line.len());
+            let end = if self.range.end.row == self.range.start.row {
+                self.range.end.col.clamp(start, line.len())
+            } else {
+                line.len()
+            };
+            let indent = line.get(..start).map_or(start, |text| text.chars().count());
+            let width = line.get(start..end).map_or(0, |text| text.chars().count());
+            out.push_str(&format!("{} |\n", gutter));
+            out.push_str(&format!("{} | {}\n", line_number, line));
+            out.push_str(&format!(
+                "{} | {}{}\n",
+                gutter,
+                " ".repeat(indent),
+                "^".repeat(width.max(1))
+            ));
+        }
+
+        for (i, name) in self.call_stack.iter().enumerate() {
+            let note = if i == 0 { "in" } else { "called from" };
+            out.push_str(&format!("{} = {} {}\n", gutter, note, name));
+        }
+
+        out
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum RuntimeErrorKind {
+    DivisionByZero,
+    IntegerOverflow,
+    InvalidBinaryOperands(BinaryOp, String, String),
+    InvalidUnaryOperand(UnaryOp, String),
+    MissingValue,
+    NotAStruct(String),
+    UnknownField(String, String),
+    InvalidAssignmentTarget,
+    NotIndexable(String),
+    NonIntegerIndex(String),
+    IndexOutOfBounds(i128, usize),
+    UnhashableKey(String),
+    MissingKey(MapKey),
+    NonIntegerRangeBound(String),
+    SliceOutOfBounds(String, usize),
+    NotIterable(String),
+    NonBoolCondition(String),
+    Io(String),
+    UnboundVariable(String),
+    UninitializedVariable(String),
+    NotCallable,
+    LoopControlOutsideLoop,
+    MissingBody,
+    MissingNode(MxirNodeRef),
+    UnsupportedNode,
+    LimitExceeded(Limit),
+    Stopped,
+    InvalidHostArgument(usize, String, String),
+    Host(String),
+}
+
+impl RuntimeErrorKind {
+    pub fn message(&self) -> String {
+        match self {
+            RuntimeErrorKind::DivisionByZero => "Division by zero".to_string(),
+            RuntimeErrorKind::IntegerOverflow => "Integer overflow".to_string(),
+            RuntimeErrorKind::InvalidBinaryOperands(op, lhs, rhs) => format!(
+                "Operator '{}' cannot be applied to {} and {}",
+                op.token(),
+                lhs,
+                rhs
+            ),
+            RuntimeErrorKind::InvalidUnaryOperand(op, operand) => {
+                format!("Operator '{}' cannot be applied to {}", op.token(), operand)
+            }
+            RuntimeErrorKind::MissingValue => "Expression did not produce a value".to_string(),
+            RuntimeErrorKind::NotAStruct(ty) => format!("Value of type {} is not a struct", ty),
+            RuntimeErrorKind::UnknownField(ty, field) => {
+                format!("Type {} has no field named {}", ty, field)
+            }
+            RuntimeErrorKind::InvalidAssignmentTarget => "Invalid assignment target".to_string(),
+            RuntimeErrorKind::NotIndexable(ty) => format!("Value of type {} cannot be indexed", ty),
+            RuntimeErrorKind::NonIntegerIndex(ty) => format!("Index must be Int32, found {}", ty),
+            RuntimeErrorKind::IndexOutOfBounds(index, len) => format!(
+                "Index {} is out of bounds for a list of length {}",
+                index, len
+            ),
+            RuntimeErrorKind::UnhashableKey(ty) => {
+                format!("Value of type {} cannot be used as a map key", ty)
+            }
+            RuntimeErrorKind::MissingKey(key) => format!("Key {} is not present in the map", key),
+            RuntimeErrorKind::NonIntegerRangeBound(ty) => {
+                format!("Range bounds must be Int32, found {}", ty)
+            }
+            RuntimeErrorKind::SliceOutOfBounds(range, len) => format!(
+                "Range {} is out of bounds for a sequence of length {}",
+                range, len
+            ),
+            RuntimeErrorKind::NotIterable(ty) => {
+                format!("Value of type {} cannot be iterated over", ty)
+            }
+            RuntimeErrorKind::NonBoolCondition(ty) => {
+                format!("Condition must be Bool, found {}", ty)
+            }
+            RuntimeErrorKind::Io(error) => format!("I/O error: {}", error),
+            RuntimeErrorKind::UnboundVariable(name) => format!("Variable {} is not bound", name),
+            RuntimeErrorKind::UninitializedVariable(name) => {
+                format!("Variable {} is read before it is assigned", name)
+            }
+            RuntimeErrorKind::NotCallable => "Callee is not a function".to_string(),
+            RuntimeErrorKind::LoopControlOutsideLoop => {
+                "Break or continue used outside of a loop".to_string()
+            }
+            RuntimeErrorKind::MissingBody => "Node does not have a body".to_string(),
+            RuntimeErrorKind::MissingNode(node_ref) => {
+                format!("Node {} does not exist", node_ref.0)
+            }
+            RuntimeErrorKind::UnsupportedNode => {
+                "Node cannot be evaluated by the interpreter".to_string()
+            }
+            RuntimeErrorKind::LimitExceeded(limit) => format!("Exceeded the {}", limit),
+            RuntimeErrorKind::Stopped => "Program was stopped by the debugger".to_string(),
+            RuntimeErrorKind::InvalidHostArgument(index, expected, found) => format!(
+                "Argument {} of the host function must be {}, found {}",
+                index + 1,
+                expected,
+                found
+            ),
+            RuntimeErrorKind::Host(message) => format!("Host function failed: {}", message),
+        }
+    }
 }
 
+/// The variables of a function call, or of the global environment. Every block opens a scope of
+/// its own, and a variable declared without a value is bound to `None` until it is assigned.
 #[derive(Debug, Clone)]
 pub struct Frame {
-    pub members: HashMap<String, InterpreterValue>,
+    pub fn_decl_ref: Option<MxirNodeRef>,
+    // The frame of the function this one is lexically nested in, or of the global environment
+    pub parent: Option<usize>,
+    // The number of scopes of the parent frame the function sees, which are those open at its
+    // declaration
+    pub parent_scopes: usize,
+    pub scopes: Vec<HashMap<String, Option<InterpreterValue>>>,
+    // The statement the frame is executing, which is only tracked while debugging
+    pub statement: Option<MxirNodeRef>,
 }
 
 impl Frame {
-    pub fn new() -> Self {
+    pub fn new(
+        fn_decl_ref: Option<MxirNodeRef>,
+        parent: Option<usize>,
+        parent_scopes: usize,
+    ) -> Self {
         Self {
-            members: HashMap::new(),
+            fn_decl_ref,
+            parent,
+            parent_scopes,
+            scopes: vec![HashMap::new()],
+            statement: None,
         }
     }
 }
 
-impl Default for Frame {
-    fn default() -> Self {
-        Self::new()
-    }
-}
-
 #[derive(Debug, Clone, PartialEq)]
 pub enum ControlFlow {
     Continue,                         // Normal execution
     Break,                            // Break out of a loop
+    ContinueLoop,                     // Skip to the next iteration of a loop
     Return(Option<InterpreterValue>), // Return from a function with optional value
+    Error(RuntimeError),              // Unwind with a runtime error
 }
 
-#[derive(Debug, Clone)]
+#[derive(Debug)]
 pub struct Interpreter<'a> {
     file: &'a AnalyzedSourceFile,
-    symbols: HashMap<String, MxirNodeRef>,
+    // The global environment is always the first frame
     frames: Vec<Frame>,
+    // The number of scopes open in the enclosing frame at the declaration of each function
+    declaring_scopes: HashMap<AstNodeRef, usize>,
+    limits: Limits,
+    budget: Budget,
+    io: Io,
+    heap: Heap,
+    debugger: Option<Debugger>,
 }
 
 impl<'a> Interpreter<'a> {
     pub fn new(file: &'a AnalyzedSourceFile) -> Self {
         Self {
             file,
-            symbols: HashMap::new(),
-            frames: vec![],
+            frames: vec![Frame::new(None, None, 0)],
+            declaring_scopes: HashMap::new(),
+            limits: Limits::default(),
+            budget: Budget::start(Limits::default()),
+            io: Io::std(),
+            heap: Heap::new(),
+            debugger: None,
         }
     }
 
-    pub fn execute(&mut self) {
-        self.register_fn_decls();
-        if let Some(main_node_ref) = self.symbols.get("main").cloned() {
-            // Append a call expr to the mxir nodes
-            let call_main = MxirNode {
-                ast_node: AstNodeRef(0),
-                self_ref: MxirNodeRef(0),
-                data: MxirNodeData::CallExpr(MxirCallExpr {
-                    fn_decl_ref: main_node_ref,
-                    args: vec![],
-                }),
-            };
-            let exit_value = self.eval_node(call_main);
-            println!("Exited with: {:#?}", exit_value);
+    /// Runs the program with `io` instead of the standard streams of the process.
+    pub fn with_io(mut self, io: Io) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Bounds the resources the program may use when it is executed.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// Allocates the values of the program on `heap`, e.g. one that holds the values of an earlier
+    /// run whose globals are restored with [`with_globals`](Self::with_globals).
+    pub fn with_heap(mut self, heap: Heap) -> Self {
+        self.heap = heap;
+        self
+    }
+
+    /// Pauses the program at the breakpoints and steps of `debugger`.
+    pub fn with_debugger(mut self, debugger: Debugger) -> Self {
+        self.debugger = Some(debugger);
+        self
+    }
+
+    /// Runs the program and returns the value returned by its entry point.
+    pub fn execute(&mut self) -> Result<Option<InterpreterValue>, RuntimeError> {
+        self.budget = Budget::start(self.limits);
+        // The source file declares the globals and ends with the call to the entry point
+        match self.eval_source_file(MxirNodeRef(0)) {
+            (_, ControlFlow::Error(error)) => Err(error),
+            (exit_value, _) => Ok(exit_value),
         }
     }
 
-    fn register_fn_decls(&mut self) {
-        for mxir_node_ref_index in 0..self.file.mxir().0.len() {
-            let mxir_node_ref = MxirNodeRef(mxir_node_ref_index as u32);
-            let mxir_node = self.node(mxir_node_ref);
-            if let MxirNodeData::FnDecl(fn_decl) = &mxir_node.data {
-                self.symbols.insert(fn_decl.name.clone(), mxir_node_ref);
+    /// Finds a top-level function by name, so that it can be [called](Self::call).
+    pub fn fn_decl(&self, name: &str) -> Option<MxirNodeRef> {
+        self.file.mxir().0.iter().find_map(|node| match &node.data {
+            MxirNodeData::FnDecl(fn_decl)
+                if fn_decl.name == name && fn_decl.enclosing.is_none() =>
+            {
+                Some(node.self_ref)
             }
-        }
+            _ => None,
+        })
     }
 
-    fn eval_node_ref(&mut self, node_ref: MxirNodeRef) -> Option<InterpreterValue> {
-        let root_node = self.node(node_ref);
-        match self.eval_node_with_control_flow(root_node) {
-            (Some(value), _) => Some(value),
-            (None, _) => None,
+    /// Calls a top-level function with the given arguments and returns its result. The globals are
+    /// not initialized first, so the program should have been [executed](Self::execute), or its
+    /// globals restored with [`with_globals`](Self::with_globals).
+    pub fn call(
+        &mut self,
+        fn_decl_ref: MxirNodeRef,
+        args: Vec<InterpreterValue>,
+    ) -> Result<Option<InterpreterValue>, RuntimeError> {
+        self.budget = Budget::start(self.limits);
+        let result = match self.node(fn_decl_ref).map(|node| node.data) {
+            Some(MxirNodeData::FnDecl(fn_decl)) => {
+                self.call_fn(fn_decl_ref, fn_decl_ref, &fn_decl, args)
+            }
+            _ => self.error(fn_decl_ref, RuntimeErrorKind::NotCallable),
+        };
+        match result {
+            (_, ControlFlow::Error(error)) => Err(error),
+            (value, _) => Ok(value),
         }
     }
 
-    fn eval_node(&mut self, node: MxirNode) -> Option<InterpreterValue> {
-        let (value, _) = self.eval_node_with_control_flow(node);
-        value
+    /// The global variables, which are `None` until they are assigned.
+    pub fn globals(&self) -> &HashMap<String, Option<InterpreterValue>> {
+        &self.frames[0].scopes[0]
+    }
+
+    /// Replaces the global variables, e.g. with those of an earlier run of the same program.
+    pub fn with_globals(mut self, globals: HashMap<String, Option<InterpreterValue>>) -> Self {
+        self.frames[0].scopes[0] = globals;
+        self
+    }
+
+    /// The heap the values of the program are allocated on, e.g. to check how much of it is in use.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn heap_mut(&mut self) -> &mut Heap {
+        &mut self.heap
+    }
+
+    /// Hands back the streams of the program.
+    pub fn into_io(self) -> Io {
+        self.io
+    }
+
+    fn eval(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
+        if let Err(limit) = self.budget.step() {
+            return self.error(node_ref, RuntimeErrorKind::LimitExceeded(limit));
+        }
+        match self.node(node_ref) {
+            Some(node) => {
+                if self.debugger.is_some() && is_statement(&node.data) {
+                    if let Err(kind) = self.debug_statement(node_ref) {
+                        return self.error(node_ref, kind);
+                    }
+                }
+                self.eval_node_with_control_flow(node)
+            }
+            None => self.error(node_ref, RuntimeErrorKind::MissingNode(node_ref)),
+        }
     }
 
     fn eval_node_with_control_flow(
@@ -104,29 +535,54 @@ impl<'a> Interpreter<'a> {
     ) -> (Option<InterpreterValue>, ControlFlow) {
         match node.data {
             MxirNodeData::SourceFile(_) => self.eval_source_file(node.self_ref),
-            MxirNodeData::CallExpr(call_expr) => self.eval_call_expr(call_expr),
+            MxirNodeData::CallExpr(call_expr) => self.eval_call_expr(node.self_ref, call_expr),
             MxirNodeData::Return(ret) => self.eval_return(ret),
             MxirNodeData::ExprStmt(expr_stmt) => self.eval_expr_stmt(expr_stmt),
             MxirNodeData::IntLiteral(int_literal) => (
                 Some(InterpreterValue::Integer(int_literal.value)),
                 ControlFlow::Continue,
             ),
+            MxirNodeData::FloatLiteral(float_literal) => (
+                Some(InterpreterValue::Float(float_literal.value)),
+                ControlFlow::Continue,
+            ),
             MxirNodeData::Loop(loop_stmt) => self.eval_loop(loop_stmt),
             MxirNodeData::Block(_) => self.eval_block(node.self_ref),
-            MxirNodeData::If(if_stmt) => self.eval_if(if_stmt),
+            MxirNodeData::If(if_stmt) => self.eval_if(node.self_ref, if_stmt),
             MxirNodeData::Break => (None, ControlFlow::Break),
-            MxirNodeData::Continue => (None, ControlFlow::Continue),
-            MxirNodeData::Nop(_) => (None, ControlFlow::Continue),
+            MxirNodeData::Continue => (None, ControlFlow::ContinueLoop),
+            MxirNodeData::Nop(msg) => {
+                if msg == "fn_decl" {
+                    let open_scopes = self.frames.last().map_or(0, |frame| frame.scopes.len());
+                    self.declaring_scopes.insert(node.ast_node, open_scopes);
+                }
+                (None, ControlFlow::Continue)
+            }
             MxirNodeData::BoolLiteral(bool_literal) => (
                 Some(InterpreterValue::Boolean(bool_literal.value)),
                 ControlFlow::Continue,
             ),
             MxirNodeData::VarDecl(var_decl) => self.eval_var_decl(var_decl),
-            MxirNodeData::VarExpr(var_expr) => self.eval_var_expr(var_expr),
-            MxirNodeData::Assign(lhs, rhs) => self.eval_assign(node.self_ref),
-            _ => {
-                todo!("Unhandled node in interpreter: {:#?}", node.data);
+            MxirNodeData::VarExpr(var_expr) => self.eval_var_expr(node.self_ref, var_expr),
+            MxirNodeData::Assign(lhs, rhs) => self.eval_assign(lhs, rhs),
+            MxirNodeData::Binary(binary) => self.eval_binary(node.self_ref, binary),
+            MxirNodeData::Unary(unary) => self.eval_unary(node.self_ref, unary),
+            MxirNodeData::StructLiteral(struct_literal) => self.eval_struct_literal(struct_literal),
+            MxirNodeData::FieldAccess(field_access) => {
+                self.eval_field_access(node.self_ref, field_access)
             }
+            MxirNodeData::ListLiteral(list_literal) => self.eval_list_literal(list_literal),
+            MxirNodeData::Index(index) => self.eval_index(node.self_ref, index),
+            MxirNodeData::MapLiteral(map_literal) => self.eval_map_literal(map_literal),
+            MxirNodeData::KeyAccess(key_access) => self.eval_key_access(node.self_ref, key_access),
+            MxirNodeData::Range(range) => self.eval_range(range),
+            MxirNodeData::Iterate(iterate) => self.eval_iterate(iterate),
+            MxirNodeData::StringLiteral(string_literal) => (
+                Some(InterpreterValue::String(string_literal.value)),
+                ControlFlow::Continue,
+            ),
+            MxirNodeData::Stringify(value_ref) => self.eval_stringify(value_ref),
+            _ => self.error(node.self_ref, RuntimeErrorKind::UnsupportedNode),
         }
     }
 
@@ -141,40 +597,83 @@ impl<'a> Interpreter<'a> {
 
     fn eval_call_expr(
         &mut self,
+        node_ref: MxirNodeRef,
         call_expr: MxirCallExpr,
     ) -> (Option<InterpreterValue>, ControlFlow) {
-        self.push_frame();
-
-        let fn_decl_node = self.node(call_expr.fn_decl_ref);
-        let mut ret_val = None;
-        let mut control_flow = ControlFlow::Continue;
+        // Evaluate the arguments in the caller's frame before entering the callee
+        let mut args = Vec::with_capacity(call_expr.args.len());
+        for arg_ref in call_expr.args {
+            let (value, control_flow) = self.eval(arg_ref);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            let Some(value) = value else {
+                return self.error(arg_ref, RuntimeErrorKind::MissingValue);
+            };
+            args.push(value);
+        }
 
+        let Some(fn_decl_node) = self.node(call_expr.fn_decl_ref) else {
+            return self.error(
+                call_expr.fn_decl_ref,
+                RuntimeErrorKind::MissingNode(call_expr.fn_decl_ref),
+            );
+        };
         if let MxirNodeData::FnDecl(fn_decl) = &fn_decl_node.data {
-            let body_node = self.node(fn_decl.body);
-            let (result, flow) = self.eval_body(body_node.self_ref);
-            ret_val = result;
-            // For function calls, we want to propagate Return control flow out of the function,
-            // but leave it as Continue so the caller continues normally
-            if let ControlFlow::Return(return_value) = flow {
-                ret_val = return_value;
-                control_flow = ControlFlow::Continue;
-            } else {
-                control_flow = flow;
-            }
+            self.call_fn(node_ref, call_expr.fn_decl_ref, fn_decl, args)
         } else if let MxirNodeData::BuiltinFnDecl(builtin_fn_decl) = &fn_decl_node.data {
-            (builtin_fn_decl.fn_)();
-            ret_val = None;
-            control_flow = ControlFlow::Continue;
+            match builtin_fn_decl.fn_.call(&mut self.io, &args) {
+                Ok(value) => (value, ControlFlow::Continue),
+                Err(kind) => self.error(node_ref, kind),
+            }
+        } else {
+            self.error(node_ref, RuntimeErrorKind::NotCallable)
         }
+    }
 
-        self.pop_frame();
+    /// Calls a function with evaluated arguments. Errors that are not raised by the body, like
+    /// exceeding the call depth, are located at `call_ref`.
+    fn call_fn(
+        &mut self,
+        call_ref: MxirNodeRef,
+        fn_decl_ref: MxirNodeRef,
+        fn_decl: &MxirFnDecl,
+        args: Vec<InterpreterValue>,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        // Every frame but the global environment is an active call
+        if let Err(limit) = self.budget.enter_call(self.frames.len()) {
+            return self.error(call_ref, RuntimeErrorKind::LimitExceeded(limit));
+        }
+        self.push_frame(fn_decl_ref, fn_decl.enclosing);
+
+        // Bind the arguments to the parameters of the callee
+        for (param, value) in fn_decl.params.iter().zip(args) {
+            self.declare_var(param.name.clone(), Some(value));
+        }
 
-        (ret_val, control_flow)
+        let (result, flow) = stacker::maybe_grow(STACK_RED_ZONE, STACK_SEGMENT_SIZE, || {
+            self.eval_block(fn_decl.body)
+        });
+        self.pop_frame();
+        // For function calls, we want to propagate Return control flow out of the function,
+        // but leave it as Continue so the caller continues normally
+        match flow {
+            ControlFlow::Return(return_value) => (return_value, ControlFlow::Continue),
+            // Loop control never crosses a function boundary
+            ControlFlow::Break | ControlFlow::ContinueLoop => {
+                self.error(call_ref, RuntimeErrorKind::LoopControlOutsideLoop)
+            }
+            flow => (result, flow),
+        }
     }
 
     fn eval_return(&mut self, node: MxirReturn) -> (Option<InterpreterValue>, ControlFlow) {
         let value = if let Some(expr_ref) = node.0 {
-            self.eval_node_ref(expr_ref)
+            let (value, control_flow) = self.eval(expr_ref);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            value
         } else {
             None
         };
@@ -186,33 +685,33 @@ impl<'a> Interpreter<'a> {
         &mut self,
         expr_stmt: MxirNodeRef,
     ) -> (Option<InterpreterValue>, ControlFlow) {
-        let (_, control_flow) = self.eval_node_with_control_flow(self.node(expr_stmt));
+        let (_, control_flow) = self.eval(expr_stmt);
         (None, control_flow)
     }
 
-    fn eval_if(&mut self, if_stmt: MxirIf) -> (Option<InterpreterValue>, ControlFlow) {
+    fn eval_if(
+        &mut self,
+        node_ref: MxirNodeRef,
+        if_stmt: MxirIf,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
         // Evaluate the condition
-        let (condition_value, condition_flow) =
-            self.eval_node_with_control_flow(self.node(if_stmt.condition));
+        let (condition_value, condition_flow) = self.eval(if_stmt.condition);
 
         // If condition evaluation produced a control flow change, propagate it
         if condition_flow != ControlFlow::Continue {
             return (None, condition_flow);
         }
 
-        // Check the condition value to determine which branch to execute
-        let condition_bool = match condition_value {
-            Some(InterpreterValue::Boolean(value)) => value,
-            Some(InterpreterValue::Integer(value)) => value != 0,
-            Some(_) => false, // Treat other types as falsy
-            None => false,    // No value is treated as falsy
+        let condition = match condition_holds(condition_value) {
+            Ok(condition) => condition,
+            Err(kind) => return self.error(node_ref, kind),
         };
 
         // Execute the appropriate branch
-        if condition_bool {
-            self.eval_node_with_control_flow(self.node(if_stmt.then_branch))
+        if condition {
+            self.eval(if_stmt.then_branch)
         } else if let Some(else_branch) = if_stmt.else_branch {
-            self.eval_node_with_control_flow(self.node(else_branch))
+            self.eval(else_branch)
         } else {
             // If no else branch and condition is false, continue with no value
             (None, ControlFlow::Continue)
@@ -222,127 +721,1600 @@ impl<'a> Interpreter<'a> {
     fn eval_var_decl(&mut self, var_decl: MxirVarDecl) -> (Option<InterpreterValue>, ControlFlow) {
         let var_name = var_decl.name;
         let var_value = if let Some(value_ref) = var_decl.value {
-            self.eval_node_with_control_flow(self.node(value_ref)).0
+            let (value, control_flow) = self.eval(value_ref);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            value
         } else {
             None
         };
 
-        if let Some(frame) = self.frames.last_mut() {
-            if let Some(value) = var_value {
-                eprintln!("Variable '{}' initialized with value {:?}", var_name, value);
-                frame.members.insert(var_name, value);
-            }
+        if let Some(value) = &var_value {
+            self.io.trace(format_args!(
+                "Variable '{}' initialized with value {:?}",
+                var_name, value
+            ));
         }
+        self.declare_var(var_name, var_value);
 
         (None, ControlFlow::Continue)
     }
 
-    fn eval_var_expr(&mut self, var_expr: MxirVarExpr) -> (Option<InterpreterValue>, ControlFlow) {
-        if let Some(frame) = self.frames.last() {
-            if let Some(value) = frame.members.get(&var_expr.name) {
-                (Some(value.clone()), ControlFlow::Continue)
-            } else {
-                (None, ControlFlow::Continue)
-            }
-        } else {
-            (None, ControlFlow::Continue)
+    fn eval_var_expr(
+        &mut self,
+        node_ref: MxirNodeRef,
+        var_expr: MxirVarExpr,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        match self.lookup_var(&var_expr.name) {
+            Some(Some(value)) => (Some(value.clone()), ControlFlow::Continue),
+            Some(None) => self.error(
+                node_ref,
+                RuntimeErrorKind::UninitializedVariable(var_expr.name),
+            ),
+            None => self.error(node_ref, RuntimeErrorKind::UnboundVariable(var_expr.name)),
         }
     }
 
-    fn eval_assign(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
-        let node = self.node(node_ref);
-        let MxirNodeData::Assign(lhs, rhs) = node.data else {
-            panic!("Expected Assign node");
-        };
-
-        let lhs_node = self.node(lhs);
-        let rhs_node = self.node(rhs);
-
+    fn eval_assign(
+        &mut self,
+        lhs: MxirNodeRef,
+        rhs: MxirNodeRef,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
         // First evaluate the right-hand side
-        let (rhs_value, control_flow) = self.eval_node_with_control_flow(rhs_node);
+        let (rhs_value, control_flow) = self.eval(rhs);
 
         // If control flow is not Continue, return early
         if control_flow != ControlFlow::Continue {
             return (None, control_flow);
         }
 
+        let Some(rhs_value) = rhs_value else {
+            return self.error(rhs, RuntimeErrorKind::MissingValue);
+        };
+
         // Now handle the assignment with the evaluated value
-        if let Some(frame) = self.frames.last_mut() {
-            // Extract variable name from the VarExpr
-            if let MxirNodeData::VarExpr(var_expr) = &lhs_node.data {
-                if let Some(value) = frame.members.get_mut(&var_expr.name) {
-                    if let Some(rhs_val) = rhs_value {
-                        *value = rhs_val;
-                        return (Some(value.clone()), ControlFlow::Continue);
-                    }
+        self.assign_to(lhs, rhs_value)
+    }
+
+    /// Stores a value into the place denoted by `target`. Assigning to a field, element or key
+    /// changes its container in place, so the change is seen through every reference to it.
+    fn assign_to(
+        &mut self,
+        target_ref: MxirNodeRef,
+        value: InterpreterValue,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let Some(target) = self.node(target_ref) else {
+            return self.error(target_ref, RuntimeErrorKind::MissingNode(target_ref));
+        };
+        match target.data {
+            MxirNodeData::VarExpr(var_expr) => match self.lookup_var_mut(&var_expr.name) {
+                Some(slot) => {
+                    *slot = Some(value.clone());
+                    (Some(value), ControlFlow::Continue)
                 }
+                None => self.error(
+                    target.self_ref,
+                    RuntimeErrorKind::UnboundVariable(var_expr.name),
+                ),
+            },
+            MxirNodeData::FieldAccess(field_access) => {
+                let (object_value, control_flow) = self.eval(field_access.object);
+                if control_flow != ControlFlow::Continue {
+                    return (None, control_flow);
+                }
+
+                let shared = match object_value {
+                    Some(InterpreterValue::Struct(shared)) => shared,
+                    Some(other) => {
+                        return self.error(
+                            field_access.object,
+                            RuntimeErrorKind::NotAStruct(other.type_name()),
+                        )
+                    }
+                    None => return self.error(field_access.object, RuntimeErrorKind::MissingValue),
+                };
+
+                let mut struct_value = shared.borrow_mut();
+                let Some(slot) = struct_value.field_mut(&field_access.field) else {
+                    let name = struct_value.name.clone();
+                    return self.error(
+                        target.self_ref,
+                        RuntimeErrorKind::UnknownField(name, field_access.field),
+                    );
+                };
+                *slot = value.clone();
+
+                (Some(value), ControlFlow::Continue)
             }
-        }
+            MxirNodeData::Index(index) => {
+                let (object_value, control_flow) = self.eval(index.object);
+                if control_flow != ControlFlow::Continue {
+                    return (None, control_flow);
+                }
+                let (index_value, control_flow) = self.eval(index.index);
+                if control_flow != ControlFlow::Continue {
+                    return (None, control_flow);
+                }
 
-        (None, ControlFlow::Continue)
-    }
+                let list = match object_value {
+                    Some(InterpreterValue::List(list)) => list,
+                    Some(other) => {
+                        return self.error(
+                            index.object,
+                            RuntimeErrorKind::NotIndexable(other.type_name()),
+                        )
+                    }
+                    None => return self.error(index.object, RuntimeErrorKind::MissingValue),
+                };
 
-    fn eval_loop(&mut self, loop_stmt: MxirLoop) -> (Option<InterpreterValue>, ControlFlow) {
-        if let Some(body_ref) = loop_stmt.0 {
-            loop {
-                let (_, control_flow) = self.eval_node_with_control_flow(self.node(body_ref));
-                match control_flow {
-                    ControlFlow::Break => break,
-                    ControlFlow::Return(value) => return (None, ControlFlow::Return(value)),
-                    ControlFlow::Continue => {} // Continue looping
+                let mut elements = list.borrow_mut();
+                let position = match list_position(index_value, elements.len()) {
+                    Ok(position) => position,
+                    Err(kind) => return self.error(index.index, kind),
+                };
+                elements[position] = value.clone();
+
+                (Some(value), ControlFlow::Continue)
+            }
+            // Assigning to a missing key inserts it into the map
+            MxirNodeData::KeyAccess(key_access) => {
+                let (map_value, control_flow) = self.eval(key_access.map);
+                if control_flow != ControlFlow::Continue {
+                    return (None, control_flow);
+                }
+                let (key, control_flow) = self.eval_map_key(key_access.key);
+                if control_flow != ControlFlow::Continue {
+                    return (None, control_flow);
+                }
+                let Some(key) = key else {
+                    return (None, control_flow);
+                };
+
+                match map_value {
+                    Some(InterpreterValue::Map(map)) => {
+                        map.borrow_mut().insert(key, value.clone());
+                        (Some(value), ControlFlow::Continue)
+                    }
+                    Some(other) => self.error(
+                        key_access.map,
+                        RuntimeErrorKind::NotIndexable(other.type_name()),
+                    ),
+                    None => self.error(key_access.map, RuntimeErrorKind::MissingValue),
                 }
             }
-            (None, ControlFlow::Continue)
-        } else {
-            // Empty loop body
-            (None, ControlFlow::Continue)
+            _ => self.error(target.self_ref, RuntimeErrorKind::InvalidAssignmentTarget),
         }
     }
 
-    fn eval_block(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
-        self.eval_body(node_ref)
-    }
+    fn eval_map_literal(
+        &mut self,
+        map_literal: MxirMapLiteral,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let mut entries = BTreeMap::new();
+        for entry in map_literal.0 {
+            let (key, control_flow) = self.eval_map_key(entry.key);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            let (value, control_flow) = self.eval(entry.value);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            let (Some(key), Some(value)) = (key, value) else {
+                return self.error(entry.value, RuntimeErrorKind::MissingValue);
+            };
+            entries.insert(key, value);
+        }
 
-    fn eval_body(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
-        let node = self.node(node_ref);
+        (
+            Some(InterpreterValue::Map(self.heap.alloc(entries))),
+            ControlFlow::Continue,
+        )
+    }
 
-        let children = match &node.data {
-            MxirNodeData::SourceFile(source_file) => &source_file.0,
-            MxirNodeData::Block(block) => &block.0,
-            _ => {
-                panic!("Node does not have a body");
-            }
+    fn eval_key_access(
+        &mut self,
+        node_ref: MxirNodeRef,
+        key_access: MxirKeyAccess,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (map_value, control_flow) = self.eval(key_access.map);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+        let (key, control_flow) = self.eval_map_key(key_access.key);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+        let Some(key) = key else {
+            return (None, control_flow);
         };
 
-        let mut result = None;
-        for child_ref in children {
-            let (value, control_flow) = self.eval_node_with_control_flow(self.node(*child_ref));
-            result = value;
+        match map_value {
+            Some(InterpreterValue::Map(map)) => match map.borrow().get(&key).cloned() {
+                Some(value) => (Some(value), ControlFlow::Continue),
+                None => self.error(node_ref, RuntimeErrorKind::MissingKey(key)),
+            },
+            Some(other) => self.error(node_ref, RuntimeErrorKind::NotIndexable(other.type_name())),
+            None => self.error(key_access.map, RuntimeErrorKind::MissingValue),
+        }
+    }
 
-            // Check if we need to exit early due to control flow
-            match control_flow {
-                ControlFlow::Continue => continue,
-                ControlFlow::Break | ControlFlow::Return(_) => return (result, control_flow),
-            }
+    /// Evaluates an expression used as a map key.
+    fn eval_map_key(&mut self, key_ref: MxirNodeRef) -> (Option<MapKey>, ControlFlow) {
+        let (key_value, control_flow) = self.eval(key_ref);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
         }
 
-        (result, ControlFlow::Continue)
+        let error = match key_value.map(MapKey::from_value) {
+            Some(Ok(key)) => return (Some(key), ControlFlow::Continue),
+            Some(Err(other)) => RuntimeErrorKind::UnhashableKey(other.type_name()),
+            None => RuntimeErrorKind::MissingValue,
+        };
+        let (_, control_flow) = self.error(key_ref, error);
+        (None, control_flow)
     }
 
-    fn node(&self, node_ref: MxirNodeRef) -> MxirNode {
-        self.file
-            .mxir()
-            .0
-            .get(node_ref.0 as usize)
-            .cloned()
-            .unwrap()
-    }
+    fn eval_list_literal(
+        &mut self,
+        list_literal: MxirListLiteral,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let mut elements = Vec::with_capacity(list_literal.0.len());
+        for element_ref in list_literal.0 {
+            let (value, control_flow) = self.eval(element_ref);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            let Some(value) = value else {
+                return self.error(element_ref, RuntimeErrorKind::MissingValue);
+            };
+            elements.push(value);
+        }
 
-    fn push_frame(&mut self) {
-        self.frames.push(Frame::new());
+        (
+            Some(InterpreterValue::List(self.heap.alloc(elements))),
+            ControlFlow::Continue,
+        )
     }
 
-    fn pop_frame(&mut self) {
-        self.frames.pop();
+    fn eval_index(
+        &mut self,
+        node_ref: MxirNodeRef,
+        index: MxirIndex,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (object_value, control_flow) = self.eval(index.object);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+        let (index_value, control_flow) = self.eval(index.index);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        // Indexing with a range produces a slice of the list or string
+        if let Some(InterpreterValue::Range(range)) = index_value {
+            return match object_value {
+                Some(InterpreterValue::List(list)) => {
+                    let elements = list.borrow();
+                    match range.resolve(elements.len()) {
+                        Ok(range) => (
+                            Some(InterpreterValue::List(
+                                self.heap.alloc(elements[range].to_vec()),
+                            )),
+                            ControlFlow::Continue,
+                        ),
+                        Err(kind) => self.error(index.index, kind),
+                    }
+                }
+                Some(InterpreterValue::String(string)) => {
+                    let chars: Vec<char> = string.chars().collect();
+                    match range.resolve(chars.len()) {
+                        Ok(range) => (
+                            Some(InterpreterValue::String(chars[range].iter().collect())),
+                            ControlFlow::Continue,
+                        ),
+                        Err(kind) => self.error(index.index, kind),
+                    }
+                }
+                Some(other) => {
+                    self.error(node_ref, RuntimeErrorKind::NotIndexable(other.type_name()))
+                }
+                None => self.error(index.object, RuntimeErrorKind::MissingValue),
+            };
+        }
+
+        match object_value {
+            Some(InterpreterValue::List(list)) => {
+                let elements = list.borrow();
+                match list_position(index_value, elements.len()) {
+                    Ok(position) => (Some(elements[position].clone()), ControlFlow::Continue),
+                    Err(kind) => self.error(index.index, kind),
+                }
+            }
+            Some(other) => self.error(node_ref, RuntimeErrorKind::NotIndexable(other.type_name())),
+            None => self.error(index.object, RuntimeErrorKind::MissingValue),
+        }
+    }
+
+    fn eval_struct_literal(
+        &mut self,
+        struct_literal: MxirStructLiteral,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let mut fields = Vec::with_capacity(struct_literal.fields.len());
+        for field in struct_literal.fields {
+            let (value, control_flow) = self.eval(field.value);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            let Some(value) = value else {
+                return self.error(field.value, RuntimeErrorKind::MissingValue);
+            };
+            fields.push((field.name, value));
+        }
+
+        (
+            Some(InterpreterValue::Struct(self.heap.alloc(StructValue {
+                name: struct_literal.name,
+                fields,
+            }))),
+            ControlFlow::Continue,
+        )
+    }
+
+    fn eval_field_access(
+        &mut self,
+        node_ref: MxirNodeRef,
+        field_access: MxirFieldAccess,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (object_value, control_flow) = self.eval(field_access.object);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        match object_value {
+            Some(InterpreterValue::Struct(shared)) => {
+                let struct_value = shared.borrow();
+                match struct_value.field(&field_access.field) {
+                    Some(value) => (Some(value.clone()), ControlFlow::Continue),
+                    None => self.error(
+                        node_ref,
+                        RuntimeErrorKind::UnknownField(
+                            struct_value.name.clone(),
+                            field_access.field,
+                        ),
+                    ),
+                }
+            }
+            // Lists, maps and strings expose their length as a builtin property
+            Some(InterpreterValue::List(list)) if field_access.field == "len" => (
+                Some(InterpreterValue::Integer(list.borrow().len() as i128)),
+                ControlFlow::Continue,
+            ),
+            Some(InterpreterValue::Map(map)) if field_access.field == "len" => (
+                Some(InterpreterValue::Integer(map.borrow().len() as i128)),
+                ControlFlow::Continue,
+            ),
+            Some(InterpreterValue::String(string)) if field_access.field == "len" => (
+                Some(InterpreterValue::Integer(string.chars().count() as i128)),
+                ControlFlow::Continue,
+            ),
+            Some(other) => self.error(
+                field_access.object,
+                RuntimeErrorKind::NotAStruct(other.type_name()),
+            ),
+            None => self.error(field_access.object, RuntimeErrorKind::MissingValue),
+        }
+    }
+
+    fn eval_loop(&mut self, loop_stmt: MxirLoop) -> (Option<InterpreterValue>, ControlFlow) {
+        if let Some(body_ref) = loop_stmt.0 {
+            loop {
+                let (_, control_flow) = self.eval(body_ref);
+                match control_flow {
+                    ControlFlow::Break => break,
+                    ControlFlow::Continue | ControlFlow::ContinueLoop => {} // Continue looping
+                    control_flow => return (None, control_flow),
+                }
+            }
+            (None, ControlFlow::Continue)
+        } else {
+            // Empty loop body
+            (None, ControlFlow::Continue)
+        }
+    }
+
+    fn eval_iterate(&mut self, iterate: MxirIterate) -> (Option<InterpreterValue>, ControlFlow) {
+        let (iterable_value, control_flow) = self.eval(iterate.iterable);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        let values = match iterate_values(iterable_value) {
+            Ok(values) => values,
+            Err(kind) => return self.error(iterate.iterable, kind),
+        };
+
+        // The binding lives in a scope that encloses the body
+        self.push_scope();
+        let mut result = (None, ControlFlow::Continue);
+        for value in values {
+            self.declare_var(iterate.binding.clone(), Some(value));
+
+            let (_, control_flow) = self.eval(iterate.body);
+            match control_flow {
+                ControlFlow::Break => break,
+                ControlFlow::Continue | ControlFlow::ContinueLoop => {} // Continue looping
+                control_flow => {
+                    result = (None, control_flow);
+                    break;
+                }
+            }
+        }
+        self.pop_scope();
+
+        result
+    }
+
+    fn eval_stringify(
+        &mut self,
+        value_ref: MxirNodeRef,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (value, control_flow) = self.eval(value_ref);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        match value {
+            Some(value) => (
+                Some(InterpreterValue::String(value.to_string())),
+                ControlFlow::Continue,
+            ),
+            None => self.error(value_ref, RuntimeErrorKind::MissingValue),
+        }
+    }
+
+    fn eval_range(&mut self, range: MxirRange) -> (Option<InterpreterValue>, ControlFlow) {
+        let mut bounds = [None, None];
+        for (bound, bound_ref) in bounds.iter_mut().zip([range.start, range.end]) {
+            let Some(bound_ref) = bound_ref else {
+                continue;
+            };
+            let (value, control_flow) = self.eval(bound_ref);
+            if control_flow != ControlFlow::Continue {
+                return (None, control_flow);
+            }
+            match value {
+                Some(InterpreterValue::Integer(value)) => *bound = Some(value),
+                Some(other) => {
+                    return self.error(
+                        bound_ref,
+                        RuntimeErrorKind::NonIntegerRangeBound(other.type_name()),
+                    )
+                }
+                None => return self.error(bound_ref, RuntimeErrorKind::MissingValue),
+            }
+        }
+        let [start, end] = bounds;
+
+        (
+            Some(InterpreterValue::Range(RangeValue { start, end })),
+            ControlFlow::Continue,
+        )
+    }
+
+    fn eval_binary(
+        &mut self,
+        node_ref: MxirNodeRef,
+        binary: MxirBinary,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (lhs_value, control_flow) = self.eval(binary.lhs);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        // `and` and `or` only evaluate their right operand when it can change the result
+        match (binary.op, &lhs_value) {
+            (BinaryOp::And, Some(InterpreterValue::Boolean(false)))
+            | (BinaryOp::Or, Some(InterpreterValue::Boolean(true))) => {
+                return (lhs_value, ControlFlow::Continue);
+            }
+            _ => {}
+        }
+
+        let (rhs_value, control_flow) = self.eval(binary.rhs);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        let (Some(lhs), Some(rhs)) = (lhs_value, rhs_value) else {
+            return self.error(node_ref, RuntimeErrorKind::MissingValue);
+        };
+
+        match apply_binary_op(binary.op, lhs, rhs) {
+            Ok(value) => (Some(value), ControlFlow::Continue),
+            Err(kind) => self.error(node_ref, kind),
+        }
+    }
+
+    fn eval_unary(
+        &mut self,
+        node_ref: MxirNodeRef,
+        unary: MxirUnary,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let (operand_value, control_flow) = self.eval(unary.operand);
+        if control_flow != ControlFlow::Continue {
+            return (None, control_flow);
+        }
+
+        let Some(operand) = operand_value else {
+            return self.error(node_ref, RuntimeErrorKind::MissingValue);
+        };
+
+        match apply_unary_op(unary.op, operand) {
+            Ok(value) => (Some(value), ControlFlow::Continue),
+            Err(kind) => self.error(node_ref, kind),
+        }
+    }
+
+    fn eval_block(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
+        self.push_scope();
+        let result = self.eval_body(node_ref);
+        self.pop_scope();
+        result
+    }
+
+    fn eval_body(&mut self, node_ref: MxirNodeRef) -> (Option<InterpreterValue>, ControlFlow) {
+        let children = match self.node(node_ref).map(|node| node.data) {
+            Some(MxirNodeData::SourceFile(source_file)) => source_file.0,
+            Some(MxirNodeData::Block(block)) => block.0,
+            Some(_) => return self.error(node_ref, RuntimeErrorKind::MissingBody),
+            None => return self.error(node_ref, RuntimeErrorKind::MissingNode(node_ref)),
+        };
+
+        let mut result = None;
+        for child_ref in children {
+            let (value, control_flow) = self.eval(child_ref);
+            result = value;
+
+            // Check if we need to exit early due to control flow
+            match control_flow {
+                ControlFlow::Continue => continue,
+                ControlFlow::Break
+                | ControlFlow::ContinueLoop
+                | ControlFlow::Return(_)
+                | ControlFlow::Error(_) => return (result, control_flow),
+            }
+        }
+
+        (result, ControlFlow::Continue)
+    }
+
+    fn node(&self, node_ref: MxirNodeRef) -> Option<MxirNode> {
+        self.file.mxir().0.get(node_ref.0 as usize).cloned()
+    }
+
+    /// Unwinds with an error located at `node_ref`.
+    fn error(
+        &self,
+        node_ref: MxirNodeRef,
+        kind: RuntimeErrorKind,
+    ) -> (Option<InterpreterValue>, ControlFlow) {
+        let error = RuntimeError {
+            range: node_range(self.file, node_ref),
+            kind: Box::new(kind),
+            call_stack: self.call_stack(),
+        };
+        (None, ControlFlow::Error(error))
+    }
+
+    /// Gives the debugger the chance to pause before a statement runs.
+    fn debug_statement(&mut self, node_ref: MxirNodeRef) -> Result<(), RuntimeErrorKind> {
+        let depth = self.frames.len();
+        let line = node_range(self.file, node_ref).start.row + 1;
+        if let Some(frame) = self.frames.last_mut() {
+            frame.statement = Some(node_ref);
+        }
+
+        let Some(reason) = self
+            .debugger
+            .as_mut()
+            .and_then(|debugger| debugger.should_pause(depth, line, node_ref))
+        else {
+            return Ok(());
+        };
+
+        // The top level is only a frame of its own while the globals are initialized
+        let frames = self
+            .frames
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(index, _)| *index > 0 || depth == 1)
+            .map(|(_, frame)| StackFrame {
+                name: match frame
+                    .fn_decl_ref
+                    .and_then(|fn_decl_ref| self.node(fn_decl_ref))
+                {
+                    Some(MxirNode {
+                        data: MxirNodeData::FnDecl(fn_decl),
+                        ..
+                    }) => fn_decl.name,
+                    _ => "<top level>".to_string(),
+                },
+                line: frame.statement.map_or(0, |statement| {
+                    node_range(self.file, statement).start.row + 1
+                }),
+                locals: scope_variables(&frame.scopes),
+            })
+            .collect();
+        let pause = Pause {
+            reason,
+            frames,
+            globals: scope_variables(&self.frames[0].scopes),
+        };
+
+        // Time spent paused does not count against the timeout
+        let paused_at = std::time::Instant::now();
+        let resume = match &mut self.debugger {
+            Some(debugger) => debugger.pause(depth, node_ref, &pause),
+            None => Resume::Continue,
+        };
+        self.budget.extend_deadline(paused_at.elapsed());
+
+        match resume {
+            Resume::Stop => Err(RuntimeErrorKind::Stopped),
+            _ => Ok(()),
+        }
+    }
+
+    /// The names of the functions being called, innermost first.
+    fn call_stack(&self) -> Vec<String> {
+        self.frames
+            .iter()
+            .rev()
+            .filter_map(|frame| match self.node(frame.fn_decl_ref?)?.data {
+                MxirNodeData::FnDecl(fn_decl) => Some(fn_decl.name),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Enters a call of `fn_decl_ref`. Its parent is the latest call of the function it is nested
+    /// in, or the global environment. The callee only sees the scopes of its parent that were open
+    /// at its declaration.
+    fn push_frame(&mut self, fn_decl_ref: MxirNodeRef, enclosing: Option<MxirNodeRef>) {
+        let parent = enclosing
+            .and_then(|enclosing| {
+                self.frames
+                    .iter()
+                    .rposition(|frame| frame.fn_decl_ref == Some(enclosing))
+            })
+            .unwrap_or(0);
+        // Functions called before their declaration runs see every scope of the parent
+        let parent_scopes = self
+            .node(fn_decl_ref)
+            .and_then(|node| self.declaring_scopes.get(&node.ast_node).copied())
+            .unwrap_or(usize::MAX);
+        self.frames
+            .push(Frame::new(Some(fn_decl_ref), Some(parent), parent_scopes));
+    }
+
+    fn pop_frame(&mut self) {
+        self.frames.pop();
+    }
+
+    fn push_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.push(HashMap::new());
+        }
+    }
+
+    fn pop_scope(&mut self) {
+        if let Some(frame) = self.frames.last_mut() {
+            frame.scopes.pop();
+        }
+    }
+
+    fn declare_var(&mut self, name: String, value: Option<InterpreterValue>) {
+        if let Some(scope) = self
+            .frames
+            .last_mut()
+            .and_then(|frame| frame.scopes.last_mut())
+        {
+            scope.insert(name, value);
+        }
+    }
+
+    /// Finds the frame and scope a variable is bound in. The scopes of the current frame are
+    /// searched innermost first, followed by those of its lexically enclosing frames as far
+    /// as each nested function can see them.
+    fn resolve_var(&self, name: &str) -> Option<(usize, usize)> {
+        let mut frame_index = self.frames.len().checked_sub(1)?;
+        let mut visible = usize::MAX;
+        loop {
+            let frame = &self.frames[frame_index];
+            let scopes = &frame.scopes[..visible.min(frame.scopes.len())];
+            if let Some(scope_index) = scopes.iter().rposition(|scope| scope.contains_key(name)) {
+                return Some((frame_index, scope_index));
+            }
+            visible = frame.parent_scopes;
+            frame_index = frame.parent?;
+        }
+    }
+
+    fn lookup_var(&self, name: &str) -> Option<&Option<InterpreterValue>> {
+        let (frame_index, scope_index) = self.resolve_var(name)?;
+        self.frames[frame_index].scopes[scope_index].get(name)
+    }
+
+    fn lookup_var_mut(&mut self, name: &str) -> Option<&mut Option<InterpreterValue>> {
+        let (frame_index, scope_index) = self.resolve_var(name)?;
+        self.frames[frame_index].scopes[scope_index].get_mut(name)
+    }
+}
+
+/// Returns the range of the source a node was lowered from. A node that cannot be found is
+/// located at the start of the file.
+pub(crate) fn node_range(file: &AnalyzedSourceFile, node_ref: MxirNodeRef) -> Range {
+    file.mxir()
+        .0
+        .get(node_ref.0 as usize)
+        .and_then(|node| file.ast().0.get(node.ast_node.0 as usize))
+        .map_or(
+            Range {
+                start: Point { row: 0, col: 0 },
+                end: Point { row: 0, col: 0 },
+            },
+            |ast_node| ast_node.range,
+        )
+}
+
+/// Returns whether the condition of an `if` holds. Conditions must be booleans, so values of other
+/// types are not truthy or falsy but an error.
+pub(crate) fn condition_holds(
+    condition: Option<InterpreterValue>,
+) -> Result<bool, RuntimeErrorKind> {
+    match condition {
+        Some(InterpreterValue::Boolean(value)) => Ok(value),
+        Some(other) => Err(RuntimeErrorKind::NonBoolCondition(other.type_name())),
+        None => Err(RuntimeErrorKind::MissingValue),
+    }
+}
+
+/// Returns the values a loop over `iterable` visits.
+pub(crate) fn iterate_values(
+    iterable: Option<InterpreterValue>,
+) -> Result<Box<dyn Iterator<Item = InterpreterValue>>, RuntimeErrorKind> {
+    match iterable {
+        Some(InterpreterValue::Range(range)) => {
+            let start = range.start.unwrap_or(0);
+            match range.end {
+                Some(end) => Ok(Box::new((start..end).map(InterpreterValue::Integer))),
+                None => Ok(Box::new((start..).map(InterpreterValue::Integer))),
+            }
+        }
+        // The loop visits the elements the list has when it starts
+        Some(InterpreterValue::List(list)) => Ok(Box::new(list.borrow().clone().into_iter())),
+        Some(other) => Err(RuntimeErrorKind::NotIterable(other.type_name())),
+        None => Err(RuntimeErrorKind::MissingValue),
+    }
+}
+
+/// Converts an evaluated index into a position within a list of length `len`.
+pub(crate) fn list_position(
+    index_value: Option<InterpreterValue>,
+    len: usize,
+) -> Result<usize, RuntimeErrorKind> {
+    match index_value {
+        Some(InterpreterValue::Integer(index)) => usize::try_from(index)
+            .ok()
+            .filter(|position| *position < len)
+            .ok_or(RuntimeErrorKind::IndexOutOfBounds(index, len)),
+        Some(other) => Err(RuntimeErrorKind::NonIntegerIndex(other.type_name())),
+        None => Err(RuntimeErrorKind::MissingValue),
+    }
+}
+
+pub(crate) fn apply_binary_op(
+    op: BinaryOp,
+    lhs: InterpreterValue,
+    rhs: InterpreterValue,
+) -> Result<InterpreterValue, RuntimeErrorKind> {
+    use InterpreterValue::{Boolean, Float, Integer, String};
+
+    let value = match (op, lhs, rhs) {
+        (BinaryOp::Add, Integer(a), Integer(b)) => int32_op(a, b, i32::checked_add)?,
+        (BinaryOp::Sub, Integer(a), Integer(b)) => int32_op(a, b, i32::checked_sub)?,
+        (BinaryOp::Mul, Integer(a), Integer(b)) => int32_op(a, b, i32::checked_mul)?,
+        (BinaryOp::Div, Integer(_), Integer(0)) => return Err(RuntimeErrorKind::DivisionByZero),
+        (BinaryOp::Div, Integer(a), Integer(b)) => int32_op(a, b, i32::checked_div)?,
+        (BinaryOp::Eq, Integer(a), Integer(b)) => Boolean(a == b),
+        (BinaryOp::Neq, Integer(a), Integer(b)) => Boolean(a != b),
+        (BinaryOp::Lt, Integer(a), Integer(b)) => Boolean(a < b),
+        (BinaryOp::Gt, Integer(a), Integer(b)) => Boolean(a > b),
+        (BinaryOp::Lte, Integer(a), Integer(b)) => Boolean(a <= b),
+        (BinaryOp::Gte, Integer(a), Integer(b)) => Boolean(a >= b),
+        (BinaryOp::BitAnd, Integer(a), Integer(b)) => Integer(a & b),
+        (BinaryOp::BitOr, Integer(a), Integer(b)) => Integer(a | b),
+        (BinaryOp::BitXor, Integer(a), Integer(b)) => Integer(a ^ b),
+        (BinaryOp::Shl, Integer(a), Integer(b)) => int32_op(a, b, |a, b| {
+            u32::try_from(b).ok().and_then(|b| a.checked_shl(b))
+        })?,
+        (BinaryOp::Shr, Integer(a), Integer(b)) => int32_op(a, b, |a, b| {
+            u32::try_from(b).ok().and_then(|b| a.checked_shr(b))
+        })?,
+
+        (BinaryOp::Add, Float(a), Float(b)) => Float(a + b),
+        (BinaryOp::Sub, Float(a), Float(b)) => Float(a - b),
+        (BinaryOp::Mul, Float(a), Float(b)) => Float(a * b),
+        (BinaryOp::Div, Float(a), Float(b)) => Float(a / b),
+        (BinaryOp::Eq, Float(a), Float(b)) => Boolean(a == b),
+        (BinaryOp::Neq, Float(a), Float(b)) => Boolean(a != b),
+        (BinaryOp::Lt, Float(a), Float(b)) => Boolean(a < b),
+        (BinaryOp::Gt, Float(a), Float(b)) => Boolean(a > b),
+        (BinaryOp::Lte, Float(a), Float(b)) => Boolean(a <= b),
+        (BinaryOp::Gte, Float(a), Float(b)) => Boolean(a >= b),
+        // The integer operand of arithmetic or a comparison with a float is converted to a float
+        (op, Integer(a), Float(b)) if is_float_op(op) => {
+            return apply_binary_op(op, Float(a as f64), Float(b))
+        }
+        (op, Float(a), Integer(b)) if is_float_op(op) => {
+            return apply_binary_op(op, Float(a), Float(b as f64))
+        }
+
+        (BinaryOp::Eq, Boolean(a), Boolean(b)) => Boolean(a == b),
+        (BinaryOp::Neq, Boolean(a), Boolean(b)) => Boolean(a != b),
+        (BinaryOp::And, Boolean(a), Boolean(b)) => Boolean(a && b),
+        (BinaryOp::Or, Boolean(a), Boolean(b)) => Boolean(a || b),
+
+        (BinaryOp::Add, String(a), String(b)) => String(a + &b),
+        (BinaryOp::Eq, String(a), String(b)) => Boolean(a == b),
+        (BinaryOp::Neq, String(a), String(b)) => Boolean(a != b),
+
+        (op, lhs, rhs) => {
+            return Err(RuntimeErrorKind::InvalidBinaryOperands(
+                op,
+                lhs.type_name(),
+                rhs.type_name(),
+            ))
+        }
+    };
+
+    Ok(value)
+}
+
+/// Applies an operator to two `Int32` values, failing when either of them or the result does not
+/// fit in an `Int32`.
+fn int32_op(
+    a: i128,
+    b: i128,
+    op: fn(i32, i32) -> Option<i32>,
+) -> Result<InterpreterValue, RuntimeErrorKind> {
+    let (Ok(a), Ok(b)) = (i32::try_from(a), i32::try_from(b)) else {
+        return Err(RuntimeErrorKind::IntegerOverflow);
+    };
+    op(a, b)
+        .map(|value| InterpreterValue::Integer(value.into()))
+        .ok_or(RuntimeErrorKind::IntegerOverflow)
+}
+
+fn is_float_op(op: BinaryOp) -> bool {
+    matches!(
+        op,
+        BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::Eq
+            | BinaryOp::Neq
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Lte
+            | BinaryOp::Gte
+    )
+}
+
+pub(crate) fn apply_unary_op(
+    op: UnaryOp,
+    operand: InterpreterValue,
+) -> Result<InterpreterValue, RuntimeErrorKind> {
+    match (op, operand) {
+        (UnaryOp::Neg, InterpreterValue::Integer(a)) => int32_op(a, 0, |a, _| a.checked_neg()),
+        (UnaryOp::Neg, InterpreterValue::Float(a)) => Ok(InterpreterValue::Float(-a)),
+        (UnaryOp::Not, InterpreterValue::Boolean(a)) => Ok(InterpreterValue::Boolean(!a)),
+        (op, operand) => Err(RuntimeErrorKind::InvalidUnaryOperand(
+            op,
+            operand.type_name(),
+        )),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::heap::HeapUsage;
+    use crate::host::HostFns;
+    use crate::source_file::UnparsedSourceFile;
+
+    fn run(source: &str) -> Result<Option<InterpreterValue>, RuntimeError> {
+        run_with_limits(source, Limits::default())
+    }
+
+    fn run_with_limits(
+        source: &str,
+        limits: Limits,
+    ) -> Result<Option<InterpreterValue>, RuntimeError> {
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        assert!(
+            analyzed.file().diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            analyzed.file().diagnostics
+        );
+        Interpreter::new(&analyzed).with_limits(limits).execute()
+    }
+
+    #[test]
+    fn test_logical_operators_short_circuit() {
+        let source = r#"
+            var calls = 0;
+
+            fn check(result: Bool): Bool {
+                calls = calls + 1;
+                return result;
+            }
+
+            fn main(): Int32 {
+                if false and check(true) {
+                    return -1;
+                }
+                if true or check(false) {
+                    calls = calls * 10;
+                }
+                if not false and check(true) or check(false) {
+                    calls = calls + 100;
+                }
+                return calls;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(101))));
+    }
+
+    #[test]
+    fn test_conditions_must_be_bool() {
+        use InterpreterValue::{Boolean, Integer};
+
+        assert_eq!(condition_holds(Some(Boolean(true))), Ok(true));
+        assert_eq!(condition_holds(Some(Boolean(false))), Ok(false));
+        assert_eq!(
+            condition_holds(Some(Integer(1))),
+            Err(RuntimeErrorKind::NonBoolCondition("Int32".to_string()))
+        );
+        assert_eq!(condition_holds(None), Err(RuntimeErrorKind::MissingValue));
+    }
+
+    #[test]
+    fn test_list_literal_index_and_len() {
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [10, 20, 30];
+                xs[0] = xs[1] + xs.len;
+                xs[2] = xs[2] * 2;
+                var nested = [[1], [2, 3]];
+                return xs[0] + xs[2] + nested[1][0] + nested.len;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(87))));
+    }
+
+    #[test]
+    fn test_list_index_out_of_bounds() {
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3];
+                return xs[3];
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(*error.kind, RuntimeErrorKind::IndexOutOfBounds(3, 3));
+        assert_eq!(error.range.start.row, 3);
+
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3];
+                xs[-1] = 0;
+                return 0;
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(*error.kind, RuntimeErrorKind::IndexOutOfBounds(-1, 3));
+    }
+
+    #[test]
+    fn test_map_lookup_and_insertion() {
+        let source = r#"
+            const LIMITS = map{"retries": 3, "timeout": 30};
+
+            fn main(): Int32 {
+                var names = map{1: "one", 2: "two"};
+                names[3] = "three";
+                names[1] = "uno";
+                var total = LIMITS["retries"] + names.len;
+                if names[1] == "uno" and names[3] == "three" {
+                    total = total + 100;
+                }
+                return total;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(106))));
+    }
+
+    #[test]
+    fn test_map_missing_key() {
+        let source = r#"
+            fn main(): Int32 {
+                var ages = map{"ada": 36};
+                return ages["bob"];
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::MissingKey(MapKey::String("bob".to_string()))
+        );
+        assert_eq!(error.range.start.row, 3);
+    }
+
+    #[test]
+    fn test_range_slicing_and_iteration() {
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3, 4];
+                var head = xs[_ to 2];
+                var tail = xs[2 to _];
+                var total = head.len * 1000 + tail[0] * 100 + tail[1] * 10;
+                if "hello"[1 to 3] == "el" and "hello"[_ to 5] == "hello" {
+                    total = total + 1;
+                }
+                loop i in 2 to 5 {
+                    total = total + i * 10000;
+                }
+                return total;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(92341))));
+    }
+
+    #[test]
+    fn test_open_ended_range_loop() {
+        let source = r#"
+            fn main(): Int32 {
+                var total = 0;
+                loop i in 10 to _ {
+                    if i == 13 {
+                        break;
+                    }
+                    total = total + i;
+                }
+                return total;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(33))));
+    }
+
+    #[test]
+    fn test_slice_out_of_bounds() {
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3, 4];
+                var last = 9;
+                return xs[2 to last].len;
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::SliceOutOfBounds("2 to 9".to_string(), 4)
+        );
+    }
+
+    #[test]
+    fn test_globals_and_block_shadowing() {
+        let source = r#"
+            var total = 100;
+
+            fn bump(by: Int32): Int32 {
+                total = total + by;
+                return total;
+            }
+
+            fn main(): Int32 {
+                var x = 1;
+                if true {
+                    var x = 10;
+                    total = total + x;
+                }
+                loop i in 0 to 2 {
+                    var x = i * 2;
+                    total = total + x;
+                }
+                bump(5);
+                return total + x;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(118))));
+    }
+
+    #[test]
+    fn test_nested_function_captures() {
+        let source = r#"
+            fn scale(factor: Int32): Int32 {
+                fn twice(): Int32 {
+                    fn once(): Int32 {
+                        return factor;
+                    }
+                    return once() + once();
+                }
+                return twice();
+            }
+
+            fn main(): Int32 {
+                var base = 40;
+                var calls = 0;
+                fn add(n: Int32): Int32 {
+                    calls = calls + 1;
+                    return base + n;
+                }
+                var first = add(2);
+                base = 50;
+                return first + add(3) * 100 + calls * 10000 + scale(7) * 100000;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(1425342))));
+    }
+
+    #[test]
+    fn test_capture_ignores_caller_blocks() {
+        let source = r#"
+            fn main(): Int32 {
+                var base = 40;
+                fn add(n: Int32): Int32 {
+                    return base + n;
+                }
+                var result = 0;
+                if true {
+                    var base = 1000;
+                    result = add(2);
+                }
+                return result;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(42))));
+    }
+
+    #[test]
+    fn test_uninitialized_variable() {
+        let source = r#"
+            var limit: Int32;
+
+            fn main(): Int32 {
+                return limit;
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::UninitializedVariable("limit".to_string())
+        );
+    }
+
+    #[test]
+    fn test_continue_skips_rest_of_body() {
+        let source = r#"
+            fn main(): Int32 {
+                var total = 0;
+                loop i in 0 to 10 {
+                    if i > 3 {
+                        continue;
+                    }
+                    total = total + i;
+                }
+                return total;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(6))));
+    }
+
+    #[test]
+    fn test_continue_in_infinite_loop() {
+        let source = r#"
+            fn main(): Int32 {
+                var i = 0;
+                var skipped = 0;
+                loop {
+                    i = i + 1;
+                    if i > 5 {
+                        break;
+                    }
+                    if i == 2 {
+                        continue;
+                    }
+                    skipped = skipped + i;
+                }
+                return skipped;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(13))));
+    }
+
+    #[test]
+    fn test_nested_loop_break_and_continue() {
+        // Loop control applies to the innermost loop only
+        let source = r#"
+            fn main(): Int32 {
+                var pairs = 0;
+                loop i in 0 to 4 {
+                    if i == 1 {
+                        continue;
+                    }
+                    loop j in 0 to 4 {
+                        if j == i {
+                            break;
+                        }
+                        if j == 0 {
+                            continue;
+                        }
+                        pairs = pairs + 10 * i + j;
+                    }
+                    pairs = pairs + 1000;
+                }
+                return pairs;
+            }
+        "#;
+        // i = 0: 1000, i = 2: 21 + 1000, i = 3: 31 + 32 + 1000
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(3084))));
+    }
+
+    #[test]
+    fn test_return_from_nested_loops() {
+        let source = r#"
+            fn find(target: Int32): Int32 {
+                loop i in 0 to 10 {
+                    loop j in 0 to 10 {
+                        if i * j == target {
+                            return 10 * i + j;
+                        }
+                        continue;
+                    }
+                }
+                return 0;
+            }
+
+            fn main(): Int32 {
+                var found = 0;
+                loop {
+                    found = find(12);
+                    break;
+                }
+                return found;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(26))));
+    }
+
+    #[test]
+    fn test_fuel_limit_stops_infinite_loop() {
+        let source = r#"
+            fn main(): Int32 {
+                var i = 0;
+                loop {
+                    i = i + 1;
+                }
+                return i;
+            }
+        "#;
+        let limits = Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        };
+        let error = run_with_limits(source, limits).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::LimitExceeded(Limit::Fuel(1000))
+        );
+        assert_eq!(error.call_stack, vec!["main"]);
+    }
+
+    #[test]
+    fn test_call_depth_limit_stops_recursion() {
+        let source = r#"
+            fn down(n: Int32): Int32 {
+                return down(n + 1);
+            }
+
+            fn main(): Int32 {
+                return down(0);
+            }
+        "#;
+        let limits = Limits {
+            max_call_depth: 8,
+            ..Limits::default()
+        };
+        let error = run_with_limits(source, limits).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::LimitExceeded(Limit::CallDepth(8))
+        );
+        assert_eq!(error.call_stack.len(), 8);
+        // The error points at the call that would have gone too deep
+        assert_eq!(error.range.start.row, 2);
+    }
+
+    #[test]
+    fn test_timeout_stops_infinite_loop() {
+        let source = r#"
+            fn main(): Int32 {
+                loop {}
+                return 0;
+            }
+        "#;
+        let limits = Limits {
+            timeout: Some(std::time::Duration::from_millis(20)),
+            ..Limits::default()
+        };
+        let error = run_with_limits(source, limits).unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::LimitExceeded(Limit::Timeout(std::time::Duration::from_millis(20)))
+        );
+    }
+
+    #[test]
+    fn test_captured_output_and_trace() {
+        let source = r#"
+            fn main(): Int32 {
+                var name = readln();
+                println("Hello,", name);
+                eprint("done");
+                return 0;
+            }
+        "#;
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        let (mut io, output) = Io::captured("Ada\n");
+        let trace = crate::io::SharedBuffer::default();
+        io.trace = Some(Box::new(trace.clone()));
+        let result = Interpreter::new(&analyzed).with_io(io).execute();
+        assert_eq!(result, Ok(Some(InterpreterValue::Integer(0))));
+        assert_eq!(output.stdout.contents(), "Hello, Ada\n");
+        assert_eq!(output.stderr.contents(), "done");
+        assert_eq!(
+            trace.contents(),
+            "Variable 'name' initialized with value String(\"Ada\")\n"
+        );
+    }
+
+    #[test]
+    fn test_host_functions() {
+        let source = r#"
+            fn main(): Int32 {
+                var shouted = shout("hi");
+                println(shouted);
+                return checked_div(7, 2) + checked_div(1, 0);
+            }
+        "#;
+        let mut host_fns = HostFns::new();
+        host_fns
+            .register("shout", |text: String| text.to_uppercase())
+            .register("checked_div", |a: i32, b: i32| -> Result<i32, String> {
+                a.checked_div(b)
+                    .ok_or_else(|| "division by zero".to_string())
+            });
+        let analyzed = UnparsedSourceFile::new("test.mx", source)
+            .parse()
+            .analyze_with(host_fns);
+        assert!(analyzed.file().diagnostics.is_empty());
+
+        let (io, output) = Io::captured("");
+        let error = Interpreter::new(&analyzed)
+            .with_io(io)
+            .execute()
+            .unwrap_err();
+        assert_eq!(output.stdout.contents(), "HI\n");
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::Host("division by zero".to_string())
+        );
+        assert_eq!(error.call_stack, vec!["main"]);
+    }
+
+    #[test]
+    fn test_float_operations() {
+        use InterpreterValue::{Boolean, Float, Integer};
+
+        assert_eq!(
+            apply_binary_op(BinaryOp::Mul, Float(1.5), Float(2.0)),
+            Ok(Float(3.0))
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Div, Integer(1), Float(4.0)),
+            Ok(Float(0.25))
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Lt, Float(1.5), Integer(2)),
+            Ok(Boolean(true))
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Eq, Integer(2), Float(2.0)),
+            Ok(Boolean(true))
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::BitAnd, Float(1.0), Integer(1)),
+            Err(RuntimeErrorKind::InvalidBinaryOperands(
+                BinaryOp::BitAnd,
+                "Float".to_string(),
+                "Int32".to_string()
+            ))
+        );
+        assert_eq!(apply_unary_op(UnaryOp::Neg, Float(0.5)), Ok(Float(-0.5)));
+
+        assert_eq!(Float(2.0).to_string(), "2.0");
+        assert_eq!(Float(0.1 + 0.2).to_string(), "0.30000000000000004");
+        assert_eq!(
+            apply_binary_op(BinaryOp::Div, Float(1.0), Integer(0)).map(|value| value.to_string()),
+            Ok("inf".to_string())
+        );
+    }
+
+    #[test]
+    fn test_scalar_equality() {
+        let source = r#"
+            fn main(): Int32 {
+                var matches = 0;
+                if "mx" == "m" + "x" {
+                    matches = matches + 1;
+                }
+                if true != false {
+                    matches = matches + 1;
+                }
+                if 2 == 2.0 and 1 != 2 {
+                    matches = matches + 1;
+                }
+                if "a" == "b" {
+                    matches = matches + 100;
+                }
+                return matches;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(3))));
+    }
+
+    #[test]
+    fn test_constant_struct_instance() {
+        let source = r#"
+            struct Person {
+                var name: String;
+                var age: Int32;
+            }
+
+            const bob = new Person { name: "Bob", age: 42 };
+
+            fn main(): Int32 {
+                return bob.age + bob.name.len;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(45))));
+    }
+
+    #[test]
+    fn test_int32_overflow() {
+        use InterpreterValue::Integer;
+
+        let max = i32::MAX as i128;
+        let min = i32::MIN as i128;
+        assert_eq!(
+            apply_binary_op(BinaryOp::Add, Integer(max), Integer(1)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Sub, Integer(min), Integer(1)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Mul, Integer(65536), Integer(65536)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Div, Integer(min), Integer(-1)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Shl, Integer(1), Integer(32)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_unary_op(UnaryOp::Neg, Integer(min)),
+            Err(RuntimeErrorKind::IntegerOverflow)
+        );
+        assert_eq!(
+            apply_binary_op(BinaryOp::Add, Integer(max - 1), Integer(1)),
+            Ok(Integer(max))
+        );
+    }
+
+    #[test]
+    fn test_operator_overloading_doc_example() {
+        let source = r#"
+            struct Vec2 {
+                var x: Int32;
+                var y: Int32;
+
+                fn add(self: Vec2, other: Vec2): Vec2 {
+                    return new Vec2 { x: self.x + other.x, y: self.y + other.y };
+                }
+            }
+
+            fn main(): Int32 {
+                var sum = new Vec2 { x: 1, y: 2 } + new Vec2 { x: 3, y: 4 };
+                return sum.x + sum.y;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(10))));
+    }
+
+    #[test]
+    fn test_heap_usage() {
+        let source = r#"
+            var kept = [0];
+
+            fn main(): Int32 {
+                loop i in 0 to 10 {
+                    var pair = [i, i + 1];
+                    kept[0] = kept[0] + pair[1];
+                }
+                return kept[0];
+            }
+        "#;
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        let mut interpreter = Interpreter::new(&analyzed);
+        assert_eq!(
+            interpreter.execute(),
+            Ok(Some(InterpreterValue::Integer(55)))
+        );
+        // Only the global is still alive, and values without cycles never need collecting
+        assert_eq!(
+            interpreter.heap().usage(),
+            HeapUsage {
+                live: 1,
+                allocated: 11,
+                collected: 0
+            }
+        );
+    }
+
+    #[test]
+    fn test_render_runtime_error() {
+        let file = SourceFile {
+            path: "main.mx".to_string(),
+            src: "fn divide(a: Int32, b: Int32): Int32 {\n    return a / b;\n}\n".to_string(),
+            diagnostics: vec![],
+        };
+        let error = RuntimeError {
+            range: Range {
+                start: Point { row: 1, col: 11 },
+                end: Point { row: 1, col: 16 },
+            },
+            kind: Box::new(RuntimeErrorKind::DivisionByZero),
+            call_stack: vec!["divide".to_string(), "main".to_string()],
+        };
+        assert_eq!(
+            error.render(&file),
+            "error: Division by zero\n \
+             --> main.mx:2:12\n  \
+             |\n\
+             2 |     return a / b;\n  \
+             |            ^^^^^\n  \
+             = in divide\n  \
+             = called from main\n"
+        );
     }
 }
@@ -0,0 +1,91 @@
+use std::cell::RefCell;
+use std::io::{BufRead, Write};
+use std::rc::Rc;
+
+/// The streams a running program reads from and writes to. Hosts that embed the interpreter can
+/// provide their own, for example to capture the output of a program.
+pub struct Io {
+    pub stdout: Box<dyn Write>,
+    pub stderr: Box<dyn Write>,
+    pub stdin: Box<dyn BufRead>,
+    /// Receives a trace of the execution, such as the values variables are initialized with. Tracing
+    /// is off when this is `None`.
+    pub trace: Option<Box<dyn Write>>,
+}
+
+impl Io {
+    /// The standard streams of the process, without tracing.
+    pub fn std() -> Self {
+        Self {
+            stdout: Box::new(std::io::stdout()),
+            stderr: Box::new(std::io::stderr()),
+            // Locking stdin here would block every other `Io` on the thread until this one is dropped
+            stdin: Box::new(std::io::BufReader::new(std::io::stdin())),
+            trace: None,
+        }
+    }
+
+    /// Streams that read `input` and collect everything written to standard output and standard
+    /// error, without tracing.
+    pub fn captured(input: &str) -> (Self, CapturedOutput) {
+        let output = CapturedOutput::default();
+        let io = Self {
+            stdout: Box::new(output.stdout.clone()),
+            stderr: Box::new(output.stderr.clone()),
+            stdin: Box::new(std::io::Cursor::new(input.as_bytes().to_vec())),
+            trace: None,
+        };
+        (io, output)
+    }
+
+    /// Writes a line to the trace, if tracing is on.
+    pub fn trace(&mut self, message: std::fmt::Arguments) {
+        if let Some(trace) = &mut self.trace {
+            // The trace is a diagnostic aid, so failing to write it does not stop the program
+            let _ = writeln!(trace, "{}", message);
+        }
+    }
+}
+
+impl std::fmt::Debug for Io {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        f.debug_struct("Io")
+            .field("trace", &self.trace.is_some())
+            .finish_non_exhaustive()
+    }
+}
+
+impl Default for Io {
+    fn default() -> Self {
+        Self::std()
+    }
+}
+
+/// The output collected by [`Io::captured`].
+#[derive(Debug, Clone, Default)]
+pub struct CapturedOutput {
+    pub stdout: SharedBuffer,
+    pub stderr: SharedBuffer,
+}
+
+/// A writer whose contents can still be read after it has been handed to a program.
+#[derive(Debug, Clone, Default)]
+pub struct SharedBuffer(Rc<RefCell<Vec<u8>>>);
+
+impl SharedBuffer {
+    /// Everything written so far, with invalid UTF-8 replaced.
+    pub fn contents(&self) -> String {
+        String::from_utf8_lossy(&self.0.borrow()).into_owned()
+    }
+}
+
+impl Write for SharedBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.borrow_mut().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
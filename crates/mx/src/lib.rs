@@ -1,11 +1,24 @@
 pub mod ast;
+pub mod builtins;
+pub mod bytecode;
 pub mod c_emitter;
 pub mod comptime;
+pub mod debugger;
 pub mod diag;
+pub mod engine;
+pub mod heap;
+pub mod host;
+pub mod interpreter;
+pub mod io;
+pub mod limits;
 pub mod mxir;
 pub mod parser;
 pub mod position;
 pub mod sema;
 pub mod source_file;
+pub mod string_literal;
 pub mod symbol_table;
-pub mod interpreter;
+pub mod types;
+pub mod vm;
+
+pub use engine::Engine;
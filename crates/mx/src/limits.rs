@@ -0,0 +1,137 @@
+use std::fmt::Display;
+use std::time::{Duration, Instant};
+
+/// The call depth allowed when no other limit is configured.
+pub const DEFAULT_MAX_CALL_DEPTH: usize = 1000;
+
+// Reading the clock on every step is comparatively slow, so the deadline is only checked this often.
+// It must be a power of two, as steps are masked with it rather than divided by it.
+const TIMEOUT_CHECK_INTERVAL: u64 = 1024;
+
+/// Bounds on the resources a program may use while it runs. A limit of `None` is unbounded, but the
+/// call depth is always bounded since every call takes up memory.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Limits {
+    /// The number of steps the program may take. The interpreter takes a step for every MXIR node
+    /// it evaluates and the VM for every instruction it executes, so the same fuel lasts longer on
+    /// the VM.
+    pub fuel: Option<u64>,
+    /// The number of function calls that may be active at once, including the entry point.
+    pub max_call_depth: usize,
+    /// The wall-clock time the program may run for.
+    pub timeout: Option<Duration>,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Self {
+            fuel: None,
+            max_call_depth: DEFAULT_MAX_CALL_DEPTH,
+            timeout: None,
+        }
+    }
+}
+
+/// A limit a program ran into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Limit {
+    Fuel(u64),
+    CallDepth(usize),
+    Timeout(Duration),
+}
+
+impl Display for Limit {
+    fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+        match self {
+            Limit::Fuel(fuel) => write!(f, "fuel limit of {} steps", fuel),
+            Limit::CallDepth(depth) => write!(f, "maximum call depth of {}", depth),
+            Limit::Timeout(timeout) => write!(f, "timeout of {:?}", timeout),
+        }
+    }
+}
+
+/// Tracks the resources used by one run of a program against its limits.
+#[derive(Debug, Clone)]
+pub(crate) struct Budget {
+    limits: Limits,
+    steps: u64,
+    deadline: Option<Instant>,
+}
+
+impl Budget {
+    /// Starts the clock for a run.
+    pub fn start(limits: Limits) -> Self {
+        Self {
+            limits,
+            steps: 0,
+            deadline: limits.timeout.map(|timeout| Instant::now() + timeout),
+        }
+    }
+
+    /// Takes a step, failing once the fuel or the time has run out.
+    pub fn step(&mut self) -> Result<(), Limit> {
+        self.steps += 1;
+        if let Some(fuel) = self.limits.fuel {
+            if self.steps > fuel {
+                return Err(Limit::Fuel(fuel));
+            }
+        }
+        if let (Some(deadline), Some(timeout)) = (self.deadline, self.limits.timeout) {
+            if self.steps & (TIMEOUT_CHECK_INTERVAL - 1) == 0 && Instant::now() >= deadline {
+                return Err(Limit::Timeout(timeout));
+            }
+        }
+        Ok(())
+    }
+
+    /// Moves the deadline back, e.g. by the time the program spent paused in a debugger.
+    pub fn extend_deadline(&mut self, duration: Duration) {
+        self.deadline = self.deadline.map(|deadline| deadline + duration);
+    }
+
+    /// Checks that a call may be made while `depth` calls, including it, are active.
+    pub fn enter_call(&self, depth: usize) -> Result<(), Limit> {
+        if depth > self.limits.max_call_depth {
+            return Err(Limit::CallDepth(self.limits.max_call_depth));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_budget_fuel() {
+        let mut budget = Budget::start(Limits {
+            fuel: Some(3),
+            ..Limits::default()
+        });
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Ok(()));
+        assert_eq!(budget.step(), Err(Limit::Fuel(3)));
+    }
+
+    #[test]
+    fn test_budget_call_depth() {
+        let budget = Budget::start(Limits::default());
+        assert_eq!(budget.enter_call(DEFAULT_MAX_CALL_DEPTH), Ok(()));
+        assert_eq!(
+            budget.enter_call(DEFAULT_MAX_CALL_DEPTH + 1),
+            Err(Limit::CallDepth(DEFAULT_MAX_CALL_DEPTH))
+        );
+    }
+
+    #[test]
+    fn test_budget_timeout() {
+        let mut budget = Budget::start(Limits {
+            timeout: Some(Duration::ZERO),
+            ..Limits::default()
+        });
+        // The clock is only read every few steps
+        let result = (0..TIMEOUT_CHECK_INTERVAL).try_for_each(|_| budget.step());
+        assert_eq!(result, Err(Limit::Timeout(Duration::ZERO)));
+    }
+}
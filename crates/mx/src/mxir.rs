@@ -1,9 +1,9 @@
-use crate::{ast::AstNodeRef, comptime::ComptimeValue};
+use crate::{ast::AstNodeRef, builtins::BuiltinFn, comptime::ComptimeValue};
 
 #[derive(Debug, Clone)]
 pub struct Mxir(pub Vec<MxirNode>);
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct MxirNodeRef(pub u32);
 
 #[derive(Debug, Clone)]
@@ -40,6 +40,17 @@ pub enum MxirNodeData {
     BoolLiteral(MxirBoolLiteral),
     VarExpr(MxirVarExpr),
     CallExpr(MxirCallExpr),
+    Binary(MxirBinary),
+    Unary(MxirUnary),
+    StructLiteral(MxirStructLiteral),
+    FieldAccess(MxirFieldAccess),
+    ListLiteral(MxirListLiteral),
+    Index(MxirIndex),
+    MapLiteral(MxirMapLiteral),
+    KeyAccess(MxirKeyAccess),
+    Range(MxirRange),
+    Iterate(MxirIterate),
+    Stringify(MxirNodeRef),
 }
 
 #[derive(Debug, Clone)]
@@ -51,13 +62,22 @@ pub struct MxirLoop(pub Option<MxirNodeRef>);
 #[derive(Debug, Clone)]
 pub struct MxirBuiltinFnDecl {
     pub name: String,
-    pub fn_: fn() -> (),
+    pub fn_: BuiltinFn,
 }
 
 #[derive(Debug, Clone)]
 pub struct MxirFnDecl {
     pub name: String,
+    pub params: Vec<MxirParamDecl>,
     pub body: MxirNodeRef,
+    // The function this one is nested in, whose variables it can refer to
+    pub enclosing: Option<MxirNodeRef>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirParamDecl {
+    pub name: String,
+    pub ty: ComptimeValue,
 }
 
 #[derive(Debug, Clone)]
@@ -107,3 +127,201 @@ pub struct MxirIf {
     pub then_branch: MxirNodeRef,
     pub else_branch: Option<MxirNodeRef>,
 }
+
+#[derive(Debug, Clone)]
+pub struct MxirStructLiteral {
+    pub name: String,
+    pub fields: Vec<MxirFieldInit>,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirFieldInit {
+    pub name: String,
+    pub value: MxirNodeRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirFieldAccess {
+    pub object: MxirNodeRef,
+    pub field: String,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirListLiteral(pub Vec<MxirNodeRef>);
+
+#[derive(Debug, Clone)]
+pub struct MxirIndex {
+    pub object: MxirNodeRef,
+    pub index: MxirNodeRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirMapLiteral(pub Vec<MxirMapEntry>);
+
+#[derive(Debug, Clone)]
+pub struct MxirMapEntry {
+    pub key: MxirNodeRef,
+    pub value: MxirNodeRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirKeyAccess {
+    pub map: MxirNodeRef,
+    pub key: MxirNodeRef,
+}
+
+/// A range of integers. A missing bound is open, and the end is exclusive.
+#[derive(Debug, Clone)]
+pub struct MxirRange {
+    pub start: Option<MxirNodeRef>,
+    pub end: Option<MxirNodeRef>,
+}
+
+/// A loop which binds each value produced by `iterable` to `binding` before running `body`.
+#[derive(Debug, Clone)]
+pub struct MxirIterate {
+    pub binding: String,
+    pub iterable: MxirNodeRef,
+    pub body: MxirNodeRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirBinary {
+    pub op: BinaryOp,
+    pub lhs: MxirNodeRef,
+    pub rhs: MxirNodeRef,
+}
+
+#[derive(Debug, Clone)]
+pub struct MxirUnary {
+    pub op: UnaryOp,
+    pub operand: MxirNodeRef,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BinaryOp {
+    // Arithmetic
+    Add,
+    Sub,
+    Mul,
+    Div,
+
+    // Relational
+    Eq,
+    Neq,
+    Lt,
+    Gt,
+    Lte,
+    Gte,
+
+    // Logical
+    And,
+    Or,
+
+    // Bitwise
+    BitAnd,
+    BitOr,
+    BitXor,
+    Shl,
+    Shr,
+}
+
+impl BinaryOp {
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "+" => Some(BinaryOp::Add),
+            "-" => Some(BinaryOp::Sub),
+            "*" => Some(BinaryOp::Mul),
+            "/" => Some(BinaryOp::Div),
+            "==" => Some(BinaryOp::Eq),
+            "!=" => Some(BinaryOp::Neq),
+            "<" => Some(BinaryOp::Lt),
+            ">" => Some(BinaryOp::Gt),
+            "<=" => Some(BinaryOp::Lte),
+            ">=" => Some(BinaryOp::Gte),
+            "and" => Some(BinaryOp::And),
+            "or" => Some(BinaryOp::Or),
+            "&" => Some(BinaryOp::BitAnd),
+            "|" => Some(BinaryOp::BitOr),
+            "^" => Some(BinaryOp::BitXor),
+            "<<" => Some(BinaryOp::Shl),
+            ">>" => Some(BinaryOp::Shr),
+            _ => None,
+        }
+    }
+
+    pub fn token(&self) -> &'static str {
+        match self {
+            BinaryOp::Add => "+",
+            BinaryOp::Sub => "-",
+            BinaryOp::Mul => "*",
+            BinaryOp::Div => "/",
+            BinaryOp::Eq => "==",
+            BinaryOp::Neq => "!=",
+            BinaryOp::Lt => "<",
+            BinaryOp::Gt => ">",
+            BinaryOp::Lte => "<=",
+            BinaryOp::Gte => ">=",
+            BinaryOp::And => "and",
+            BinaryOp::Or => "or",
+            BinaryOp::BitAnd => "&",
+            BinaryOp::BitOr => "|",
+            BinaryOp::BitXor => "^",
+            BinaryOp::Shl => "<<",
+            BinaryOp::Shr => ">>",
+        }
+    }
+
+    /// Returns the name of the method that overloads this operator for structs.
+    pub fn method_name(&self) -> Option<&'static str> {
+        match self {
+            BinaryOp::Add => Some("add"),
+            BinaryOp::Sub => Some("sub"),
+            BinaryOp::Mul => Some("mul"),
+            BinaryOp::Div => Some("div"),
+            BinaryOp::Eq => Some("eq"),
+            BinaryOp::Neq => Some("neq"),
+            BinaryOp::Gt => Some("gt"),
+            BinaryOp::Lt => Some("lt"),
+            BinaryOp::Gte => Some("gte"),
+            BinaryOp::Lte => Some("lte"),
+            BinaryOp::BitOr => Some("bit_or"),
+            BinaryOp::BitAnd => Some("bit_and"),
+            BinaryOp::BitXor => Some("bit_xor"),
+            BinaryOp::And => Some("logical_and"),
+            BinaryOp::Or => Some("logical_or"),
+            BinaryOp::Shl | BinaryOp::Shr => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum UnaryOp {
+    Neg,
+    Not,
+}
+
+impl UnaryOp {
+    pub fn from_token(token: &str) -> Option<Self> {
+        match token {
+            "-" => Some(UnaryOp::Neg),
+            "not" => Some(UnaryOp::Not),
+            _ => None,
+        }
+    }
+
+    pub fn token(&self) -> &'static str {
+        match self {
+            UnaryOp::Neg => "-",
+            UnaryOp::Not => "not",
+        }
+    }
+
+    /// Returns the name of the method that overloads this operator for structs.
+    pub fn method_name(&self) -> Option<&'static str> {
+        match self {
+            UnaryOp::Not => Some("logical_not"),
+            UnaryOp::Neg => None,
+        }
+    }
+}
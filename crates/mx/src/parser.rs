@@ -80,13 +80,23 @@ impl<'a> Parser<'a> {
         self.check_extra_errors(node, src, range);
 
         // Traverse children once, adding them to both `children` and `named_children` if applicable
-        for i in 0..node.named_child_count() {
-            if let Some(child) = node.named_child(i) {
+        for i in 0..node.child_count() {
+            if let Some(child) = node.child(i) {
+                let field_name = node.field_name_for_child(i as u32);
+
+                // Anonymous tokens are only kept when they are bound to a field, such as the
+                // `operator` of a binary expression
+                if !child.is_named() && field_name.is_none() {
+                    continue;
+                }
+
                 let child_ref = self.walk_tree(path, child, src);
-                self.ast.0[node_ref.0 as usize].children.push(child_ref);
+                if child.is_named() {
+                    self.ast.0[node_ref.0 as usize].children.push(child_ref);
+                }
 
                 // Get the field name for the child if it has one
-                if let Some(field_name) = node.field_name_for_named_child(i as u32) {
+                if let Some(field_name) = field_name {
                     self.ast.0[node_ref.0 as usize]
                         .named_children
                         .insert(field_name.to_string(), child_ref);
@@ -1,18 +1,42 @@
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 use crate::{
     ast::{AstNode, AstNodeRef},
-    comptime::{ComptimeEnv, ComptimeValue, FnProto, ParamDecl},
+    builtins::{BuiltinFn, BUILTINS},
+    comptime::{
+        apply_binary_op, apply_unary_op, ComptimeEnv, ComptimeError, ComptimeValue, FnDecl,
+        FnProto, ParamDecl,
+    },
     diag::{Diagnostic, DiagnosticKind},
+    host::HostFns,
     mxir::{
-        Mxir, MxirBlock, MxirBoolLiteral, MxirBuiltinFnDecl, MxirCallExpr, MxirFnDecl, MxirIf,
-        MxirIntLiteral, MxirLoop, MxirNode, MxirNodeData, MxirNodeRef, MxirReturn,
-        MxirStringLiteral, MxirVarDecl, MxirVarExpr,
+        BinaryOp, Mxir, MxirBinary, MxirBlock, MxirBoolLiteral, MxirBuiltinFnDecl, MxirCallExpr,
+        MxirFieldAccess, MxirFieldInit, MxirFloatLiteral, MxirFnDecl, MxirIf, MxirIndex,
+        MxirIntLiteral, MxirIterate, MxirKeyAccess, MxirListLiteral, MxirLoop, MxirMapEntry,
+        MxirMapLiteral, MxirNode, MxirNodeData, MxirNodeRef, MxirParamDecl, MxirRange, MxirReturn,
+        MxirStringLiteral, MxirStructLiteral, MxirUnary, MxirVarDecl, MxirVarExpr, UnaryOp,
     },
     position::Range,
     source_file::ParsedSourceFile,
+    string_literal::{self, StringSegment},
+    symbol_table::SymbolTableRef,
+    types::{StructField, StructType, Type},
 };
 
+// The deepest nesting of function calls evaluated at compile time
+const MAX_COMPTIME_CALL_DEPTH: usize = 64;
+// The number of statements and expressions a compile-time expression may evaluate, including
+// those in the bodies of the functions it calls
+const MAX_COMPTIME_STEPS: usize = 100_000;
+
+/// What happens after a statement in the body of a function called at compile time.
+enum ComptimeFlow {
+    Next,
+    Return(ComptimeValue),
+    // Evaluation stopped at an error that has already been reported
+    Abort,
+}
+
 #[derive(Debug, Clone)]
 pub struct Sema<'a> {
     file: &'a ParsedSourceFile,
@@ -20,6 +44,33 @@ pub struct Sema<'a> {
     env: ComptimeEnv,
     mxir: Mxir,
     diagnostics: Vec<Diagnostic>,
+    // The kind and range of each diagnostic reported so far. The same code can be analyzed more
+    // than once, as functions are evaluated at compile time for every call and lowered as well, so
+    // diagnostics that were already reported are skipped
+    reported: HashSet<(DiagnosticKind, Range)>,
+
+    // Functions that have already been lowered to MXIR, keyed by their declaration node
+    fn_decls: HashMap<AstNodeRef, MxirNodeRef>,
+    // Scopes that were open where each function was declared, along with the function it is
+    // nested in, keyed by the declaration node
+    fn_scopes: HashMap<AstNodeRef, (Vec<SymbolTableRef>, Option<MxirNodeRef>)>,
+    // Functions whose bodies are currently being lowered, innermost last
+    enclosing_fns: Vec<MxirNodeRef>,
+
+    // Types of the expressions lowered so far, when known
+    types: HashMap<MxirNodeRef, Type>,
+    // Declared return types of the functions currently being analyzed
+    return_types: Vec<Option<Type>>,
+
+    // Number of function calls currently being evaluated at compile time, and the steps taken by
+    // the compile-time expression being evaluated
+    comptime_depth: usize,
+    comptime_steps: usize,
+
+    // Functions registered by the host, declared alongside the builtins
+    host_fns: HostFns,
+    // Whether the program has a `main` function that runs once the globals are initialized
+    entry_point: bool,
 }
 
 impl<'a> Sema<'a> {
@@ -29,9 +80,32 @@ impl<'a> Sema<'a> {
             env: ComptimeEnv::new(),
             mxir: Mxir(vec![]),
             diagnostics: vec![],
+            reported: HashSet::new(),
+            fn_decls: HashMap::new(),
+            fn_scopes: HashMap::new(),
+            enclosing_fns: vec![],
+            types: HashMap::new(),
+            return_types: vec![],
+            comptime_depth: 0,
+            comptime_steps: 0,
+            host_fns: HostFns::default(),
+            entry_point: true,
         }
     }
 
+    /// Makes the functions registered by the host callable from the program.
+    pub fn with_host_fns(mut self, host_fns: HostFns) -> Self {
+        self.host_fns = host_fns;
+        self
+    }
+
+    /// Analyzes the file as a library whose functions are called by the host, rather than a
+    /// program with a `main` function. Every top-level function is lowered, called or not.
+    pub fn without_entry_point(mut self) -> Self {
+        self.entry_point = false;
+        self
+    }
+
     pub fn analyze(mut self) -> (Mxir, Vec<Diagnostic>) {
         self.analyze_source_file();
 
@@ -39,17 +113,46 @@ impl<'a> Sema<'a> {
     }
 
     fn initialize_builtins(&mut self) {
-        let _ = self.env.declare_builtin_fn(
-            FnProto {
-                name: Some("print".to_string()),
-                comptime_params: vec![],
-                params: vec![],
-                return_type: ComptimeValue::Undefined,
-            },
-            || {
-                println!("Hello world");
-            },
-        );
+        for (name, ty) in [
+            ("Void", Type::Void),
+            ("Bool", Type::Bool),
+            ("Int32", Type::Int32),
+            ("Float", Type::Float),
+            ("String", Type::String),
+        ] {
+            let _ = self
+                .env
+                .declare_const(AstNodeRef(0), name, None, ComptimeValue::Type(ty));
+        }
+
+        // Builtins accept any number of arguments, so their prototypes declare no parameters
+        for builtin in BUILTINS {
+            let _ = self.env.declare_builtin_fn(
+                FnProto {
+                    name: Some(builtin.name.to_string()),
+                    comptime_params: vec![],
+                    params: vec![],
+                    return_type: ComptimeValue::Type(builtin.return_type.clone()),
+                },
+                BuiltinFn::new(builtin.f),
+                true,
+            );
+        }
+
+        // Host functions cannot shadow builtins, so a clashing registration is reported on the file
+        let mut clashing = vec![];
+        for host_fn in self.host_fns.iter() {
+            if self
+                .env
+                .declare_builtin_fn(host_fn.proto.clone(), host_fn.f.clone(), false)
+                .is_err()
+            {
+                clashing.extend(host_fn.proto.name.clone());
+            }
+        }
+        for name in clashing {
+            self.report(AstNodeRef(0), DiagnosticKind::HostFnShadowsBuiltin(name));
+        }
     }
 
     fn analyze_source_file(&mut self) {
@@ -69,7 +172,9 @@ impl<'a> Sema<'a> {
             let mut stmts = self.analyze_body(source_file_node_ref);
 
             // Lookup the entry point and mimic a function call
-            if let Some(main_fn_decl) = self.env.get("main").cloned() {
+            if !self.entry_point {
+                self.analyze_uncalled_fns(source_file_node_ref);
+            } else if let Some(main_fn_decl) = self.env.get("main").cloned() {
                 stmts.push(self.analyze_fn_call(
                     main_fn_decl.node_ref,
                     main_fn_decl.value,
@@ -98,10 +203,40 @@ impl<'a> Sema<'a> {
         }
     }
 
+    /// Lowers the top-level functions that have not been called yet. Generic functions can only
+    /// be lowered once their comptime arguments are known, so they are skipped.
+    fn analyze_uncalled_fns(&mut self, source_file_node_ref: AstNodeRef) {
+        for child_ref in self.node(source_file_node_ref).children {
+            let child = self.node(child_ref);
+            if child.kind != "fn_decl" {
+                continue;
+            }
+            let Some(name) = child
+                .named_children
+                .get("proto")
+                .and_then(|proto_ref| self.node(*proto_ref).named_children.get("name").copied())
+                .map(|name_ref| self.node(name_ref).text)
+            else {
+                continue;
+            };
+            let Some(ComptimeValue::FnDecl(fn_decl)) =
+                self.env.get(&name).map(|binding| binding.value.clone())
+            else {
+                continue;
+            };
+            if fn_decl.proto.comptime_params.is_empty()
+                && !self.fn_decls.contains_key(&fn_decl.node_ref)
+            {
+                self.analyze_fn_body(&fn_decl);
+            }
+        }
+    }
+
     fn analyze_node(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
         let node = self.node(node_ref);
         match node.kind.as_str() {
             "fn_decl" => self.analyze_fn_decl(node_ref),
+            "struct_decl" => self.analyze_struct_decl(node_ref),
             "const_decl" => self.analyze_const_decl(node_ref),
             "var_decl" => self.analyze_var_decl(node_ref),
             "block" => self.analyze_block(node_ref),
@@ -120,6 +255,13 @@ impl<'a> Sema<'a> {
     }
 
     fn analyze_fn_decl(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        self.declare_fn_decl(node_ref, None);
+        self.emit_nop(node_ref, "fn_decl")
+    }
+
+    /// Declares a function in the current scope. Methods are qualified with the name of their
+    /// struct, e.g. `Vec2.add`.
+    fn declare_fn_decl(&mut self, node_ref: AstNodeRef, qualifier: Option<&str>) {
         let node = self.node(node_ref);
 
         let proto_ref = node
@@ -167,8 +309,12 @@ impl<'a> Sema<'a> {
 
         // Only register the function in the environment if it has a valid name
         if let Some(name) = name {
+            let name = match qualifier {
+                Some(qualifier) => format!("{}.{}", qualifier, name),
+                None => name,
+            };
             let proto = FnProto {
-                name: Some(name.clone()),
+                name: Some(name),
                 comptime_params,
                 params,
                 return_type,
@@ -178,12 +324,99 @@ impl<'a> Sema<'a> {
                 // Report duplicate definition
                 self.report(node_ref, DiagnosticKind::DuplicateDefinition);
             }
+            self.fn_scopes.insert(
+                node_ref,
+                (self.env.scopes(), self.enclosing_fns.last().copied()),
+            );
         }
+    }
 
-        self.emit_nop(node_ref, "fn_decl")
+    fn analyze_struct_decl(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+
+        let name_ref = node
+            .named_children
+            .get("name")
+            .copied()
+            .expect("Struct name not found");
+        let name = self.node(name_ref).text;
+
+        let body_ref = node
+            .named_children
+            .get("body")
+            .copied()
+            .expect("Struct body not found");
+        let body_node = self.node(body_ref);
+
+        let mut fields: Vec<StructField> = vec![];
+        for member_ref in body_node.children.iter().copied() {
+            let member_node = self.node(member_ref);
+            if member_node.kind != "var_decl" {
+                continue;
+            }
+
+            let field_name_ref = member_node
+                .named_children
+                .get("name")
+                .copied()
+                .expect("Field name not found");
+            let field_name = self.node(field_name_ref).text;
+
+            if fields.iter().any(|field| field.name == field_name) {
+                self.report(field_name_ref, DiagnosticKind::DuplicateField(field_name));
+                continue;
+            }
+
+            // Fields must always be declared with a type
+            let field_type = member_node
+                .named_children
+                .get("type")
+                .copied()
+                .and_then(|ty_ref| self.comptime_eval_comptime_expr(ty_ref).as_type());
+            let Some(field_type) = field_type else {
+                self.report(member_ref, DiagnosticKind::ExpectedType);
+                continue;
+            };
+
+            fields.push(StructField {
+                name: field_name,
+                ty: field_type,
+            });
+        }
+
+        if self
+            .env
+            .declare_struct(
+                node_ref,
+                StructType {
+                    name: name.clone(),
+                    fields,
+                },
+            )
+            .is_err()
+        {
+            // Report duplicate definition
+            self.report(node_ref, DiagnosticKind::DuplicateDefinition);
+            return self.emit_nop(node_ref, "duplicate definition");
+        }
+
+        // Methods are declared once the struct type is in scope, so they can refer to it
+        for member_ref in body_node.children {
+            if self.node(member_ref).kind == "fn_decl" {
+                self.declare_fn_decl(member_ref, Some(name.as_str()));
+            }
+        }
+
+        self.emit_nop(node_ref, "struct_decl")
     }
 
     fn analyze_const_decl(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        self.declare_const_decl(node_ref);
+        self.emit_nop(node_ref, "const_decl")
+    }
+
+    /// Evaluates the value of a constant and binds it in the current scope.
+    fn declare_const_decl(&mut self, node_ref: AstNodeRef) {
         let node = self.node(node_ref);
 
         let name_ref = node
@@ -207,12 +440,21 @@ impl<'a> Sema<'a> {
             .expect("Constant value not found");
         let value = self.comptime_eval_comptime_expr(value_ref);
 
+        if let (Some(expected), Some(found)) =
+            (ty.as_ref().and_then(|ty| ty.as_type()), value.value_type())
+        {
+            if !expected.is_assignable_from(&found) {
+                self.report(
+                    value_ref,
+                    DiagnosticKind::MismatchedAssignmentType(expected, found),
+                );
+            }
+        }
+
         if self.env.declare_const(node_ref, name, ty, value).is_err() {
             // Report duplicate definition
             self.report(node_ref, DiagnosticKind::DuplicateDefinition);
         }
-
-        self.emit_nop(node_ref, node.kind.as_str())
     }
 
     fn analyze_var_decl(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
@@ -236,9 +478,26 @@ impl<'a> Sema<'a> {
 
         let mxir_value_ref = value_ref.map(|value_ref| self.analyze_expr(value_ref));
 
+        // Check the initializer against the declared type, or infer the type from it
+        let declared_type = ty.as_ref().and_then(|ty| ty.as_type());
+        let binding_ty = match (&declared_type, mxir_value_ref) {
+            (Some(expected), Some(mxir_value_ref)) => {
+                self.check_type(
+                    expected,
+                    mxir_value_ref,
+                    DiagnosticKind::MismatchedAssignmentType,
+                );
+                ty.clone()
+            }
+            (None, Some(mxir_value_ref)) if ty.is_none() => {
+                self.type_of(mxir_value_ref).map(ComptimeValue::Type)
+            }
+            _ => ty.clone(),
+        };
+
         if self
             .env
-            .declare_var(node_ref, name, ty.clone(), value_ref)
+            .declare_var(node_ref, name, binding_ty.clone(), value_ref)
             .is_err()
         {
             // Report duplicate definition
@@ -246,12 +505,12 @@ impl<'a> Sema<'a> {
             return self.emit_nop(node_ref, "duplicate definition");
         }
 
-        // Emit the variable declaration
+        // Emit the variable declaration with its declared or inferred type
         self.emit(
             node_ref,
             MxirNodeData::VarDecl(MxirVarDecl {
                 name: name.to_string(),
-                ty,
+                ty: binding_ty,
                 value: mxir_value_ref,
             }),
         )
@@ -294,6 +553,18 @@ impl<'a> Sema<'a> {
         } else {
             MxirReturn(None)
         };
+
+        if let Some(Some(expected)) = self.return_types.last().cloned() {
+            if let Some(expr) = mxir_return.0 {
+                self.check_type(&expected, expr, DiagnosticKind::MismatchedReturnType);
+            } else if !expected.is_assignable_from(&Type::Void) {
+                self.report(
+                    node_ref,
+                    DiagnosticKind::MismatchedReturnType(expected, Type::Void),
+                );
+            }
+        }
+
         self.emit(node_ref, MxirNodeData::Return(mxir_return))
     }
 
@@ -304,6 +575,53 @@ impl<'a> Sema<'a> {
             .get("body")
             .copied()
             .expect("Loop statement must have a body");
+
+        // `loop i in xs { ... }` binds each value of the iterable in a scope of its own
+        if let Some(iterable_node_ref) = node.named_children.get("iterable").copied() {
+            let binding_node_ref = node
+                .named_children
+                .get("binding")
+                .copied()
+                .expect("Loop over an iterable must have a binding");
+            let binding = self.node(binding_node_ref).text;
+
+            let iterable = self.analyze_expr(iterable_node_ref);
+            let binding_ty = match self.type_of(iterable) {
+                Some(Type::Range) => Some(Type::Int32),
+                Some(Type::List(element_type)) => Some(*element_type),
+                Some(ty) => {
+                    self.report(iterable_node_ref, DiagnosticKind::NotIterable(ty));
+                    None
+                }
+                None => None,
+            };
+
+            self.env.push_scope(node.range);
+            if self
+                .env
+                .declare_var(
+                    binding_node_ref,
+                    binding.as_str(),
+                    binding_ty.map(ComptimeValue::Type),
+                    None,
+                )
+                .is_err()
+            {
+                self.report(binding_node_ref, DiagnosticKind::DuplicateDefinition);
+            }
+            let body = self.analyze_expr(body_node_ref);
+            self.env.pop_scope();
+
+            return self.emit(
+                node_ref,
+                MxirNodeData::Iterate(MxirIterate {
+                    binding,
+                    iterable,
+                    body,
+                }),
+            );
+        }
+
         let body = self.analyze_expr(body_node_ref);
         self.emit(node_ref, MxirNodeData::Loop(MxirLoop(Some(body))))
     }
@@ -316,6 +634,14 @@ impl<'a> Sema<'a> {
             .copied()
             .expect("If statement must have a condition");
         let condition = self.analyze_expr(condition_node_ref);
+        if let Some(condition_type) = self.type_of(condition) {
+            if condition_type != Type::Bool {
+                self.report(
+                    condition_node_ref,
+                    DiagnosticKind::NonBoolCondition(condition_type),
+                );
+            }
+        }
         let then_node_ref = node
             .named_children
             .get("then")
@@ -349,6 +675,17 @@ impl<'a> Sema<'a> {
         let node = self.node(node_ref);
         let lhs = self.analyze_expr(node.children[0]);
         let rhs = self.analyze_expr(node.children[1]);
+        match self.mxir.0[lhs.0 as usize].data {
+            MxirNodeData::VarExpr(_)
+            | MxirNodeData::FieldAccess(_)
+            | MxirNodeData::Index(_)
+            | MxirNodeData::KeyAccess(_)
+            | MxirNodeData::Nop(_) => {}
+            _ => self.report(node.children[0], DiagnosticKind::InvalidAssignmentTarget),
+        }
+        if let Some(expected) = self.type_of(lhs) {
+            self.check_type(&expected, rhs, DiagnosticKind::MismatchedAssignmentType);
+        }
         self.emit(node_ref, MxirNodeData::Assign(lhs, rhs))
     }
 
@@ -358,418 +695,2432 @@ impl<'a> Sema<'a> {
             "variable_expr" => self.analyze_variable_expr(node_ref),
             "call_expr" => self.analyze_call_expr(node_ref),
             "int_literal" => self.analyze_int_literal(node_ref),
+            "float_literal" => self.analyze_float_literal(node_ref),
+            "bool_literal" => self.analyze_bool_literal(node_ref),
             "string_literal" => self.analyze_string_literal(node_ref),
+            "binary_expr" => self.analyze_binary_expr(node_ref),
+            "unary_expr" => self.analyze_unary_expr(node_ref),
+            "paren_expr" => self.analyze_paren_expr(node_ref),
+            "new_expr" => self.analyze_new_expr(node_ref),
+            "member_expr" => self.analyze_member_expr(node_ref),
+            "list_literal" => self.analyze_list_literal(node_ref),
+            "index_expr" => self.analyze_index_expr(node_ref),
+            "map_literal" => self.analyze_map_literal(node_ref),
+            "range_expr" => self.analyze_range_expr(node_ref),
             "block" => self.analyze_block(node_ref),
             _ => self.emit_nop(node_ref, node.kind.as_str()),
         }
     }
 
-    fn analyze_variable_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+    fn analyze_binary_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
         let node = self.node(node_ref);
-        let name = node.text.as_str();
-        let binding = self.env.lookup(name);
-        if let Some(binding) = binding {
-            if let Some(mxir_node_data) = self.analyze_comptime_value(binding.value.clone()) {
-                self.emit(node_ref, mxir_node_data)
-            } else {
-                self.emit_nop(node_ref, "unhandled comptime value")
-            }
-        } else {
-            self.emit_nop(node_ref, "undefined variable")
+        let lhs_node_ref = node
+            .named_children
+            .get("left")
+            .copied()
+            .expect("Binary expression must have a left operand");
+        let operator_node_ref = node
+            .named_children
+            .get("operator")
+            .copied()
+            .expect("Binary expression must have an operator");
+        let rhs_node_ref = node
+            .named_children
+            .get("right")
+            .copied()
+            .expect("Binary expression must have a right operand");
+
+        let lhs = self.analyze_expr(lhs_node_ref);
+        let rhs = self.analyze_expr(rhs_node_ref);
+
+        let operator = self.node(operator_node_ref).text;
+        let Some(op) = BinaryOp::from_token(operator.as_str()) else {
+            self.report(operator_node_ref, DiagnosticKind::UnknownOperator(operator));
+            return self.emit_nop(node_ref, "unknown operator");
+        };
+
+        // Operators applied to structs are calls to their overloading method
+        if let Some(Type::Struct(struct_type)) = self.type_of(lhs) {
+            return self.analyze_operator_call(
+                node_ref,
+                &struct_type.name,
+                op.token(),
+                op.method_name(),
+                vec![lhs, rhs],
+            );
         }
+
+        let ty = match (self.type_of(lhs), self.type_of(rhs)) {
+            (Some(lhs_type), Some(rhs_type)) => {
+                let ty = binary_result_type(op, &lhs_type, &rhs_type);
+                if ty.is_none() {
+                    self.report(
+                        node_ref,
+                        DiagnosticKind::InvalidOperandTypes(
+                            op.token().to_string(),
+                            lhs_type,
+                            rhs_type,
+                        ),
+                    );
+                }
+                ty
+            }
+            _ => None,
+        };
+
+        let binary_ref = self.emit(node_ref, MxirNodeData::Binary(MxirBinary { op, lhs, rhs }));
+        self.set_type(binary_ref, ty);
+        binary_ref
     }
 
-    fn analyze_call_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+    fn analyze_unary_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
         let node = self.node(node_ref);
-        let callee_node_ref = node
+        let operator_node_ref = node
             .named_children
-            .get("callee")
+            .get("operator")
             .copied()
-            .expect("callee node not found");
-        let callee_value = self.comptime_eval_comptime_expr(callee_node_ref);
+            .expect("Unary expression must have an operator");
+        let operand_node_ref = node
+            .named_children
+            .get("operand")
+            .copied()
+            .expect("Unary expression must have an operand");
 
-        self.analyze_fn_call(node_ref, callee_value, vec![], vec![])
-    }
+        let operator = self.node(operator_node_ref).text;
+        let Some(op) = UnaryOp::from_token(operator.as_str()) else {
+            self.analyze_expr(operand_node_ref);
+            self.report(operator_node_ref, DiagnosticKind::UnknownOperator(operator));
+            return self.emit_nop(node_ref, "unknown operator");
+        };
 
-    fn analyze_comptime_value(&mut self, value: ComptimeValue) -> Option<MxirNodeData> {
-        match value {
-            ComptimeValue::VarDecl(var_decl) => Some(MxirNodeData::VarExpr(MxirVarExpr {
-                name: var_decl.name,
-            })),
-            ComptimeValue::ComptimeInt(comptime_int) => {
-                Some(MxirNodeData::IntLiteral(MxirIntLiteral {
-                    value: comptime_int,
-                }))
-            }
-            ComptimeValue::ComptimeBool(comptime_bool) => {
-                Some(MxirNodeData::BoolLiteral(MxirBoolLiteral {
-                    value: comptime_bool,
-                }))
-            }
-            ComptimeValue::ComptimeString(comptime_string) => {
-                Some(MxirNodeData::StringLiteral(MxirStringLiteral {
-                    value: comptime_string,
-                }))
-            }
-            _ => None,
+        // A negated literal is a single value, as `2147483648` alone does not fit in an `Int32`
+        if op == UnaryOp::Neg && self.node(operand_node_ref).kind == "int_literal" {
+            let int_ref = match self.int_literal(operand_node_ref, true) {
+                Some(value) => {
+                    self.emit(node_ref, MxirNodeData::IntLiteral(MxirIntLiteral { value }))
+                }
+                None => self.emit_nop(node_ref, "integer literal out of range"),
+            };
+            self.set_type(int_ref, Some(Type::Int32));
+            return int_ref;
         }
-    }
 
-    fn analyze_int_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
-        let node = self.node(node_ref);
-        let value: i128 = node.text.parse().expect("Invalid integer literal");
-        self.emit(node_ref, MxirNodeData::IntLiteral(MxirIntLiteral { value }))
+        let operand = self.analyze_expr(operand_node_ref);
+
+        // Operators applied to structs are calls to their overloading method
+        if let Some(Type::Struct(struct_type)) = self.type_of(operand) {
+            return self.analyze_operator_call(
+                node_ref,
+                &struct_type.name,
+                op.token(),
+                op.method_name(),
+                vec![operand],
+            );
+        }
+
+        let ty = match self.type_of(operand) {
+            Some(operand_type) => {
+                let ty = unary_result_type(op, &operand_type);
+                if ty.is_none() {
+                    self.report(
+                        node_ref,
+                        DiagnosticKind::InvalidOperandType(op.token().to_string(), operand_type),
+                    );
+                }
+                ty
+            }
+            None => None,
+        };
+
+        let unary_ref = self.emit(node_ref, MxirNodeData::Unary(MxirUnary { op, operand }));
+        self.set_type(unary_ref, ty);
+        unary_ref
     }
 
-    fn analyze_string_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
-        let node = self.node(node_ref);
-        let value = node.text;
-        self.emit(
-            node_ref,
-            MxirNodeData::StringLiteral(MxirStringLiteral { value }),
-        )
+    fn analyze_operator_call(
+        &mut self,
+        node_ref: AstNodeRef,
+        struct_name: &str,
+        operator: &str,
+        method_name: Option<&str>,
+        args: Vec<MxirNodeRef>,
+    ) -> MxirNodeRef {
+        let method = method_name.and_then(|method_name| {
+            self.env
+                .lookup(&format!("{}.{}", struct_name, method_name))
+                .map(|binding| binding.value.clone())
+        });
+
+        match method {
+            Some(method) => self.analyze_fn_call(node_ref, method, vec![], args),
+            None => {
+                self.report(
+                    node_ref,
+                    DiagnosticKind::MissingOperatorMethod(
+                        operator.to_string(),
+                        struct_name.to_string(),
+                        method_name.map(str::to_string),
+                    ),
+                );
+                self.emit_nop(node_ref, "missing operator method")
+            }
+        }
     }
 
-    fn comptime_eval_comptime_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+    fn analyze_paren_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
         let node = self.node(node_ref);
-        assert!(node.kind == "comptime_expr");
-
         let expr_node_ref = node
             .named_children
             .get("expr")
             .copied()
-            .expect("Expression node not found");
+            .expect("Parenthesized expression must have an inner expression");
+        self.analyze_expr(expr_node_ref)
+    }
 
-        let expr_node = self.node(expr_node_ref);
+    fn analyze_new_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let type_node_ref = node
+            .named_children
+            .get("type")
+            .copied()
+            .expect("New expression must have a type");
 
-        match expr_node.kind.as_str() {
-            "int_literal" => {
-                let value = node.text.parse().expect("Invalid integer literal");
-                ComptimeValue::ComptimeInt(value)
+        let Some(Type::Struct(struct_type)) =
+            self.comptime_eval_comptime_expr(type_node_ref).as_type()
+        else {
+            self.report(type_node_ref, DiagnosticKind::ExpectedType);
+            return self.emit_nop(node_ref, "invalid struct type");
+        };
+
+        let mut field_inits: Vec<(String, MxirNodeRef)> = vec![];
+        for field_init_ref in node.children {
+            let field_init_node = self.node(field_init_ref);
+            if field_init_node.kind != "field_init" {
+                continue;
             }
-            "string_literal" => {
-                let value = node.text.parse().expect("Invalid string literal");
-                ComptimeValue::ComptimeString(value)
+
+            let field_name_ref = field_init_node
+                .named_children
+                .get("name")
+                .copied()
+                .expect("Field name not found");
+            let field_name = self.node(field_name_ref).text;
+            let value_ref = field_init_node
+                .named_children
+                .get("value")
+                .copied()
+                .expect("Field value not found");
+            let value = self.analyze_expr(value_ref);
+
+            if field_inits.iter().any(|(name, _)| *name == field_name) {
+                self.report(field_name_ref, DiagnosticKind::DuplicateField(field_name));
+                continue;
             }
-            "variable_expr" => {
-                let name = expr_node.text.as_str();
-                if let Some(binding) = self.env.lookup(name) {
-                    binding.value.clone()
-                } else {
+
+            match struct_type.field(&field_name) {
+                Some(field) => {
+                    self.check_type(&field.ty, value, DiagnosticKind::MismatchedAssignmentType)
+                }
+                None => {
                     self.report(
-                        expr_node_ref,
-                        DiagnosticKind::SymbolNotFound(name.to_string()),
+                        field_name_ref,
+                        DiagnosticKind::UnknownField(struct_type.name.clone(), field_name),
                     );
-                    ComptimeValue::Undefined
+                    continue;
                 }
             }
-            "fn_proto" => self.comptime_eval_fn_proto(expr_node_ref),
-            _ => panic!("Unsupported comptime expression: {}", expr_node.kind),
+
+            field_inits.push((field_name, value));
+        }
+
+        // Every field must be initialized, and is stored in declaration order
+        let mut fields = vec![];
+        for field in &struct_type.fields {
+            if let Some((_, value)) = field_inits.iter().find(|(name, _)| *name == field.name) {
+                fields.push(MxirFieldInit {
+                    name: field.name.clone(),
+                    value: *value,
+                });
+            } else {
+                self.report(
+                    node_ref,
+                    DiagnosticKind::MissingField(struct_type.name.clone(), field.name.clone()),
+                );
+            }
         }
+
+        let struct_ref = self.emit(
+            node_ref,
+            MxirNodeData::StructLiteral(MxirStructLiteral {
+                name: struct_type.name.clone(),
+                fields,
+            }),
+        );
+        self.set_type(struct_ref, Some(Type::Struct(struct_type)));
+        struct_ref
     }
 
-    fn comptime_eval_fn_proto(&mut self, expr_node_ref: AstNodeRef) -> ComptimeValue {
-        let expr_node = self.node(expr_node_ref);
+    fn analyze_member_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let object_node_ref = node
+            .named_children
+            .get("object")
+            .copied()
+            .expect("Member expression must have an object");
+        let member_node_ref = node
+            .named_children
+            .get("member")
+            .copied()
+            .expect("Member expression must have a member");
+        let field = self.node(member_node_ref).text;
 
-        // Push a comptime scope for analyzing the function prototype
-        self.env.push_scope(expr_node.range);
+        let object = self.analyze_expr(object_node_ref);
 
-        let name = if let Some(name_ref) = expr_node.named_children.get("name").copied() {
-            let name = self.node(name_ref);
-            Some(name.text)
-        } else {
-            None
+        let ty = match self.type_of(object) {
+            Some(Type::Struct(struct_type)) => {
+                let field_type = struct_type.field(&field).map(|field| field.ty.clone());
+                if field_type.is_none() {
+                    self.report(
+                        member_node_ref,
+                        DiagnosticKind::UnknownField(struct_type.name.clone(), field.clone()),
+                    );
+                }
+                field_type
+            }
+            // Lists, maps and strings expose their length as a builtin property
+            Some(Type::List(_) | Type::Map(..) | Type::String) if field == "len" => {
+                Some(Type::Int32)
+            }
+            Some(ty) => {
+                self.report(
+                    member_node_ref,
+                    DiagnosticKind::UnknownField(ty.to_string(), field.clone()),
+                );
+                None
+            }
+            None => None,
         };
 
-        // Extract and bind comptime parameters
-        let comptime_params = self.bind_comptime_params(expr_node_ref, "comptime_params");
-
-        // Extract parameters using the shared function
-        let params = self.extract_params(expr_node_ref, "params");
+        let field_ref = self.emit(
+            node_ref,
+            MxirNodeData::FieldAccess(MxirFieldAccess { object, field }),
+        );
+        self.set_type(field_ref, ty);
+        field_ref
+    }
 
-        // Get the return type by evaluating the return type node with comptime params in scope
-        let return_type_ref = expr_node
-            .named_children
-            .get("return_type")
-            .copied()
-            .expect("Return type not found");
-        let return_type = self.comptime_eval_comptime_expr(return_type_ref);
+    fn analyze_list_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
 
-        // Pop the comptime scope
-        self.env.pop_scope();
+        let elements: Vec<_> = node
+            .children
+            .iter()
+            .map(|element_ref| self.analyze_expr(*element_ref))
+            .collect();
+
+        // The first element determines the element type of the list
+        let element_type = elements.first().and_then(|element| self.type_of(*element));
+        if let Some(element_type) = &element_type {
+            for element in elements.iter().skip(1) {
+                self.check_type(
+                    element_type,
+                    *element,
+                    DiagnosticKind::MismatchedElementType,
+                );
+            }
+        }
 
-        let fn_proto = Box::new(FnProto {
-            name,
-            comptime_params,
-            params,
-            return_type,
-        });
-        ComptimeValue::FnProto(fn_proto)
+        let list_ref = self.emit(
+            node_ref,
+            MxirNodeData::ListLiteral(MxirListLiteral(elements)),
+        );
+        self.set_type(list_ref, element_type.map(|ty| Type::List(Box::new(ty))));
+        list_ref
     }
 
-    // Helper function to bind comptime parameters to the current scope
-    fn bind_comptime_params(
-        &mut self,
-        proto_ref: AstNodeRef,
-        param_list_field: &str,
-    ) -> Vec<ParamDecl> {
-        let proto_node = self.node(proto_ref);
-        let mut comptime_params = vec![];
-        let mut param_names = HashSet::new();
-
-        if let Some(param_list_node_ref) = proto_node.named_children.get(param_list_field).copied()
-        {
-            let param_list_node = self.node(param_list_node_ref);
-            for param_node_ref in param_list_node.children {
-                let param_node = self.node(param_node_ref);
-
-                let param_name_ref = param_node
-                    .named_children
-                    .get("name")
-                    .copied()
-                    .expect("Param name not found");
-                let param_name_node = self.node(param_name_ref);
-                let param_name = param_name_node.text.clone();
+    fn analyze_index_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let object_node_ref = node
+            .named_children
+            .get("object")
+            .copied()
+            .expect("Index expression must have an object");
+        let index_node_ref = node
+            .named_children
+            .get("index")
+            .copied()
+            .expect("Index expression must have an index");
+
+        let object = self.analyze_expr(object_node_ref);
+        let index = self.analyze_expr(index_node_ref);
+
+        // Indexing a map looks up the value stored under a key
+        if let Some(Type::Map(key_type, value_type)) = self.type_of(object) {
+            self.check_type(&key_type, index, DiagnosticKind::MismatchedKeyType);
+            let key_access_ref = self.emit(
+                node_ref,
+                MxirNodeData::KeyAccess(MxirKeyAccess {
+                    map: object,
+                    key: index,
+                }),
+            );
+            self.set_type(key_access_ref, Some(*value_type));
+            return key_access_ref;
+        }
 
-                // Check for duplicate parameter names
-                if param_names.contains(&param_name) {
-                    self.report(param_name_ref, DiagnosticKind::DuplicateParamName);
-                } else {
-                    param_names.insert(param_name.clone());
+        // Indexing a list or string with a range slices it
+        if self.type_of(index) == Some(Type::Range) {
+            let ty = match self.type_of(object) {
+                Some(ty @ (Type::List(_) | Type::String)) => Some(ty),
+                Some(ty) => {
+                    self.report(object_node_ref, DiagnosticKind::NotIndexable(ty));
+                    None
                 }
+                None => None,
+            };
+            let slice_ref = self.emit(node_ref, MxirNodeData::Index(MxirIndex { object, index }));
+            self.set_type(slice_ref, ty);
+            return slice_ref;
+        }
 
-                let param_type_ref = param_node
-                    .named_children
-                    .get("type")
-                    .copied()
-                    .expect("Param type not found");
-                let param_ty = self.comptime_eval_comptime_expr(param_type_ref);
-
-                // Create a placeholder value for the parameter
-                // In a real implementation, this would be filled in when the function is called
-                let param_value = param_ty.clone();
-
-                // Declare the comptime parameter in the current scope
-                let _ = self.env.declare_const(
-                    param_node_ref,
-                    &param_name,
-                    Some(param_ty.clone()),
-                    param_value,
-                );
-
-                let param_decl = ParamDecl {
-                    name: param_name,
-                    ty: param_ty,
-                };
-                comptime_params.push(param_decl);
+        if let Some(index_type) = self.type_of(index) {
+            if index_type != Type::Int32 {
+                self.report(index_node_ref, DiagnosticKind::NonIntegerIndex(index_type));
             }
         }
 
-        comptime_params
+        let ty = match self.type_of(object) {
+            Some(Type::List(element_type)) => Some(*element_type),
+            Some(ty) => {
+                self.report(object_node_ref, DiagnosticKind::NotIndexable(ty));
+                None
+            }
+            None => None,
+        };
+
+        let index_ref = self.emit(node_ref, MxirNodeData::Index(MxirIndex { object, index }));
+        self.set_type(index_ref, ty);
+        index_ref
     }
 
-    fn extract_params(&mut self, proto_ref: AstNodeRef, param_list_field: &str) -> Vec<ParamDecl> {
-        let proto_node = self.node(proto_ref);
-        let mut params = vec![];
-        let mut param_names = HashSet::new();
+    fn analyze_range_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
 
-        if let Some(param_list_node_ref) = proto_node.named_children.get(param_list_field).copied()
-        {
-            let param_list_node = self.node(param_list_node_ref);
-            for param_node_ref in param_list_node.children {
-                let param_node = self.node(param_node_ref);
+        let mut bounds = [None, None];
+        for (bound, field) in bounds.iter_mut().zip(["start", "end"]) {
+            let Some(bound_node_ref) = self.range_bound(&node, field) else {
+                continue;
+            };
+            let bound_ref = self.analyze_expr(bound_node_ref);
+            self.check_type(&Type::Int32, bound_ref, |_, found| {
+                DiagnosticKind::NonIntegerRangeBound(found)
+            });
+            *bound = Some(bound_ref);
+        }
+        let [start, end] = bounds;
 
-                let param_name_ref = param_node
-                    .named_children
-                    .get("name")
-                    .copied()
-                    .expect("Param name not found");
-                let param_name_node = self.node(param_name_ref);
-                let param_name = param_name_node.text.clone();
+        // Bounds which are literals are checked right away
+        let start_value = start.and_then(|start| self.int_literal_value(start));
+        let end_value = end.and_then(|end| self.int_literal_value(end));
+        self.check_range_bounds(node_ref, start_value, end_value);
 
-                if param_names.contains(&param_name) {
-                    self.report(param_name_ref, DiagnosticKind::DuplicateParamName);
-                } else {
-                    param_names.insert(param_name.clone());
-                }
+        let range_ref = self.emit(node_ref, MxirNodeData::Range(MxirRange { start, end }));
+        self.set_type(range_ref, Some(Type::Range));
+        range_ref
+    }
 
-                let param_type_ref = param_node
-                    .named_children
-                    .get("type")
-                    .copied()
-                    .expect("Param type not found");
-                let param_ty = self.comptime_eval_comptime_expr(param_type_ref);
+    /// Returns the bound of a range expression stored in `field`, or `None` if the bound is open.
+    /// An open bound is either omitted or written as `_`.
+    fn range_bound(&self, node: &AstNode, field: &str) -> Option<AstNodeRef> {
+        let bound_ref = node.named_children.get(field).copied()?;
+        Some(bound_ref).filter(|bound_ref| self.node(*bound_ref).text != "_")
+    }
 
-                let param_decl = ParamDecl {
-                    name: param_name,
-                    ty: param_ty,
-                };
-                params.push(param_decl);
+    /// Reports range bounds known at compile time that can never describe a valid range.
+    fn check_range_bounds(&mut self, node_ref: AstNodeRef, start: Option<i128>, end: Option<i128>) {
+        for bound in [start, end].into_iter().flatten() {
+            if bound < 0 {
+                self.report(node_ref, DiagnosticKind::NegativeRangeBound(bound));
+            }
+        }
+        if let (Some(start), Some(end)) = (start, end) {
+            if start > end {
+                self.report(node_ref, DiagnosticKind::ReversedRangeBounds(start, end));
             }
         }
-
-        params
     }
 
-    fn analyze_fn_call(
-        &mut self,
-        caller_node_ref: AstNodeRef,
-        callee_value: ComptimeValue,
-        comptime_args: Vec<ComptimeValue>,
-        _args: Vec<ComptimeValue>,
-    ) -> MxirNodeRef {
-        // Check that the callee_value comptime value is a function
-        if let ComptimeValue::FnDecl(fn_decl) = callee_value {
-            let fn_decl_node_ref = fn_decl.node_ref;
-
-            self.env.push_scope(self.node_range(fn_decl_node_ref));
-            // Check that the function has the correct number of comptime parameters/args
-            if fn_decl.proto.comptime_params.len() != comptime_args.len() {
-                self.report(caller_node_ref, DiagnosticKind::IncorrectArgumentCount);
-                return self.emit_nop(caller_node_ref, "incorrect argument count");
-            }
+    /// Returns the value of an integer literal, including a negated one.
+    fn int_literal_value(&self, node_ref: MxirNodeRef) -> Option<i128> {
+        match &self.mxir.0[node_ref.0 as usize].data {
+            MxirNodeData::IntLiteral(int_literal) => Some(int_literal.value),
+            MxirNodeData::Unary(MxirUnary {
+                op: UnaryOp::Neg,
+                operand,
+            }) => self.int_literal_value(*operand).map(|value| -value),
+            _ => None,
+        }
+    }
 
-            let fn_decl_node = self.node(fn_decl.node_ref);
+    fn analyze_map_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
 
-            let proto_node_ref = fn_decl_node
+        let mut entries = Vec::with_capacity(node.children.len());
+        for entry_ref in node.children {
+            let entry_node = self.node(entry_ref);
+            let key_node_ref = entry_node
                 .named_children
-                .get("proto")
+                .get("key")
                 .copied()
-                .expect("Function proto node not found");
-            let proto_node = self.node(proto_node_ref);
-
-            let name_node_ref = proto_node
+                .expect("Map entry must have a key");
+            let value_node_ref = entry_node
                 .named_children
-                .get("name")
+                .get("value")
                 .copied()
-                .expect("Function name node not found");
-            let name = self.node(name_node_ref).text;
+                .expect("Map entry must have a value");
 
-            let block_ref = *fn_decl_node.named_children.get("body").unwrap();
-
-            let mxir_body_ref = self.analyze_node(block_ref);
-
-            let fn_decl_ref = self.generate_fn_decl(fn_decl_node_ref, name.as_str(), mxir_body_ref);
+            let key = self.analyze_expr(key_node_ref);
+            let value = self.analyze_expr(value_node_ref);
+            if let Some(key_type) = self.type_of(key) {
+                if !key_type.is_hashable() {
+                    self.report(key_node_ref, DiagnosticKind::UnhashableKeyType(key_type));
+                }
+            }
+            entries.push(MxirMapEntry { key, value });
+        }
 
-            self.env.pop_scope();
+        // The first entry determines the key and value types of the map
+        let entry_types = entries
+            .first()
+            .and_then(|entry| Some((self.type_of(entry.key)?, self.type_of(entry.value)?)));
+        if let Some((key_type, value_type)) = &entry_types {
+            for entry in entries.iter().skip(1) {
+                self.check_type(key_type, entry.key, DiagnosticKind::MismatchedKeyType);
+                self.check_type(
+                    value_type,
+                    entry.value,
+                    DiagnosticKind::MismatchedElementType,
+                );
+            }
+        }
 
-            self.emit(
-                caller_node_ref,
-                MxirNodeData::CallExpr(MxirCallExpr {
-                    fn_decl_ref,
-                    args: vec![],
-                }),
-            )
-        } else if let ComptimeValue::BuiltinFnDecl(builtin_fn_decl) = callee_value {
-            let fn_decl_ref = self.generate_builtin_fn_decl(
-                builtin_fn_decl
-                    .proto
-                    .name
-                    .expect("Builtin fn proto is missing a name")
-                    .as_str(),
-                builtin_fn_decl.f,
-            );
+        let map_ref = self.emit(node_ref, MxirNodeData::MapLiteral(MxirMapLiteral(entries)));
+        self.set_type(
+            map_ref,
+            entry_types
+                .map(|(key_type, value_type)| Type::Map(Box::new(key_type), Box::new(value_type))),
+        );
+        map_ref
+    }
 
-            self.emit(
-                caller_node_ref,
-                MxirNodeData::CallExpr(MxirCallExpr {
-                    fn_decl_ref,
-                    args: vec![],
-                }),
-            )
+    fn analyze_variable_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let name = node.text.as_str();
+        let binding = self.env.lookup(name);
+        if let Some(binding) = binding {
+            self.emit_comptime_value(node_ref, binding.value.clone())
+                .unwrap_or_else(|| self.emit_nop(node_ref, "unhandled comptime value"))
         } else {
-            self.report(caller_node_ref, DiagnosticKind::InvalidFunctionCall);
-            self.emit_nop(caller_node_ref, "invalid function call")
+            self.report(node_ref, DiagnosticKind::SymbolNotFound(name.to_string()));
+            self.emit_nop(node_ref, "undefined variable")
         }
     }
 
-    fn generate_fn_decl(
-        &mut self,
-        fn_decl_node_ref: AstNodeRef,
-        name: &str,
-        mxir_body_ref: MxirNodeRef,
-    ) -> MxirNodeRef {
-        self.emit(
-            fn_decl_node_ref,
-            MxirNodeData::FnDecl(MxirFnDecl {
-                name: name.to_string(),
-                body: mxir_body_ref,
-            }),
-        )
-    }
-
-    fn generate_builtin_fn_decl(&mut self, name: &str, fn_: fn() -> ()) -> MxirNodeRef {
-        self.emit(
-            AstNodeRef(0),
-            MxirNodeData::BuiltinFnDecl(MxirBuiltinFnDecl {
-                name: name.to_string(),
-                fn_,
-            }),
-        )
-    }
+    fn analyze_call_expr(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let callee_node_ref = node
+            .named_children
+            .get("callee")
+            .copied()
+            .expect("callee node not found");
+        let callee_value = self.comptime_eval_comptime_expr(callee_node_ref);
 
-    fn emit(&mut self, ast_node: AstNodeRef, data: MxirNodeData) -> MxirNodeRef {
-        let self_ref = MxirNodeRef(self.mxir.0.len() as u32);
-        let mxir_node = MxirNode {
-            self_ref,
-            ast_node,
-            data,
+        let args = if let Some(args_node_ref) = node.named_children.get("args").copied() {
+            let args_node = self.node(args_node_ref);
+            args_node
+                .children
+                .iter()
+                .map(|arg_node_ref| self.analyze_expr(*arg_node_ref))
+                .collect()
+        } else {
+            vec![]
         };
-        self.mxir.0.push(mxir_node);
-        self_ref
-    }
 
-    fn emit_nop(&mut self, ast_node: AstNodeRef, msg: &str) -> MxirNodeRef {
-        let self_ref = MxirNodeRef(self.mxir.0.len() as u32);
-        let mxir_node = MxirNode {
-            self_ref,
-            ast_node,
-            data: MxirNodeData::Nop(msg.to_string()),
-        };
-        self.mxir.0.push(mxir_node);
-        self_ref
+        self.analyze_fn_call(node_ref, callee_value, vec![], args)
     }
 
-    fn report(&mut self, node_ref: AstNodeRef, diag_kind: DiagnosticKind) {
-        let node = self.node(node_ref);
-        let diag = Diagnostic {
-            path: self.file.path().to_string(),
-            range: node.range,
-            kind: diag_kind,
-        };
-        self.diagnostics.push(diag);
+    /// Lowers a compile-time value into MXIR, typed with the type of the value.
+    fn emit_comptime_value(
+        &mut self,
+        node_ref: AstNodeRef,
+        value: ComptimeValue,
+    ) -> Option<MxirNodeRef> {
+        let ty = value.value_type();
+        let mxir_node_data = self.analyze_comptime_value(node_ref, value)?;
+        let value_ref = self.emit(node_ref, mxir_node_data);
+        self.set_type(value_ref, ty);
+        Some(value_ref)
     }
 
-    fn node(&self, node_ref: AstNodeRef) -> AstNode {
-        self.file.node(node_ref).expect("Node not found")
-    }
+    fn analyze_comptime_value(
+        &mut self,
+        node_ref: AstNodeRef,
+        value: ComptimeValue,
+    ) -> Option<MxirNodeData> {
+        match value {
+            ComptimeValue::VarDecl(var_decl) => Some(MxirNodeData::VarExpr(MxirVarExpr {
+                name: var_decl.name,
+            })),
+            ComptimeValue::ComptimeInt(comptime_int) => {
+                Some(MxirNodeData::IntLiteral(MxirIntLiteral {
+                    value: comptime_int,
+                }))
+            }
+            ComptimeValue::ComptimeFloat(comptime_float) => {
+                Some(MxirNodeData::FloatLiteral(MxirFloatLiteral {
+                    value: comptime_float,
+                }))
+            }
+            ComptimeValue::ComptimeBool(comptime_bool) => {
+                Some(MxirNodeData::BoolLiteral(MxirBoolLiteral {
+                    value: comptime_bool,
+                }))
+            }
+            ComptimeValue::ComptimeString(comptime_string) => {
+                Some(MxirNodeData::StringLiteral(MxirStringLiteral {
+                    value: comptime_string,
+                }))
+            }
+            ComptimeValue::ComptimeRange(start, end) => {
+                let start = start.and_then(|start| {
+                    self.emit_comptime_value(node_ref, ComptimeValue::ComptimeInt(start))
+                });
+                let end = end.and_then(|end| {
+                    self.emit_comptime_value(node_ref, ComptimeValue::ComptimeInt(end))
+                });
+                Some(MxirNodeData::Range(MxirRange { start, end }))
+            }
+            ComptimeValue::Map(comptime_entries) => {
+                let mut entries = Vec::with_capacity(comptime_entries.len());
+                for (key, value) in comptime_entries {
+                    let key = self.emit_comptime_value(node_ref, key)?;
+                    let value = self.emit_comptime_value(node_ref, value)?;
+                    entries.push(MxirMapEntry { key, value });
+                }
+                Some(MxirNodeData::MapLiteral(MxirMapLiteral(entries)))
+            }
+            ComptimeValue::Struct(struct_type, values) => {
+                let mut fields = Vec::with_capacity(values.len());
+                for (field, value) in struct_type.fields.iter().zip(values) {
+                    fields.push(MxirFieldInit {
+                        name: field.name.clone(),
+                        value: self.emit_comptime_value(node_ref, value)?,
+                    });
+                }
+                Some(MxirNodeData::StructLiteral(MxirStructLiteral {
+                    name: struct_type.name,
+                    fields,
+                }))
+            }
+            _ => None,
+        }
+    }
+
+    fn analyze_int_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let int_ref = match self.int_literal(node_ref, false) {
+            Some(value) => self.emit(node_ref, MxirNodeData::IntLiteral(MxirIntLiteral { value })),
+            None => self.emit_nop(node_ref, "integer literal out of range"),
+        };
+        self.set_type(int_ref, Some(Type::Int32));
+        int_ref
+    }
+
+    /// Parses an integer literal, reporting literals that do not fit in an `Int32`. A negated
+    /// literal is parsed with its sign, so that `-2147483648` fits.
+    fn int_literal(&mut self, node_ref: AstNodeRef, negated: bool) -> Option<i128> {
+        let text = self.node(node_ref).text;
+        let text = if negated { format!("-{}", text) } else { text };
+        match text.parse::<i32>() {
+            Ok(value) => Some(value.into()),
+            Err(_) => {
+                self.report(node_ref, DiagnosticKind::IntegerLiteralOutOfRange(text));
+                None
+            }
+        }
+    }
+
+    fn analyze_bool_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let value = node.text == "true";
+        let bool_ref = self.emit(
+            node_ref,
+            MxirNodeData::BoolLiteral(MxirBoolLiteral { value }),
+        );
+        self.set_type(bool_ref, Some(Type::Bool));
+        bool_ref
+    }
+
+    fn analyze_float_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let value: f64 = node.text.parse().expect("Invalid float literal");
+        let float_ref = self.emit(
+            node_ref,
+            MxirNodeData::FloatLiteral(MxirFloatLiteral { value }),
+        );
+        self.set_type(float_ref, Some(Type::Float));
+        float_ref
+    }
+
+    /// Lowers a string literal to the concatenation of its text and its `${expr}` segments.
+    fn analyze_string_literal(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let interpolations = self.string_interpolations(node_ref);
+
+        let mut parts = vec![];
+        for segment in self.decode_string_literal(node_ref, &interpolations) {
+            let part_ref = match segment {
+                StringSegment::Text(value) => self.emit_string_literal(node_ref, value),
+                StringSegment::Interpolation(i) => self.analyze_interpolation(interpolations[i]),
+            };
+            parts.push(part_ref);
+        }
+
+        let mut parts = parts.into_iter();
+        let first = parts
+            .next()
+            .unwrap_or_else(|| self.emit_string_literal(node_ref, String::new()));
+        parts.fold(first, |lhs, rhs| {
+            let concat_ref = self.emit(
+                node_ref,
+                MxirNodeData::Binary(MxirBinary {
+                    op: BinaryOp::Add,
+                    lhs,
+                    rhs,
+                }),
+            );
+            self.set_type(concat_ref, Some(Type::String));
+            concat_ref
+        })
+    }
+
+    fn analyze_interpolation(&mut self, node_ref: AstNodeRef) -> MxirNodeRef {
+        let node = self.node(node_ref);
+        let expr_node_ref = node
+            .named_children
+            .get("expr")
+            .or(node.children.first())
+            .copied()
+            .expect("Interpolation must have an expression");
+
+        let value = self.analyze_expr(expr_node_ref);
+        match self.type_of(value) {
+            Some(Type::String) => value,
+            ty => {
+                if let Some(ty @ (Type::Void | Type::Fn(_))) = ty {
+                    self.report(expr_node_ref, DiagnosticKind::NotStringConvertible(ty));
+                }
+                let string_ref = self.emit(node_ref, MxirNodeData::Stringify(value));
+                self.set_type(string_ref, Some(Type::String));
+                string_ref
+            }
+        }
+    }
+
+    /// Returns the `${expr}` segments of a string literal in source order.
+    fn string_interpolations(&self, node_ref: AstNodeRef) -> Vec<AstNodeRef> {
+        self.node(node_ref)
+            .children
+            .into_iter()
+            .filter(|child_ref| self.node(*child_ref).kind == "interpolation")
+            .collect()
+    }
+
+    /// Strips the quotes of a string literal and decodes its escape sequences, reporting invalid
+    /// escapes at their own range.
+    fn decode_string_literal(
+        &mut self,
+        node_ref: AstNodeRef,
+        interpolations: &[AstNodeRef],
+    ) -> Vec<StringSegment> {
+        let node = self.node(node_ref);
+        let holes: Vec<_> = interpolations
+            .iter()
+            .map(|interpolation| self.node_range(*interpolation))
+            .collect();
+
+        let (segments, errors) = string_literal::decode(&node.text, node.range.start, &holes);
+        for error in errors {
+            self.report_at(
+                error.range,
+                DiagnosticKind::InvalidEscapeSequence(error.sequence),
+            );
+        }
+        segments
+    }
+
+    fn emit_string_literal(&mut self, node_ref: AstNodeRef, value: String) -> MxirNodeRef {
+        let string_ref = self.emit(
+            node_ref,
+            MxirNodeData::StringLiteral(MxirStringLiteral { value }),
+        );
+        self.set_type(string_ref, Some(Type::String));
+        string_ref
+    }
+
+    fn comptime_eval_comptime_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+        assert!(node.kind == "comptime_expr");
+
+        let expr_node_ref = node
+            .named_children
+            .get("expr")
+            .copied()
+            .expect("Expression node not found");
+
+        // Each compile-time expression has its own steps, shared with the functions it calls
+        if self.comptime_depth == 0 {
+            self.comptime_steps = 0;
+        }
+        self.comptime_eval_expr(expr_node_ref)
+    }
+
+    fn comptime_eval_expr(&mut self, expr_node_ref: AstNodeRef) -> ComptimeValue {
+        let expr_node = self.node(expr_node_ref);
+        if !self.comptime_step(expr_node_ref) {
+            return ComptimeValue::Undefined;
+        }
+
+        match expr_node.kind.as_str() {
+            "int_literal" => match self.int_literal(expr_node_ref, false) {
+                Some(value) => ComptimeValue::ComptimeInt(value),
+                None => ComptimeValue::Undefined,
+            },
+            "float_literal" => {
+                let value = expr_node.text.parse().expect("Invalid float literal");
+                ComptimeValue::ComptimeFloat(value)
+            }
+            "bool_literal" => ComptimeValue::ComptimeBool(expr_node.text == "true"),
+            "string_literal" => {
+                let interpolations = self.string_interpolations(expr_node_ref);
+                let mut value = String::new();
+                for segment in self.decode_string_literal(expr_node_ref, &interpolations) {
+                    match segment {
+                        StringSegment::Text(text) => value.push_str(&text),
+                        StringSegment::Interpolation(i) => {
+                            self.report(interpolations[i], DiagnosticKind::ExpectedComptimeValue);
+                            return ComptimeValue::Undefined;
+                        }
+                    }
+                }
+                ComptimeValue::ComptimeString(value)
+            }
+            "variable_expr" => {
+                let name = expr_node.text.as_str();
+                if let Some(binding) = self.env.lookup(name) {
+                    binding.value.clone()
+                } else {
+                    self.report(
+                        expr_node_ref,
+                        DiagnosticKind::SymbolNotFound(name.to_string()),
+                    );
+                    ComptimeValue::Undefined
+                }
+            }
+            "fn_proto" => self.comptime_eval_fn_proto(expr_node_ref),
+            "map_literal" => self.comptime_eval_map_literal(expr_node_ref),
+            "new_expr" => self.comptime_eval_new_expr(expr_node_ref),
+            "range_expr" => self.comptime_eval_range_expr(expr_node_ref),
+            "paren_expr" => {
+                let inner_node_ref = expr_node
+                    .named_children
+                    .get("expr")
+                    .copied()
+                    .expect("Parenthesized expression must have an inner expression");
+                self.comptime_eval_expr(inner_node_ref)
+            }
+            "binary_expr" => self.comptime_eval_binary_expr(expr_node_ref),
+            "unary_expr" => self.comptime_eval_unary_expr(expr_node_ref),
+            "call_expr" => self.comptime_eval_call_expr(expr_node_ref),
+            _ => {
+                self.report(expr_node_ref, DiagnosticKind::ExpectedComptimeValue);
+                ComptimeValue::Undefined
+            }
+        }
+    }
+
+    /// Evaluates the operand of an operator or condition, which must be a constant. Returns
+    /// `None` once a diagnostic has been reported for it.
+    fn comptime_eval_operand(&mut self, node_ref: AstNodeRef) -> Option<ComptimeValue> {
+        match self.comptime_eval_expr(node_ref) {
+            ComptimeValue::Undefined => None,
+            value if value.is_constant() => Some(value),
+            _ => {
+                self.report(node_ref, DiagnosticKind::ExpectedComptimeValue);
+                None
+            }
+        }
+    }
+
+    fn comptime_eval_binary_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+        let lhs_node_ref = node
+            .named_children
+            .get("left")
+            .copied()
+            .expect("Binary expression must have a left operand");
+        let operator_node_ref = node
+            .named_children
+            .get("operator")
+            .copied()
+            .expect("Binary expression must have an operator");
+        let rhs_node_ref = node
+            .named_children
+            .get("right")
+            .copied()
+            .expect("Binary expression must have a right operand");
+
+        let operator = self.node(operator_node_ref).text;
+        let Some(op) = BinaryOp::from_token(operator.as_str()) else {
+            self.report(operator_node_ref, DiagnosticKind::UnknownOperator(operator));
+            return ComptimeValue::Undefined;
+        };
+
+        let lhs = self.comptime_eval_operand(lhs_node_ref);
+        // `and` and `or` only evaluate their right operand when the left one does not decide
+        if let Some(ComptimeValue::ComptimeBool(value)) = lhs {
+            if (op == BinaryOp::And && !value) || (op == BinaryOp::Or && value) {
+                return ComptimeValue::ComptimeBool(value);
+            }
+        }
+        let rhs = self.comptime_eval_operand(rhs_node_ref);
+        let (Some(lhs), Some(rhs)) = (lhs, rhs) else {
+            return ComptimeValue::Undefined;
+        };
+
+        match apply_binary_op(op, &lhs, &rhs) {
+            Ok(value) => value,
+            Err(ComptimeError::InvalidOperands) => {
+                let kind = match (lhs.value_type(), rhs.value_type()) {
+                    (Some(lhs_type), Some(rhs_type)) => DiagnosticKind::InvalidOperandTypes(
+                        op.token().to_string(),
+                        lhs_type,
+                        rhs_type,
+                    ),
+                    _ => DiagnosticKind::ExpectedComptimeValue,
+                };
+                self.report(node_ref, kind);
+                ComptimeValue::Undefined
+            }
+            Err(error) => self.report_comptime_error(node_ref, error),
+        }
+    }
+
+    fn comptime_eval_unary_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+        let operator_node_ref = node
+            .named_children
+            .get("operator")
+            .copied()
+            .expect("Unary expression must have an operator");
+        let operand_node_ref = node
+            .named_children
+            .get("operand")
+            .copied()
+            .expect("Unary expression must have an operand");
+
+        let operator = self.node(operator_node_ref).text;
+        let Some(op) = UnaryOp::from_token(operator.as_str()) else {
+            self.report(operator_node_ref, DiagnosticKind::UnknownOperator(operator));
+            return ComptimeValue::Undefined;
+        };
+
+        if op == UnaryOp::Neg && self.node(operand_node_ref).kind == "int_literal" {
+            return match self.int_literal(operand_node_ref, true) {
+                Some(value) => ComptimeValue::ComptimeInt(value),
+                None => ComptimeValue::Undefined,
+            };
+        }
+
+        let Some(operand) = self.comptime_eval_operand(operand_node_ref) else {
+            return ComptimeValue::Undefined;
+        };
+
+        match apply_unary_op(op, &operand) {
+            Ok(value) => value,
+            Err(ComptimeError::InvalidOperands) => {
+                let kind = match operand.value_type() {
+                    Some(operand_type) => {
+                        DiagnosticKind::InvalidOperandType(op.token().to_string(), operand_type)
+                    }
+                    None => DiagnosticKind::ExpectedComptimeValue,
+                };
+                self.report(node_ref, kind);
+                ComptimeValue::Undefined
+            }
+            Err(error) => self.report_comptime_error(node_ref, error),
+        }
+    }
+
+    fn report_comptime_error(
+        &mut self,
+        node_ref: AstNodeRef,
+        error: ComptimeError,
+    ) -> ComptimeValue {
+        let kind = match error {
+            ComptimeError::DivisionByZero => DiagnosticKind::ComptimeDivisionByZero,
+            ComptimeError::IntegerOverflow => DiagnosticKind::ComptimeIntegerOverflow,
+            ComptimeError::InvalidOperands => DiagnosticKind::ExpectedComptimeValue,
+        };
+        self.report(node_ref, kind);
+        ComptimeValue::Undefined
+    }
+
+    fn comptime_eval_call_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+        let callee_node_ref = node
+            .named_children
+            .get("callee")
+            .copied()
+            .expect("callee node not found");
+        let callee_value = self.comptime_eval_comptime_expr(callee_node_ref);
+
+        let comptime_args = self.comptime_eval_args(&node, "comptime_args");
+        let args = self.comptime_eval_args(&node, "args");
+
+        match callee_value {
+            ComptimeValue::FnDecl(fn_decl) => {
+                let (Some(comptime_args), Some(args)) = (comptime_args, args) else {
+                    return ComptimeValue::Undefined;
+                };
+                self.comptime_eval_fn_call(node_ref, &fn_decl, comptime_args, args)
+            }
+            ComptimeValue::Undefined => ComptimeValue::Undefined,
+            // Builtins and host functions only run once the program does
+            ComptimeValue::BuiltinFnDecl(_) => {
+                self.report(node_ref, DiagnosticKind::ExpectedComptimeValue);
+                ComptimeValue::Undefined
+            }
+            _ => {
+                self.report(node_ref, DiagnosticKind::InvalidFunctionCall);
+                ComptimeValue::Undefined
+            }
+        }
+    }
+
+    /// Evaluates the arguments in the given list of a call, along with the node of each. Returns
+    /// `None` if any of them could not be evaluated.
+    fn comptime_eval_args(
+        &mut self,
+        node: &AstNode,
+        arg_list_field: &str,
+    ) -> Option<Vec<(AstNodeRef, ComptimeValue)>> {
+        let Some(args_node_ref) = node.named_children.get(arg_list_field).copied() else {
+            return Some(vec![]);
+        };
+
+        let arg_node_refs = self.node(args_node_ref).children;
+        let mut args = Vec::with_capacity(arg_node_refs.len());
+        for arg_node_ref in arg_node_refs.iter().copied() {
+            if let Some(arg) = self.comptime_eval_operand(arg_node_ref) {
+                args.push((arg_node_ref, arg));
+            }
+        }
+
+        (args.len() == arg_node_refs.len()).then_some(args)
+    }
+
+    /// Evaluates a call at compile time by running the body of the function with its parameters
+    /// bound to the arguments.
+    fn comptime_eval_fn_call(
+        &mut self,
+        caller_node_ref: AstNodeRef,
+        fn_decl: &FnDecl,
+        comptime_args: Vec<(AstNodeRef, ComptimeValue)>,
+        args: Vec<(AstNodeRef, ComptimeValue)>,
+    ) -> ComptimeValue {
+        if fn_decl.proto.comptime_params.len() != comptime_args.len()
+            || fn_decl.proto.params.len() != args.len()
+        {
+            self.report(caller_node_ref, DiagnosticKind::IncorrectArgumentCount);
+            return ComptimeValue::Undefined;
+        }
+
+        if self.comptime_depth == MAX_COMPTIME_CALL_DEPTH {
+            self.report(
+                caller_node_ref,
+                DiagnosticKind::ComptimeCallDepthExceeded(MAX_COMPTIME_CALL_DEPTH),
+            );
+            // Use up the steps as well, so the calls being unwound stop without further reports
+            self.comptime_steps = MAX_COMPTIME_STEPS + 1;
+            return ComptimeValue::Undefined;
+        }
+
+        for (param, (arg_node_ref, arg)) in fn_decl
+            .proto
+            .comptime_params
+            .iter()
+            .chain(&fn_decl.proto.params)
+            .zip(comptime_args.iter().chain(&args))
+        {
+            if let (Some(expected), Some(found)) = (param.ty.as_type(), arg.value_type()) {
+                if !expected.is_assignable_from(&found) {
+                    self.report(
+                        *arg_node_ref,
+                        DiagnosticKind::MismatchedArgumentType(expected, found),
+                    );
+                }
+            }
+        }
+
+        // Like its lowering, the body sees the scopes the function was declared in
+        let (declaring_scopes, _) = self
+            .fn_scopes
+            .get(&fn_decl.node_ref)
+            .cloned()
+            .unwrap_or_else(|| (self.env.scopes(), None));
+        let caller_scopes = self.env.replace_scopes(declaring_scopes);
+        self.env.push_scope(self.node_range(fn_decl.node_ref));
+
+        // Parameters are bound as constants holding the arguments. Duplicate names were already
+        // reported when the function was declared.
+        for (param, (_, arg)) in fn_decl
+            .proto
+            .comptime_params
+            .iter()
+            .chain(&fn_decl.proto.params)
+            .zip(comptime_args.into_iter().chain(args))
+        {
+            let _ =
+                self.env
+                    .declare_const(param.node_ref, &param.name, Some(param.ty.clone()), arg);
+        }
+
+        let return_type = fn_decl.proto.return_type.as_type();
+        self.return_types.push(return_type.clone());
+        self.comptime_depth += 1;
+        let flow = self.comptime_exec_stmt(fn_decl.body_ref);
+        self.comptime_depth -= 1;
+        self.return_types.pop();
+        self.env.pop_scope();
+        self.env.replace_scopes(caller_scopes);
+
+        match flow {
+            ComptimeFlow::Return(value) => value,
+            ComptimeFlow::Next => {
+                // Only a function that returns Void may finish without returning a value
+                if return_type != Some(Type::Void) {
+                    self.report(caller_node_ref, DiagnosticKind::ExpectedComptimeValue);
+                }
+                ComptimeValue::Undefined
+            }
+            ComptimeFlow::Abort => ComptimeValue::Undefined,
+        }
+    }
+
+    /// Runs a statement in the body of a function called at compile time.
+    fn comptime_exec_stmt(&mut self, node_ref: AstNodeRef) -> ComptimeFlow {
+        let node = self.node(node_ref);
+        if !self.comptime_step(node_ref) {
+            return ComptimeFlow::Abort;
+        }
+
+        match node.kind.as_str() {
+            "block" => {
+                self.env.push_scope(node.range);
+                let mut flow = ComptimeFlow::Next;
+                for child_ref in node.children {
+                    flow = self.comptime_exec_stmt(child_ref);
+                    if !matches!(flow, ComptimeFlow::Next) {
+                        break;
+                    }
+                }
+                self.env.pop_scope();
+                flow
+            }
+            "return_stmt" => {
+                let Some(expr_node_ref) = node.named_children.get("expr").copied() else {
+                    return ComptimeFlow::Return(ComptimeValue::Undefined);
+                };
+                let value = self.comptime_eval_expr(expr_node_ref);
+                if let (Some(Some(expected)), Some(found)) =
+                    (self.return_types.last().cloned(), value.value_type())
+                {
+                    if !expected.is_assignable_from(&found) {
+                        self.report(
+                            expr_node_ref,
+                            DiagnosticKind::MismatchedReturnType(expected, found),
+                        );
+                    }
+                }
+                match value {
+                    ComptimeValue::Undefined => ComptimeFlow::Abort,
+                    value => ComptimeFlow::Return(value),
+                }
+            }
+            "if_stmt" => {
+                let condition_node_ref = node
+                    .named_children
+                    .get("condition")
+                    .copied()
+                    .expect("If statement must have a condition");
+                let branch = match self.comptime_eval_operand(condition_node_ref) {
+                    Some(ComptimeValue::ComptimeBool(true)) => node.named_children.get("then"),
+                    Some(ComptimeValue::ComptimeBool(false)) => node.named_children.get("else"),
+                    Some(condition) => {
+                        if let Some(condition_type) = condition.value_type() {
+                            self.report(
+                                condition_node_ref,
+                                DiagnosticKind::NonBoolCondition(condition_type),
+                            );
+                        }
+                        return ComptimeFlow::Abort;
+                    }
+                    None => return ComptimeFlow::Abort,
+                };
+                match branch.copied() {
+                    Some(branch_node_ref) => self.comptime_exec_stmt(branch_node_ref),
+                    None => ComptimeFlow::Next,
+                }
+            }
+            "const_decl" => {
+                self.declare_const_decl(node_ref);
+                ComptimeFlow::Next
+            }
+            "expr_stmt" => {
+                let expr_node_ref = node
+                    .named_children
+                    .get("expr")
+                    .copied()
+                    .expect("Expression statement must have an expression");
+                self.comptime_eval_expr(expr_node_ref);
+                ComptimeFlow::Next
+            }
+            _ => {
+                self.report(node_ref, DiagnosticKind::ExpectedComptimeValue);
+                ComptimeFlow::Abort
+            }
+        }
+    }
+
+    /// Counts a statement or expression evaluated at compile time, reporting once the expression
+    /// being evaluated runs out of steps. Returns whether evaluation may go on.
+    fn comptime_step(&mut self, node_ref: AstNodeRef) -> bool {
+        self.comptime_steps += 1;
+        if self.comptime_steps == MAX_COMPTIME_STEPS + 1 {
+            self.report(
+                node_ref,
+                DiagnosticKind::ComptimeStepLimitExceeded(MAX_COMPTIME_STEPS),
+            );
+        }
+        self.comptime_steps <= MAX_COMPTIME_STEPS
+    }
+
+    fn comptime_eval_range_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+
+        let mut bounds = [None, None];
+        for (bound, field) in bounds.iter_mut().zip(["start", "end"]) {
+            let Some(bound_node_ref) = self.range_bound(&node, field) else {
+                continue;
+            };
+            match self.comptime_eval_expr(bound_node_ref) {
+                ComptimeValue::ComptimeInt(value) => *bound = Some(value),
+                value => {
+                    if let Some(ty) = value.value_type() {
+                        self.report(bound_node_ref, DiagnosticKind::NonIntegerRangeBound(ty));
+                    }
+                }
+            }
+        }
+        let [start, end] = bounds;
+
+        self.check_range_bounds(node_ref, start, end);
+        ComptimeValue::ComptimeRange(start, end)
+    }
+
+    fn comptime_eval_map_literal(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+
+        let mut entries = Vec::with_capacity(node.children.len());
+        for entry_ref in node.children {
+            let entry_node = self.node(entry_ref);
+            let key_node_ref = entry_node
+                .named_children
+                .get("key")
+                .copied()
+                .expect("Map entry must have a key");
+            let value_node_ref = entry_node
+                .named_children
+                .get("value")
+                .copied()
+                .expect("Map entry must have a value");
+
+            let key = self.comptime_eval_expr(key_node_ref);
+            if let Some(key_type) = key.value_type() {
+                if !key_type.is_hashable() {
+                    self.report(key_node_ref, DiagnosticKind::UnhashableKeyType(key_type));
+                }
+            }
+            let value = self.comptime_eval_expr(value_node_ref);
+            entries.push((key, value));
+        }
+
+        ComptimeValue::Map(entries)
+    }
+
+    fn comptime_eval_new_expr(&mut self, node_ref: AstNodeRef) -> ComptimeValue {
+        let node = self.node(node_ref);
+        let type_node_ref = node
+            .named_children
+            .get("type")
+            .copied()
+            .expect("New expression must have a type");
+
+        let Some(Type::Struct(struct_type)) =
+            self.comptime_eval_comptime_expr(type_node_ref).as_type()
+        else {
+            self.report(type_node_ref, DiagnosticKind::ExpectedType);
+            return ComptimeValue::Undefined;
+        };
+
+        // Fields whose value is not a constant have been reported, and are kept as `None`
+        let mut field_inits: Vec<(String, Option<ComptimeValue>)> = vec![];
+        for field_init_ref in node.children {
+            let field_init_node = self.node(field_init_ref);
+            if field_init_node.kind != "field_init" {
+                continue;
+            }
+
+            let field_name_ref = field_init_node
+                .named_children
+                .get("name")
+                .copied()
+                .expect("Field name not found");
+            let field_name = self.node(field_name_ref).text;
+            let value_ref = field_init_node
+                .named_children
+                .get("value")
+                .copied()
+                .expect("Field value not found");
+            let value = self.comptime_eval_operand(value_ref);
+
+            if field_inits.iter().any(|(name, _)| *name == field_name) {
+                self.report(field_name_ref, DiagnosticKind::DuplicateField(field_name));
+                continue;
+            }
+
+            match struct_type.field(&field_name) {
+                Some(field) => {
+                    if let Some(found) = value.as_ref().and_then(|value| value.value_type()) {
+                        if !field.ty.is_assignable_from(&found) {
+                            self.report(
+                                value_ref,
+                                DiagnosticKind::MismatchedAssignmentType(field.ty.clone(), found),
+                            );
+                        }
+                    }
+                }
+                None => {
+                    self.report(
+                        field_name_ref,
+                        DiagnosticKind::UnknownField(struct_type.name.clone(), field_name),
+                    );
+                    continue;
+                }
+            }
+
+            field_inits.push((field_name, value));
+        }
+
+        // Every field must be initialized, and is stored in declaration order
+        let mut values = vec![];
+        let mut complete = true;
+        for field in &struct_type.fields {
+            match field_inits.iter().position(|(name, _)| *name == field.name) {
+                Some(i) => match field_inits.swap_remove(i).1 {
+                    Some(value) => values.push(value),
+                    None => complete = false,
+                },
+                None => {
+                    self.report(
+                        node_ref,
+                        DiagnosticKind::MissingField(struct_type.name.clone(), field.name.clone()),
+                    );
+                    complete = false;
+                }
+            }
+        }
+
+        if !complete {
+            return ComptimeValue::Undefined;
+        }
+        ComptimeValue::Struct(struct_type, values)
+    }
+
+    fn comptime_eval_fn_proto(&mut self, expr_node_ref: AstNodeRef) -> ComptimeValue {
+        let expr_node = self.node(expr_node_ref);
+
+        // Push a comptime scope for analyzing the function prototype
+        self.env.push_scope(expr_node.range);
+
+        let name = if let Some(name_ref) = expr_node.named_children.get("name").copied() {
+            let name = self.node(name_ref);
+            Some(name.text)
+        } else {
+            None
+        };
+
+        // Extract and bind comptime parameters
+        let comptime_params = self.bind_comptime_params(expr_node_ref, "comptime_params");
+
+        // Extract parameters using the shared function
+        let params = self.extract_params(expr_node_ref, "params");
+
+        // Get the return type by evaluating the return type node with comptime params in scope
+        let return_type_ref = expr_node
+            .named_children
+            .get("return_type")
+            .copied()
+            .expect("Return type not found");
+        let return_type = self.comptime_eval_comptime_expr(return_type_ref);
+
+        // Pop the comptime scope
+        self.env.pop_scope();
+
+        let fn_proto = Box::new(FnProto {
+            name,
+            comptime_params,
+            params,
+            return_type,
+        });
+        ComptimeValue::FnProto(fn_proto)
+    }
+
+    // Helper function to bind comptime parameters to the current scope
+    fn bind_comptime_params(
+        &mut self,
+        proto_ref: AstNodeRef,
+        param_list_field: &str,
+    ) -> Vec<ParamDecl> {
+        let proto_node = self.node(proto_ref);
+        let mut comptime_params = vec![];
+        let mut param_names = HashSet::new();
+
+        if let Some(param_list_node_ref) = proto_node.named_children.get(param_list_field).copied()
+        {
+            let param_list_node = self.node(param_list_node_ref);
+            for param_node_ref in param_list_node.children {
+                let param_node = self.node(param_node_ref);
+
+                let param_name_ref = param_node
+                    .named_children
+                    .get("name")
+                    .copied()
+                    .expect("Param name not found");
+                let param_name_node = self.node(param_name_ref);
+                let param_name = param_name_node.text.clone();
+
+                // Check for duplicate parameter names
+                if param_names.contains(&param_name) {
+                    self.report(param_name_ref, DiagnosticKind::DuplicateParamName);
+                } else {
+                    param_names.insert(param_name.clone());
+                }
+
+                let param_type_ref = param_node
+                    .named_children
+                    .get("type")
+                    .copied()
+                    .expect("Param type not found");
+                let param_ty = self.comptime_eval_comptime_expr(param_type_ref);
+
+                // Create a placeholder value for the parameter
+                // In a real implementation, this would be filled in when the function is called
+                let param_value = param_ty.clone();
+
+                // Declare the comptime parameter in the current scope
+                let _ = self.env.declare_const(
+                    param_node_ref,
+                    &param_name,
+                    Some(param_ty.clone()),
+                    param_value,
+                );
+
+                let param_decl = ParamDecl {
+                    node_ref: param_node_ref,
+                    name: param_name,
+                    ty: param_ty,
+                };
+                comptime_params.push(param_decl);
+            }
+        }
+
+        comptime_params
+    }
+
+    fn extract_params(&mut self, proto_ref: AstNodeRef, param_list_field: &str) -> Vec<ParamDecl> {
+        let proto_node = self.node(proto_ref);
+        let mut params = vec![];
+        let mut param_names = HashSet::new();
+
+        if let Some(param_list_node_ref) = proto_node.named_children.get(param_list_field).copied()
+        {
+            let param_list_node = self.node(param_list_node_ref);
+            for param_node_ref in param_list_node.children {
+                let param_node = self.node(param_node_ref);
+
+                let param_name_ref = param_node
+                    .named_children
+                    .get("name")
+                    .copied()
+                    .expect("Param name not found");
+                let param_name_node = self.node(param_name_ref);
+                let param_name = param_name_node.text.clone();
+
+                if param_names.contains(&param_name) {
+                    self.report(param_name_ref, DiagnosticKind::DuplicateParamName);
+                } else {
+                    param_names.insert(param_name.clone());
+                }
+
+                let param_type_ref = param_node
+                    .named_children
+                    .get("type")
+                    .copied()
+                    .expect("Param type not found");
+                let param_ty = self.comptime_eval_comptime_expr(param_type_ref);
+
+                let param_decl = ParamDecl {
+                    node_ref: param_node_ref,
+                    name: param_name,
+                    ty: param_ty,
+                };
+                params.push(param_decl);
+            }
+        }
+
+        params
+    }
+
+    fn analyze_fn_call(
+        &mut self,
+        caller_node_ref: AstNodeRef,
+        callee_value: ComptimeValue,
+        comptime_args: Vec<ComptimeValue>,
+        args: Vec<MxirNodeRef>,
+    ) -> MxirNodeRef {
+        // Check that the callee_value comptime value is a function
+        if let ComptimeValue::FnDecl(fn_decl) = callee_value {
+            let fn_decl_node_ref = fn_decl.node_ref;
+
+            // Check that the function has the correct number of comptime parameters/args
+            if fn_decl.proto.comptime_params.len() != comptime_args.len() {
+                self.report(caller_node_ref, DiagnosticKind::IncorrectArgumentCount);
+                return self.emit_nop(caller_node_ref, "incorrect argument count");
+            }
+
+            // Check that the function has the correct number of runtime parameters/args
+            if fn_decl.proto.params.len() != args.len() {
+                self.report(caller_node_ref, DiagnosticKind::IncorrectArgumentCount);
+                return self.emit_nop(caller_node_ref, "incorrect argument count");
+            }
+
+            // Check that each argument matches the type of its parameter
+            for (param, arg) in fn_decl.proto.params.iter().zip(&args) {
+                if let Some(expected) = param.ty.as_type() {
+                    self.check_type(&expected, *arg, DiagnosticKind::MismatchedArgumentType);
+                }
+            }
+
+            let fn_decl_ref = if let Some(fn_decl_ref) = self.fn_decls.get(&fn_decl_node_ref) {
+                *fn_decl_ref
+            } else {
+                self.analyze_fn_body(&fn_decl)
+            };
+
+            let call_ref = self.emit(
+                caller_node_ref,
+                MxirNodeData::CallExpr(MxirCallExpr { fn_decl_ref, args }),
+            );
+            self.set_type(call_ref, fn_decl.proto.return_type.as_type());
+            call_ref
+        } else if let ComptimeValue::BuiltinFnDecl(builtin_fn_decl) = callee_value {
+            // Variadic builtins accept any arguments, the rest are checked like other functions
+            if !builtin_fn_decl.variadic {
+                if builtin_fn_decl.proto.params.len() != args.len() {
+                    self.report(caller_node_ref, DiagnosticKind::IncorrectArgumentCount);
+                    return self.emit_nop(caller_node_ref, "incorrect argument count");
+                }
+
+                for (param, arg) in builtin_fn_decl.proto.params.iter().zip(&args) {
+                    if let Some(expected) = param.ty.as_type() {
+                        self.check_type(&expected, *arg, DiagnosticKind::MismatchedArgumentType);
+                    }
+                }
+            }
+
+            let fn_decl_ref = self.generate_builtin_fn_decl(
+                builtin_fn_decl
+                    .proto
+                    .name
+                    .as_deref()
+                    .expect("Builtin fn proto is missing a name"),
+                builtin_fn_decl.f.clone(),
+            );
+
+            let call_ref = self.emit(
+                caller_node_ref,
+                MxirNodeData::CallExpr(MxirCallExpr { fn_decl_ref, args }),
+            );
+            self.set_type(call_ref, builtin_fn_decl.proto.return_type.as_type());
+            call_ref
+        } else {
+            self.report(caller_node_ref, DiagnosticKind::InvalidFunctionCall);
+            self.emit_nop(caller_node_ref, "invalid function call")
+        }
+    }
+
+    fn analyze_fn_body(&mut self, fn_decl: &FnDecl) -> MxirNodeRef {
+        let fn_decl_node_ref = fn_decl.node_ref;
+        let fn_decl_node = self.node(fn_decl_node_ref);
+
+        let name = fn_decl
+            .proto
+            .name
+            .clone()
+            .expect("Function proto is missing a name");
+
+        let params = fn_decl
+            .proto
+            .params
+            .iter()
+            .map(|param| MxirParamDecl {
+                name: param.name.clone(),
+                ty: param.ty.clone(),
+            })
+            .collect();
+
+        // The body sees the scopes the function was declared in, not those of the caller
+        let (declaring_scopes, enclosing) = self
+            .fn_scopes
+            .get(&fn_decl_node_ref)
+            .cloned()
+            .unwrap_or_else(|| (self.env.scopes(), None));
+
+        // Emit the declaration before its body so that recursive calls can refer to it
+        let fn_decl_ref = self.generate_fn_decl(fn_decl_node_ref, name.as_str(), params, enclosing);
+        self.fn_decls.insert(fn_decl_node_ref, fn_decl_ref);
+
+        let caller_scopes = self.env.replace_scopes(declaring_scopes);
+        self.env.push_scope(self.node_range(fn_decl_node_ref));
+        self.return_types.push(fn_decl.proto.return_type.as_type());
+        self.enclosing_fns.push(fn_decl_ref);
+
+        // Bind the runtime parameters as variables of the function scope
+        for param in &fn_decl.proto.params {
+            if self
+                .env
+                .declare_var(param.node_ref, &param.name, Some(param.ty.clone()), None)
+                .is_err()
+            {
+                self.report(param.node_ref, DiagnosticKind::DuplicateParamName);
+            }
+        }
+
+        let block_ref = *fn_decl_node.named_children.get("body").unwrap();
+
+        let mxir_body_ref = self.analyze_node(block_ref);
+
+        self.enclosing_fns.pop();
+        self.return_types.pop();
+        self.env.pop_scope();
+        self.env.replace_scopes(caller_scopes);
+
+        // Update the function declaration with the analyzed body
+        let mxir_fn_decl_node = self.mxir.0.get_mut(fn_decl_ref.0 as usize).unwrap();
+        if let MxirNodeData::FnDecl(mxir_fn_decl) = &mut mxir_fn_decl_node.data {
+            mxir_fn_decl.body = mxir_body_ref;
+        }
+
+        fn_decl_ref
+    }
+
+    fn generate_fn_decl(
+        &mut self,
+        fn_decl_node_ref: AstNodeRef,
+        name: &str,
+        params: Vec<MxirParamDecl>,
+        enclosing: Option<MxirNodeRef>,
+    ) -> MxirNodeRef {
+        // The body is filled in once it has been analyzed
+        let body = MxirNodeRef(self.mxir.0.len() as u32);
+        self.emit(
+            fn_decl_node_ref,
+            MxirNodeData::FnDecl(MxirFnDecl {
+                name: name.to_string(),
+                params,
+                body,
+                enclosing,
+            }),
+        )
+    }
+
+    fn generate_builtin_fn_decl(&mut self, name: &str, fn_: BuiltinFn) -> MxirNodeRef {
+        self.emit(
+            AstNodeRef(0),
+            MxirNodeData::BuiltinFnDecl(MxirBuiltinFnDecl {
+                name: name.to_string(),
+                fn_,
+            }),
+        )
+    }
+
+    fn set_type(&mut self, node_ref: MxirNodeRef, ty: Option<Type>) {
+        if let Some(ty) = ty {
+            self.types.insert(node_ref, ty);
+        }
+    }
+
+    fn type_of(&self, node_ref: MxirNodeRef) -> Option<Type> {
+        self.types.get(&node_ref).cloned()
+    }
+
+    /// Reports the diagnostic built by `kind` if the type of the value is known and cannot be
+    /// assigned to `expected`.
+    fn check_type(
+        &mut self,
+        expected: &Type,
+        value_ref: MxirNodeRef,
+        kind: fn(Type, Type) -> DiagnosticKind,
+    ) {
+        let Some(found) = self.type_of(value_ref) else {
+            return;
+        };
+
+        if !expected.is_assignable_from(&found) {
+            let ast_node = self.mxir.0[value_ref.0 as usize].ast_node;
+            self.report(ast_node, kind(expected.clone(), found));
+        }
+    }
+
+    fn emit(&mut self, ast_node: AstNodeRef, data: MxirNodeData) -> MxirNodeRef {
+        let self_ref = MxirNodeRef(self.mxir.0.len() as u32);
+        let mxir_node = MxirNode {
+            self_ref,
+            ast_node,
+            data,
+        };
+        self.mxir.0.push(mxir_node);
+        self_ref
+    }
+
+    fn emit_nop(&mut self, ast_node: AstNodeRef, msg: &str) -> MxirNodeRef {
+        let self_ref = MxirNodeRef(self.mxir.0.len() as u32);
+        let mxir_node = MxirNode {
+            self_ref,
+            ast_node,
+            data: MxirNodeData::Nop(msg.to_string()),
+        };
+        self.mxir.0.push(mxir_node);
+        self_ref
+    }
+
+    fn report(&mut self, node_ref: AstNodeRef, diag_kind: DiagnosticKind) {
+        let node = self.node(node_ref);
+        self.report_at(node.range, diag_kind);
+    }
+
+    fn report_at(&mut self, range: Range, diag_kind: DiagnosticKind) {
+        if !self.reported.insert((diag_kind.clone(), range)) {
+            return;
+        }
+        self.diagnostics.push(Diagnostic {
+            path: self.file.path().to_string(),
+            range,
+            kind: diag_kind,
+        });
+    }
+
+    fn node(&self, node_ref: AstNodeRef) -> AstNode {
+        self.file.node(node_ref).expect("Node not found")
+    }
+
+    fn node_range(&self, node_ref: AstNodeRef) -> Range {
+        self.node(node_ref).range
+    }
+}
+
+fn binary_result_type(op: BinaryOp, lhs: &Type, rhs: &Type) -> Option<Type> {
+    match (op, lhs, rhs) {
+        (BinaryOp::Add, Type::String, Type::String) => Some(Type::String),
+        (
+            BinaryOp::Add
+            | BinaryOp::Sub
+            | BinaryOp::Mul
+            | BinaryOp::Div
+            | BinaryOp::BitAnd
+            | BinaryOp::BitOr
+            | BinaryOp::BitXor
+            | BinaryOp::Shl
+            | BinaryOp::Shr,
+            Type::Int32,
+            Type::Int32,
+        ) => Some(Type::Int32),
+        // Arithmetic with a float operand is done in floating point
+        (BinaryOp::Add | BinaryOp::Sub | BinaryOp::Mul | BinaryOp::Div, lhs, rhs)
+            if lhs.is_numeric() && rhs.is_numeric() =>
+        {
+            Some(Type::Float)
+        }
+        // Only scalars compare by value; lists, maps, ranges and fns have no equality
+        (BinaryOp::Eq | BinaryOp::Neq, Type::Bool, Type::Bool)
+        | (BinaryOp::Eq | BinaryOp::Neq, Type::String, Type::String) => Some(Type::Bool),
+        (
+            BinaryOp::Eq
+            | BinaryOp::Neq
+            | BinaryOp::Lt
+            | BinaryOp::Gt
+            | BinaryOp::Lte
+            | BinaryOp::Gte,
+            lhs,
+            rhs,
+        ) if lhs.is_numeric() && rhs.is_numeric() => Some(Type::Bool),
+        (BinaryOp::And | BinaryOp::Or, Type::Bool, Type::Bool) => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+fn unary_result_type(op: UnaryOp, operand: &Type) -> Option<Type> {
+    match (op, operand) {
+        (UnaryOp::Neg, Type::Int32) => Some(Type::Int32),
+        (UnaryOp::Neg, Type::Float) => Some(Type::Float),
+        (UnaryOp::Not, Type::Bool) => Some(Type::Bool),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::position::Point;
+    use crate::source_file::UnparsedSourceFile;
+
+    fn analyze_source(source: &str) -> (Mxir, Vec<Diagnostic>) {
+        // Parse the source into a parsed source file
+        let unparsed = UnparsedSourceFile::new("test.mx", source);
+        let parsed = unparsed.parse();
+
+        // Perform semantic analysis
+        let sema = Sema::new(&parsed);
+        sema.analyze()
+    }
+
+    #[test]
+    fn test_simple_function_declaration() {
+        let source = "fn main(): 0 { }";
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics - main function exists
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_missing_main_function() {
+        let source = "fn other(): 0 { }";
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should report a missing entrypoint function
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::MissingEntrypointFunction
+        );
+    }
+
+    #[test]
+    fn test_function_with_return_type() {
+        let source = "fn main(): 42 { }";
+        let (_, diagnostics) = analyze_source(source);
+
+        // The return type is a comptime int, which should parse successfully
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_const_declaration() {
+        let source = r#"
+            const MY_CONST: 42 = 42;
+            fn main(): 0 { }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_var_declaration() {
+        let source = r#"
+            fn main(): 0 {
+                var x: 42 = 10;
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    #[should_panic]
+    fn test_nested_blocks() {
+        let source = r#"
+            fn main(): 0 {
+                {
+                    var x: 42 = 10;
+                    {
+                        var y: 42 = 20;
+                    }
+                }
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_multiple_function_declarations() {
+        let source = r#"
+            fn helper(): 0 {
+                // Helper function
+            }
+
+            fn main(): 0 {
+                // Main function
+            }
+
+            fn another(): 0 {
+                // Another function
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics - main function exists
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_combined_declarations() {
+        let source = r#"
+            const PI: 42 = 3;
+
+            fn calculate(): 0 {
+                var radius: 42 = 5;
+                var area: 42 = 0;
+            }
+
+            fn main(): 0 {
+                var value: 42 = 10;
+                {
+                    var temp: 42 = 20;
+                }
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics - main function exists
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_function_in_function() {
+        let source = r#"
+            fn main(): 0 {
+                fn inner(): 0 {
+                    // This is not allowed in MX - functions should be at top level
+                }
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // The current implementation doesn't check for nested functions,
+        // but this test is added for when that validation is implemented
+        // For now, this will pass without diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_complex_return_type() {
+        let source = r#"
+            fn complex(): 42 {
+                // Function with complex return type
+            }
 
-    fn node_range(&self, node_ref: AstNodeRef) -> Range {
-        self.node(node_ref).range
+            fn main(): 0 {
+                // Main function
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::source_file::UnparsedSourceFile;
+    #[test]
+    fn test_comptime_expressions() {
+        let source = r#"
+            const TYPE: 42 = 42;
 
-    fn analyze_source(source: &str) -> (Mxir, Vec<Diagnostic>) {
-        // Parse the source into a parsed source file
-        let unparsed = UnparsedSourceFile::new("test.mx", source);
-        let parsed = unparsed.parse();
+            fn main(): TYPE {
+                // Main function with comptime return type
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
 
-        // Perform semantic analysis
-        let sema = Sema::new(&parsed);
-        sema.analyze()
+        // The current implementation doesn't fully resolve comptime expressions,
+        // so this should pass without diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
     }
 
     #[test]
-    fn test_simple_function_declaration() {
-        let source = "fn main(): 0 { }";
+    fn test_comptime_evaluation() {
+        let source = r#"
+            const SIZE = (2 + 3) * 4;
+            const BIG = SIZE > 10 and not false;
+            const GREETING = "hello" + " world";
+
+            fn factorial(n: Int32): Int32 {
+                if n == 0 {
+                    return 1;
+                } else {
+                    return n * factorial(n - 1);
+                }
+            }
+
+            const FACTORIAL_5 = factorial(5);
+
+            fn area(side: Int32): Int32 {
+                const SQUARE = side * side;
+                return SQUARE;
+            }
+
+            const AREA = area(3);
+
+            fn main(): Int32 {
+                return FACTORIAL_5 + SIZE + AREA;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+        let has_int = |expected: i128| {
+            mxir.0.iter().any(|node| {
+                matches!(node.data, MxirNodeData::IntLiteral(MxirIntLiteral { value }) if value == expected)
+            })
+        };
+        assert!(has_int(120));
+        assert!(has_int(20));
+        assert!(has_int(9));
+
+        // Constants declared while a function is evaluated at compile time emit no MXIR
+        let const_decls = mxir
+            .0
+            .iter()
+            .filter(|node| matches!(&node.data, MxirNodeData::Nop(msg) if msg == "const_decl"))
+            .count();
+        assert_eq!(const_decls, 5);
+    }
+
+    #[test]
+    fn test_comptime_struct_instances() {
+        let source = r#"
+            struct Person {
+                var name: String;
+                var age: Int32;
+            }
+
+            const bob = new Person { name: "Bob", age: 40 + 2 };
+            const eve = new Person { name: "Eve" };
+
+            fn main(): Int32 {
+                var x = 1;
+                const ada = new Person { name: "Ada", age: x };
+                return bob.age;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MissingField("Person".to_string(), "age".to_string()),
+                DiagnosticKind::ExpectedComptimeValue,
+            ]
+        );
+
+        // The instance is emitted where the constant is used, with its fields evaluated
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::StructLiteral(struct_literal) if struct_literal.name == "Person"
+        )));
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::IntLiteral(int_literal) if int_literal.value == 42
+        )));
+    }
+
+    #[test]
+    fn test_comptime_diagnostics() {
+        let source = r#"
+            const A = 1 / 0;
+            const B = 1 + "one";
+            const C = not 1;
+
+            fn forever(n: Int32): Int32 {
+                return forever(n + 1);
+            }
+
+            const D = forever(0);
+
+            fn greet(): Void {
+                println("hi");
+            }
+
+            const E = greet();
+            const E_AGAIN = greet();
+
+            fn main(): 0 {
+                var x = 1;
+                const F = x + 1;
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // `greet` is evaluated twice, but the builtin call in it is reported once
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::ComptimeDivisionByZero,
+                DiagnosticKind::InvalidOperandTypes("+".to_string(), Type::Int32, Type::String),
+                DiagnosticKind::InvalidOperandType("not".to_string(), Type::Int32),
+                DiagnosticKind::ComptimeCallDepthExceeded(MAX_COMPTIME_CALL_DEPTH),
+                DiagnosticKind::ExpectedComptimeValue,
+                DiagnosticKind::ExpectedComptimeValue,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_environment_scoping() {
+        let source = r#"
+            const GLOBAL: 42 = 100;
+
+            fn main(): 0 {
+                const LOCAL: 42 = 200;
+                {
+                    const NESTED: 42 = 300;
+                }
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_function_with_body() {
+        let source = r#"
+            fn main(): 0 {
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        // Should not report any diagnostics
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_binary_and_unary_expressions() {
+        let source = r#"
+            fn main(): 0 {
+                var x = 1 + 2 * 3;
+                var y = not (x == 7 or x < 0);
+                return -x;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+
+        // Each operator should be lowered to its own MXIR node
+        for op in [
+            BinaryOp::Add,
+            BinaryOp::Mul,
+            BinaryOp::Eq,
+            BinaryOp::Or,
+            BinaryOp::Lt,
+        ] {
+            assert!(
+                mxir.0.iter().any(
+                    |node| matches!(&node.data, MxirNodeData::Binary(binary) if binary.op == op)
+                ),
+                "Missing binary node for {:?}",
+                op
+            );
+        }
+        for op in [UnaryOp::Not, UnaryOp::Neg] {
+            assert!(
+                mxir.0
+                    .iter()
+                    .any(|node| matches!(&node.data, MxirNodeData::Unary(unary) if unary.op == op)),
+                "Missing unary node for {:?}",
+                op
+            );
+        }
+    }
+
+    #[test]
+    fn test_call_with_arguments() {
+        let source = r#"
+            fn add(a: Int32, b: Int32): Int32 {
+                return a + b;
+            }
+
+            fn main(): 0 {
+                return add(1, 2);
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+
+        let fn_decl = mxir
+            .0
+            .iter()
+            .find_map(|node| match &node.data {
+                MxirNodeData::FnDecl(fn_decl) if fn_decl.name == "add" => Some(fn_decl),
+                _ => None,
+            })
+            .expect("Missing fn decl for add");
+        let param_names: Vec<_> = fn_decl.params.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(param_names, vec!["a", "b"]);
+
+        // The parameters are visible as variables inside the body
+        assert!(mxir.0.iter().any(
+            |node| matches!(&node.data, MxirNodeData::VarExpr(var_expr) if var_expr.name == "a")
+        ));
+
+        assert!(mxir.0.iter().any(
+            |node| matches!(&node.data, MxirNodeData::CallExpr(call_expr) if call_expr.args.len() == 2)
+        ));
+    }
+
+    #[test]
+    fn test_incorrect_argument_count() {
+        let source = r#"
+            fn add(a: Int32, b: Int32): Int32 {
+                return a + b;
+            }
+
+            fn main(): 0 {
+                return add(1);
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind, DiagnosticKind::IncorrectArgumentCount);
+    }
+
+    #[test]
+    fn test_recursive_call() {
+        let source = r#"
+            fn countdown(n: Int32): Int32 {
+                if n == 0 {
+                    return 0;
+                }
+                return countdown(n - 1);
+            }
+
+            fn main(): 0 {
+                return countdown(3);
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+
+        // The recursive call reuses the declaration instead of lowering the body again
+        let fn_decl_count = mxir
+            .0
+            .iter()
+            .filter(|node| matches!(&node.data, MxirNodeData::FnDecl(fn_decl) if fn_decl.name == "countdown"))
+            .count();
+        assert_eq!(fn_decl_count, 1);
+    }
+
+    #[test]
+    fn test_well_typed_program() {
+        let source = r#"
+            fn greet(name: String): String {
+                return "Hello " + name;
+            }
+
+            fn main(): Int32 {
+                var count: Int32 = 0;
+                var message = greet("Bob");
+                if count < 10 and message != "" {
+                    count = count + 1;
+                }
+                return count;
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        assert!(
+            diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            diagnostics
+        );
+    }
+
+    #[test]
+    fn test_mismatched_assignment_type() {
+        let source = r#"
+            fn main(): 0 {
+                var x: Int32 = "hello";
+                var y = 1;
+                y = "world";
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MismatchedAssignmentType(Type::Int32, Type::String),
+                DiagnosticKind::MismatchedAssignmentType(Type::Int32, Type::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_mismatched_return_type() {
+        let source = r#"
+            fn main(): Int32 {
+                return "hello";
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::MismatchedReturnType(Type::Int32, Type::String)
+        );
+    }
+
+    #[test]
+    fn test_non_bool_if_condition() {
+        let source = r#"
+            fn main(): 0 {
+                var x = 1;
+                if x {
+                }
+            }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
+
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::NonBoolCondition(Type::Int32)
+        );
+    }
+
+    #[test]
+    fn test_bool_literals() {
+        let source = r#"
+            const DEBUG = false;
+
+            fn main(): 0 {
+                var enabled: Bool = not DEBUG and true;
+                if true or enabled {
+                    println("on");
+                }
+            }
+        "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics - main function exists
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
@@ -778,237 +3129,737 @@ mod tests {
     }
 
     #[test]
-    fn test_missing_main_function() {
-        let source = "fn other(): 0 { }";
+    fn test_mismatched_argument_type() {
+        let source = r#"
+            fn square(n: Int32): Int32 {
+                return n * n;
+            }
+
+            fn main(): Int32 {
+                return square("four");
+            }
+        "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should report a missing entrypoint function
         assert_eq!(diagnostics.len(), 1);
         assert_eq!(
             diagnostics[0].kind,
-            DiagnosticKind::MissingEntrypointFunction
+            DiagnosticKind::MismatchedArgumentType(Type::Int32, Type::String)
         );
     }
 
     #[test]
-    fn test_function_with_return_type() {
-        let source = "fn main(): 42 { }";
+    fn test_invalid_operand_types() {
+        let source = r#"
+            fn main(): 0 {
+                var x = 1 + "one";
+            }
+        "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // The return type is a comptime int, which should parse successfully
-        assert!(
-            diagnostics.is_empty(),
-            "Unexpected diagnostics: {:?}",
-            diagnostics
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::InvalidOperandTypes("+".to_string(), Type::Int32, Type::String)
         );
     }
 
     #[test]
-    fn test_const_declaration() {
+    fn test_equality_on_scalars_only() {
         let source = r#"
-            const MY_CONST: 42 = 42;
-            fn main(): 0 { }
+            fn main(): 0 {
+                var same: Bool = "a" == "a" and true != false and 1 == 1.0;
+                var lists = [1] == [1];
+                var ranges = (0 to 2) != (0 to 2);
+            }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
-        assert!(
-            diagnostics.is_empty(),
-            "Unexpected diagnostics: {:?}",
-            diagnostics
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::InvalidOperandTypes(
+                    "==".to_string(),
+                    Type::List(Box::new(Type::Int32)),
+                    Type::List(Box::new(Type::Int32)),
+                ),
+                DiagnosticKind::InvalidOperandTypes("!=".to_string(), Type::Range, Type::Range),
+            ]
         );
     }
 
     #[test]
-    fn test_var_declaration() {
+    fn test_int_literal_out_of_range() {
         let source = r#"
+            const BIG = 3000000000;
+            const MIN = -2147483648;
+
             fn main(): 0 {
-                var x: 42 = 10;
+                var max = 2147483647;
+                var over = 2147483648;
+                var min = -2147483648;
+                var under = -2147483649;
             }
         "#;
-        let (_, diagnostics) = analyze_source(source);
+        let (mxir, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
-        assert!(
-            diagnostics.is_empty(),
-            "Unexpected diagnostics: {:?}",
-            diagnostics
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::IntegerLiteralOutOfRange("3000000000".to_string()),
+                DiagnosticKind::IntegerLiteralOutOfRange("2147483648".to_string()),
+                DiagnosticKind::IntegerLiteralOutOfRange("-2147483649".to_string()),
+            ]
         );
+
+        // The negated literal is lowered as a single value rather than a negation
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::IntLiteral(int_literal) if int_literal.value == i32::MIN as i128
+        )));
     }
 
     #[test]
-    #[should_panic]
-    fn test_nested_blocks() {
+    fn test_float_arithmetic() {
         let source = r#"
+            const PI = 3.14159;
+
             fn main(): 0 {
-                {
-                    var x: 42 = 10;
-                    {
-                        var y: 42 = 20;
-                    }
-                }
+                var area: Float = PI * 2 * 2;
+                var half = 1 / 2.0;
+                var smaller: Bool = half < 1 and -half != 0;
+                var invalid = 1.5 & 2;
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(
+            diagnostics[0].kind,
+            DiagnosticKind::InvalidOperandTypes("&".to_string(), Type::Float, Type::Int32)
+        );
+    }
+
+    #[test]
+    fn test_struct_instantiation_and_member_access() {
+        let source = r#"
+            struct Person {
+                var name: String;
+                var age: Int32;
+            }
+
+            fn main(): Int32 {
+                var bob = new Person { name: "Bob", age: 42 };
+                bob.age = bob.age + 1;
+                return bob.age;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        let struct_literal = mxir
+            .0
+            .iter()
+            .find_map(|node| match &node.data {
+                MxirNodeData::StructLiteral(struct_literal) => Some(struct_literal),
+                _ => None,
+            })
+            .expect("Missing struct literal");
+        assert_eq!(struct_literal.name, "Person");
+        let field_names: Vec<_> = struct_literal
+            .fields
+            .iter()
+            .map(|field| field.name.as_str())
+            .collect();
+        assert_eq!(field_names, vec!["name", "age"]);
+
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::FieldAccess(field_access) if field_access.field == "age"
+        )));
     }
 
     #[test]
-    fn test_multiple_function_declarations() {
+    fn test_struct_field_diagnostics() {
         let source = r#"
-            fn helper(): 0 {
-                // Helper function
+            struct Person {
+                var name: String;
+                var age: Int32;
             }
 
             fn main(): 0 {
-                // Main function
+                var bob = new Person { name: 42, height: 180 };
+                var x = bob.weight;
             }
+        "#;
+        let (_, diagnostics) = analyze_source(source);
 
-            fn another(): 0 {
-                // Another function
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MismatchedAssignmentType(Type::String, Type::Int32),
+                DiagnosticKind::UnknownField("Person".to_string(), "height".to_string()),
+                DiagnosticKind::MissingField("Person".to_string(), "age".to_string()),
+                DiagnosticKind::UnknownField("Person".to_string(), "weight".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_nested_member_assignment_type() {
+        let source = r#"
+            struct Inner {
+                var c: Int32;
+            }
+
+            struct Outer {
+                var b: Inner;
+            }
+
+            fn reset(a: Outer): 0 {
+                a.b.c = "y";
+            }
+
+            fn main(): 0 {
+                var a = new Outer { b: new Inner { c: 1 } };
+                a.b.c = "x";
+                a.b = 2;
+                reset(a);
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics - main function exists
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MismatchedAssignmentType(Type::Int32, Type::String),
+                DiagnosticKind::MismatchedAssignmentType(
+                    Type::Struct(Box::new(StructType {
+                        name: "Inner".to_string(),
+                        fields: vec![StructField {
+                            name: "c".to_string(),
+                            ty: Type::Int32,
+                        }],
+                    })),
+                    Type::Int32
+                ),
+                DiagnosticKind::MismatchedAssignmentType(Type::Int32, Type::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_operator_overloading() {
+        let source = r#"
+            struct Vec2 {
+                var x: Int32;
+                var y: Int32;
+
+                fn add(self: Vec2, other: Vec2): Vec2 {
+                    return new Vec2 { x: self.x + other.x, y: self.y + other.y };
+                }
+
+                fn eq(self: Vec2, other: Vec2): Bool {
+                    return self.x == other.x and self.y == other.y;
+                }
+            }
+
+            fn main(): Int32 {
+                var a = new Vec2 { x: 1, y: 2 };
+                var b = a + a;
+                if b == a {
+                    return 1;
+                }
+                return b.x;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        // The operators are lowered to calls to the methods
+        for name in ["Vec2.add", "Vec2.eq"] {
+            let fn_decl_ref = mxir
+                .0
+                .iter()
+                .find(|node| matches!(&node.data, MxirNodeData::FnDecl(fn_decl) if fn_decl.name == name))
+                .map(|node| node.self_ref)
+                .unwrap_or_else(|| panic!("Missing fn decl for {}", name));
+            assert!(mxir.0.iter().any(|node| matches!(
+                &node.data,
+                MxirNodeData::CallExpr(call_expr)
+                    if call_expr.fn_decl_ref == fn_decl_ref && call_expr.args.len() == 2
+            )));
+        }
     }
 
     #[test]
-    fn test_combined_declarations() {
+    fn test_missing_operator_method() {
         let source = r#"
-            const PI: 42 = 3;
-
-            fn calculate(): 0 {
-                var radius: 42 = 5;
-                var area: 42 = 0;
+            struct Vec2 {
+                var x: Int32;
+                var y: Int32;
             }
 
             fn main(): 0 {
-                var value: 42 = 10;
-                {
-                    var temp: 42 = 20;
-                }
+                var a = new Vec2 { x: 1, y: 2 };
+                var b = a * a;
+                var c = not a;
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics - main function exists
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MissingOperatorMethod(
+                    "*".to_string(),
+                    "Vec2".to_string(),
+                    Some("mul".to_string())
+                ),
+                DiagnosticKind::MissingOperatorMethod(
+                    "not".to_string(),
+                    "Vec2".to_string(),
+                    Some("logical_not".to_string())
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_list_literal_and_index() {
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3];
+                xs[0] = xs[1] + xs.len;
+                return xs[0];
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::ListLiteral(MxirListLiteral(elements)) if elements.len() == 3
+        )));
+        assert_eq!(
+            mxir.0
+                .iter()
+                .filter(|node| matches!(node.data, MxirNodeData::Index(_)))
+                .count(),
+            3
+        );
     }
 
     #[test]
-    fn test_function_in_function() {
+    fn test_list_diagnostics() {
         let source = r#"
             fn main(): 0 {
-                fn inner(): 0 {
-                    // This is not allowed in MX - functions should be at top level
-                }
+                var xs = [1, "two"];
+                var a = xs["zero"];
+                var b = 42[0];
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // The current implementation doesn't check for nested functions,
-        // but this test is added for when that validation is implemented
-        // For now, this will pass without diagnostics
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MismatchedElementType(Type::Int32, Type::String),
+                DiagnosticKind::NonIntegerIndex(Type::String),
+                DiagnosticKind::NotIndexable(Type::Int32),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_map_literal_lookup_and_insertion() {
+        let source = r#"
+            const LIMITS = map{"retries": 3, "timeout": 30};
+
+            fn main(): Int32 {
+                var names = map{1: "one", 2: "two"};
+                names[3] = "three";
+                var greeting = names[1];
+                return LIMITS["retries"] + names.len;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        assert_eq!(
+            mxir.0
+                .iter()
+                .filter(|node| matches!(
+                    &node.data,
+                    MxirNodeData::MapLiteral(MxirMapLiteral(entries)) if entries.len() == 2
+                ))
+                .count(),
+            2
+        );
+        assert_eq!(
+            mxir.0
+                .iter()
+                .filter(|node| matches!(node.data, MxirNodeData::KeyAccess(_)))
+                .count(),
+            3
+        );
     }
 
     #[test]
-    fn test_complex_return_type() {
+    fn test_map_diagnostics() {
         let source = r#"
-            fn complex(): 42 {
-                // Function with complex return type
-            }
-
             fn main(): 0 {
-                // Main function
+                var bad = map{[1]: 2};
+                var names = map{1: "one", "two": 2};
+                var x = names["one"];
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::UnhashableKeyType(Type::List(Box::new(Type::Int32))),
+                DiagnosticKind::MismatchedKeyType(Type::Int32, Type::String),
+                DiagnosticKind::MismatchedElementType(Type::String, Type::Int32),
+                DiagnosticKind::MismatchedKeyType(Type::Int32, Type::String),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_range_slicing_and_iteration() {
+        let source = r#"
+            const DIGITS = 0 to 10;
+
+            fn main(): Int32 {
+                var xs = [1, 2, 3, 4];
+                var head = xs[_ to 2];
+                var tail = xs[2 to _];
+                var name = "hello"[1 to 3];
+                var total = 0;
+                loop i in DIGITS {
+                    total = total + i;
+                }
+                loop x in head {
+                    total = total + x;
+                }
+                return total;
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        let open_bounds: Vec<_> = mxir
+            .0
+            .iter()
+            .filter_map(|node| match &node.data {
+                MxirNodeData::Range(range) => Some((range.start.is_some(), range.end.is_some())),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            open_bounds,
+            vec![(false, true), (true, false), (true, true), (true, true)]
+        );
+        let bindings: Vec<_> = mxir
+            .0
+            .iter()
+            .filter_map(|node| match &node.data {
+                MxirNodeData::Iterate(iterate) => Some(iterate.binding.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(bindings, vec!["i", "x"]);
     }
 
     #[test]
-    fn test_comptime_expressions() {
+    fn test_range_diagnostics() {
         let source = r#"
-            const TYPE: 42 = 42;
+            const BACKWARDS = 10 to 1;
 
-            fn main(): TYPE {
-                // Main function with comptime return type
+            fn main(): 0 {
+                var xs = [1, 2, 3];
+                var a = xs[3 to 1];
+                var b = xs[-1 to _];
+                var c = xs["a" to 2];
+                loop i in 42 { }
             }
         "#;
         let (_, diagnostics) = analyze_source(source);
 
-        // The current implementation doesn't fully resolve comptime expressions,
-        // so this should pass without diagnostics
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::ReversedRangeBounds(10, 1),
+                DiagnosticKind::ReversedRangeBounds(3, 1),
+                DiagnosticKind::NegativeRangeBound(-1),
+                DiagnosticKind::NonIntegerRangeBound(Type::String),
+                DiagnosticKind::NotIterable(Type::Int32),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_string_interpolation() {
+        let source = r#"
+            fn main(): 0 {
+                var name = "Bob";
+                var age = 42;
+                var greeting = "Hello ${name}, you are ${age}!\n";
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        let literals: Vec<_> = mxir
+            .0
+            .iter()
+            .filter_map(|node| match &node.data {
+                MxirNodeData::StringLiteral(string_literal) => Some(string_literal.value.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(literals, vec!["Bob", "Hello ", ", you are ", "!\n"]);
+
+        // Only the integer needs to be converted before it is concatenated
+        assert_eq!(
+            mxir.0
+                .iter()
+                .filter(|node| matches!(node.data, MxirNodeData::Stringify(_)))
+                .count(),
+            1
+        );
+        assert_eq!(
+            mxir.0
+                .iter()
+                .filter(|node| matches!(
+                    node.data,
+                    MxirNodeData::Binary(MxirBinary {
+                        op: BinaryOp::Add,
+                        ..
+                    })
+                ))
+                .count(),
+            4
+        );
     }
 
     #[test]
-    fn test_environment_scoping() {
-        let source = r#"
-            const GLOBAL: 42 = 100;
+    fn test_string_literal_diagnostics() {
+        let source = "fn main(): 0 {\n    var s = \"bad \\q ${1 + \"one\"}\";\n}";
+        let (_, diagnostics) = analyze_source(source);
+
+        let reported: Vec<_> = diagnostics
+            .into_iter()
+            .map(|d| (d.kind, d.range.start, d.range.end))
+            .collect();
+        assert_eq!(
+            reported,
+            vec![
+                (
+                    DiagnosticKind::InvalidEscapeSequence("\\q".to_string()),
+                    Point { row: 1, col: 17 },
+                    Point { row: 1, col: 19 },
+                ),
+                (
+                    DiagnosticKind::InvalidOperandTypes("+".to_string(), Type::Int32, Type::String),
+                    Point { row: 1, col: 22 },
+                    Point { row: 1, col: 31 },
+                ),
+            ]
+        );
+    }
 
+    #[test]
+    fn test_print_builtins() {
+        let source = r#"
             fn main(): 0 {
-                const LOCAL: 42 = 200;
-                {
-                    const NESTED: 42 = 300;
-                }
+                var name = "Bob";
+                println("Hello", name);
+                print(42);
+                eprint("done");
             }
         "#;
-        let (_, diagnostics) = analyze_source(source);
+        let (mxir, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        let calls: Vec<_> = mxir
+            .0
+            .iter()
+            .filter_map(|node| match &node.data {
+                MxirNodeData::CallExpr(call_expr) => {
+                    match &mxir.0[call_expr.fn_decl_ref.0 as usize].data {
+                        MxirNodeData::BuiltinFnDecl(builtin) => {
+                            Some((builtin.name.as_str(), call_expr.args.len()))
+                        }
+                        _ => None,
+                    }
+                }
+                _ => None,
+            })
+            .collect();
+        assert_eq!(calls, vec![("println", 2), ("print", 1), ("eprint", 1)]);
     }
 
     #[test]
-    fn test_function_with_body() {
+    fn test_host_fn_calls_are_checked() {
+        let source = r#"
+            fn main(): Int32 {
+                var greeting = greet("Ada");
+                greet(42);
+                return greet();
+            }
+        "#;
+        let mut host_fns = HostFns::new();
+        host_fns.register("greet", |name: String| format!("Hello, {}", name));
+        let parsed = UnparsedSourceFile::new("test.mx", source).parse();
+        let (_, diagnostics) = Sema::new(&parsed).with_host_fns(host_fns).analyze();
+
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![
+                DiagnosticKind::MismatchedArgumentType(Type::String, Type::Int32),
+                DiagnosticKind::IncorrectArgumentCount,
+            ]
+        );
+    }
+
+    #[test]
+    fn test_host_fn_cannot_shadow_builtin() {
         let source = r#"
             fn main(): 0 {
+                println("hi");
             }
         "#;
-        let (_, diagnostics) = analyze_source(source);
+        let mut host_fns = HostFns::new();
+        host_fns.register("println", |_: String| {});
+        let parsed = UnparsedSourceFile::new("test.mx", source).parse();
+        let (_, diagnostics) = Sema::new(&parsed).with_host_fns(host_fns).analyze();
+
+        let kinds: Vec<_> = diagnostics.iter().map(|d| d.kind.clone()).collect();
+        assert_eq!(
+            kinds,
+            vec![DiagnosticKind::HostFnShadowsBuiltin("println".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_function_scope_is_lexical() {
+        let source = r#"
+            var counter = 0;
+
+            fn helper(): Int32 {
+                counter = counter + 1;
+                return local;
+            }
+
+            fn main(): Int32 {
+                var local = 1;
+                return helper();
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
+
+        // The global is visible to the function, but the locals of its caller are not
+        let kinds: Vec<_> = diagnostics.into_iter().map(|d| d.kind).collect();
+        assert_eq!(
+            kinds,
+            vec![DiagnosticKind::SymbolNotFound("local".to_string())]
+        );
+        let var_exprs: Vec<_> = mxir
+            .0
+            .iter()
+            .filter_map(|node| match &node.data {
+                MxirNodeData::VarExpr(var_expr) => Some(var_expr.name.as_str()),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(var_exprs, vec!["counter", "counter"]);
+        assert!(mxir.0.iter().any(|node| matches!(
+            &node.data,
+            MxirNodeData::Nop(msg) if msg == "undefined variable"
+        )));
+    }
+
+    #[test]
+    fn test_nested_function_captures() {
+        let source = r#"
+            fn main(): Int32 {
+                var base = 40;
+                fn add(n: Int32): Int32 {
+                    return base + n;
+                }
+                return add(2);
+            }
+        "#;
+        let (mxir, diagnostics) = analyze_source(source);
 
-        // Should not report any diagnostics
         assert!(
             diagnostics.is_empty(),
             "Unexpected diagnostics: {:?}",
             diagnostics
         );
+
+        let fn_decl = |name: &str| {
+            mxir.0
+                .iter()
+                .find_map(|node| match &node.data {
+                    MxirNodeData::FnDecl(fn_decl) if fn_decl.name == name => {
+                        Some((node.self_ref, fn_decl.enclosing))
+                    }
+                    _ => None,
+                })
+                .unwrap_or_else(|| panic!("Missing fn decl for {}", name))
+        };
+        let (main_ref, main_enclosing) = fn_decl("main");
+        let (_, add_enclosing) = fn_decl("add");
+        assert_eq!(main_enclosing, None);
+        assert_eq!(add_enclosing, Some(main_ref));
     }
 
     #[test]
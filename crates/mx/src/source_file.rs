@@ -4,6 +4,7 @@ use crate::{
     ast::{Ast, AstNode, AstNodeRef},
     c_emitter::CEmitter,
     diag::Diagnostic,
+    host::HostFns,
     mxir::Mxir,
     parser::Parser,
     sema::Sema,
@@ -76,10 +77,30 @@ impl ParsedSourceFile {
         self.ast = parsed_file.ast;
     }
 
-    pub fn analyze(mut self) -> AnalyzedSourceFile {
-        let sema = Sema::new(&self);
-        let (mxir, diagnostics) = sema.analyze();
+    pub fn analyze(self) -> AnalyzedSourceFile {
+        self.analyze_with(HostFns::default())
+    }
+
+    /// Analyzes the file with the functions registered by the host in scope.
+    pub fn analyze_with(self, host_fns: HostFns) -> AnalyzedSourceFile {
+        let analysis = Sema::new(&self).with_host_fns(host_fns).analyze();
+        self.finish_analysis(analysis)
+    }
+
+    /// Analyzes the file as a library of functions for the host to call, which needs no `main`
+    /// function.
+    pub fn analyze_library(self, host_fns: HostFns) -> AnalyzedSourceFile {
+        let analysis = Sema::new(&self)
+            .with_host_fns(host_fns)
+            .without_entry_point()
+            .analyze();
+        self.finish_analysis(analysis)
+    }
 
+    fn finish_analysis(
+        mut self,
+        (mxir, diagnostics): (Mxir, Vec<Diagnostic>),
+    ) -> AnalyzedSourceFile {
         self.data.diagnostics.extend(diagnostics);
 
         AnalyzedSourceFile {
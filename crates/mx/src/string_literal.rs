@@ -0,0 +1,219 @@
+use crate::position::{Point, Range};
+
+const TRIPLE_QUOTE: &str = "\"\"\"";
+
+/// A piece of a string literal once its delimiters are stripped and its escapes decoded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum StringSegment {
+    Text(String),
+    /// An `${expr}` segment, given as the index of its range in the `holes` passed to [`decode`].
+    Interpolation(usize),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct EscapeError {
+    pub range: Range,
+    pub sequence: String,
+}
+
+/// Decodes the source text of a string literal which starts at `start`. The `holes` are the
+/// ranges of its `${expr}` segments, in source order. Both `"..."` and `"""..."""` literals are
+/// accepted, and a line break directly after an opening `"""` is not part of the string.
+pub fn decode(text: &str, start: Point, holes: &[Range]) -> (Vec<StringSegment>, Vec<EscapeError>) {
+    let is_triple_quoted =
+        text.len() >= 6 && text.starts_with(TRIPLE_QUOTE) && text.ends_with(TRIPLE_QUOTE);
+    let (open, close) = if is_triple_quoted {
+        let line_break = ["\r\n", "\n"]
+            .into_iter()
+            .find(|line_break| text[3..].starts_with(line_break))
+            .map_or(0, str::len);
+        (3 + line_break, text.len() - 3)
+    } else if text.len() >= 2 && text.starts_with('"') && text.ends_with('"') {
+        (1, text.len() - 1)
+    } else {
+        (0, text.len())
+    };
+
+    let mut segments = vec![];
+    let mut errors = vec![];
+    let mut offset = open;
+    for (i, hole) in holes.iter().enumerate() {
+        let hole_start = byte_offset(text, start, hole.start).clamp(offset, close);
+        let hole_end = byte_offset(text, start, hole.end).clamp(hole_start, close);
+        decode_text(text, start, offset..hole_start, &mut segments, &mut errors);
+        segments.push(StringSegment::Interpolation(i));
+        offset = hole_end;
+    }
+    decode_text(text, start, offset..close, &mut segments, &mut errors);
+
+    (segments, errors)
+}
+
+fn decode_text(
+    text: &str,
+    start: Point,
+    span: std::ops::Range<usize>,
+    segments: &mut Vec<StringSegment>,
+    errors: &mut Vec<EscapeError>,
+) {
+    let mut decoded = String::new();
+    let mut chars = text[span.clone()].char_indices().peekable();
+    while let Some((i, c)) = chars.next() {
+        if c != '\\' {
+            decoded.push(c);
+            continue;
+        }
+
+        let escape_start = span.start + i;
+        let value = match chars.next() {
+            Some((_, 'n')) => Some('\n'),
+            Some((_, 't')) => Some('\t'),
+            Some((_, 'r')) => Some('\r'),
+            Some((_, '0')) => Some('\0'),
+            Some((_, '\\')) => Some('\\'),
+            Some((_, '"')) => Some('"'),
+            Some((_, '\'')) => Some('\''),
+            Some((_, '$')) => Some('$'),
+            Some((_, 'u')) if chars.peek().map(|(_, c)| *c) == Some('{') => {
+                let mut digits = String::new();
+                chars.next();
+                let mut closed = false;
+                for (_, c) in chars.by_ref() {
+                    if c == '}' {
+                        closed = true;
+                        break;
+                    }
+                    digits.push(c);
+                }
+                u32::from_str_radix(&digits, 16)
+                    .ok()
+                    .and_then(char::from_u32)
+                    .filter(|_| closed)
+            }
+            _ => None,
+        };
+
+        match value {
+            Some(value) => decoded.push(value),
+            None => {
+                let escape_end = chars.peek().map_or(span.end, |(j, _)| span.start + j);
+                errors.push(EscapeError {
+                    range: Range {
+                        start: point_at(text, start, escape_start),
+                        end: point_at(text, start, escape_end),
+                    },
+                    sequence: text[escape_start..escape_end].to_string(),
+                });
+            }
+        }
+    }
+
+    if !decoded.is_empty() {
+        segments.push(StringSegment::Text(decoded));
+    }
+}
+
+/// Converts a point in the source into a byte offset within `text`, which starts at `start`.
+fn byte_offset(text: &str, start: Point, point: Point) -> usize {
+    let mut line_start = 0;
+    for _ in start.row..point.row {
+        match text[line_start..].find('\n') {
+            Some(newline) => line_start += newline + 1,
+            None => return text.len(),
+        }
+    }
+    let col = if point.row == start.row {
+        point.col.saturating_sub(start.col)
+    } else {
+        point.col
+    };
+    (line_start + col).min(text.len())
+}
+
+/// Converts a byte offset within `text`, which starts at `start`, into a point in the source.
+fn point_at(text: &str, start: Point, offset: usize) -> Point {
+    let before = &text[..offset];
+    match before.rfind('\n') {
+        Some(newline) => Point {
+            row: start.row + before.matches('\n').count(),
+            col: offset - newline - 1,
+        },
+        None => Point {
+            row: start.row,
+            col: start.col + offset,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn point(row: usize, col: usize) -> Point {
+        Point { row, col }
+    }
+
+    #[test]
+    fn test_decode_escapes() {
+        let (segments, errors) = decode(r#""a\tb\n\"c\" \u{1F600}""#, point(0, 0), &[]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            segments,
+            vec![StringSegment::Text("a\tb\n\"c\" \u{1F600}".to_string())]
+        );
+    }
+
+    #[test]
+    fn test_decode_invalid_escape() {
+        let (segments, errors) = decode(r#""a\qb""#, point(2, 4), &[]);
+        assert_eq!(segments, vec![StringSegment::Text("ab".to_string())]);
+        assert_eq!(
+            errors,
+            vec![EscapeError {
+                range: Range {
+                    start: point(2, 6),
+                    end: point(2, 8),
+                },
+                sequence: "\\q".to_string(),
+            }]
+        );
+    }
+
+    #[test]
+    fn test_decode_interpolation() {
+        // "Hello ${name}!" starting at column 10
+        let hole = Range {
+            start: point(0, 17),
+            end: point(0, 24),
+        };
+        let (segments, errors) = decode("\"Hello ${name}!\"", point(0, 10), &[hole]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            segments,
+            vec![
+                StringSegment::Text("Hello ".to_string()),
+                StringSegment::Interpolation(0),
+                StringSegment::Text("!".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_decode_triple_quoted() {
+        let text = "\"\"\"\nfirst \"line\"\n  ${x}\n\"\"\"";
+        let hole = Range {
+            start: point(3, 2),
+            end: point(3, 6),
+        };
+        let (segments, errors) = decode(text, point(1, 8), &[hole]);
+        assert!(errors.is_empty());
+        assert_eq!(
+            segments,
+            vec![
+                StringSegment::Text("first \"line\"\n  ".to_string()),
+                StringSegment::Interpolation(0),
+                StringSegment::Text("\n".to_string()),
+            ]
+        );
+    }
+}
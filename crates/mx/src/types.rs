@@ -0,0 +1,129 @@
+use std::fmt::Display;
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Type {
+    Void,
+    Bool,
+    Int32,
+    Float,
+    String,
+    Range,
+    List(Box<Type>),
+    Map(Box<Type>, Box<Type>),
+    Struct(Box<StructType>),
+    Fn(Box<FnType>),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StructType {
+    pub name: String,
+    pub fields: Vec<StructField>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct StructField {
+    pub name: String,
+    pub ty: Type,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct FnType {
+    pub params: Vec<Type>,
+    pub return_type: Type,
+}
+
+impl StructType {
+    pub fn field(&self, name: &str) -> Option<&StructField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+}
+
+impl Type {
+    /// Returns true if a value of type `other` can be stored where `self` is expected.
+    pub fn is_assignable_from(&self, other: &Type) -> bool {
+        self == other
+    }
+
+    /// Returns true if values of this type support arithmetic and ordering.
+    pub fn is_numeric(&self) -> bool {
+        matches!(self, Type::Int32 | Type::Float)
+    }
+
+    /// Returns true if values of this type can be used as map keys.
+    pub fn is_hashable(&self) -> bool {
+        matches!(self, Type::Bool | Type::Int32 | Type::String)
+    }
+}
+
+impl Display for Type {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Type::Void => write!(f, "Void"),
+            Type::Bool => write!(f, "Bool"),
+            Type::Int32 => write!(f, "Int32"),
+            Type::Float => write!(f, "Float"),
+            Type::String => write!(f, "String"),
+            Type::Range => write!(f, "Range"),
+            Type::List(element_type) => write!(f, "List[{}]", element_type),
+            Type::Map(key_type, value_type) => write!(f, "Map[{}, {}]", key_type, value_type),
+            Type::Struct(struct_type) => write!(f, "{}", struct_type.name),
+            Type::Fn(fn_type) => {
+                write!(f, "fn(")?;
+                for (i, param) in fn_type.params.iter().enumerate() {
+                    if i > 0 {
+                        write!(f, ", ")?;
+                    }
+                    write!(f, "{}", param)?;
+                }
+                write!(f, "): {}", fn_type.return_type)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_display() {
+        assert_eq!(Type::Int32.to_string(), "Int32");
+        assert_eq!(
+            Type::List(Box::new(Type::String)).to_string(),
+            "List[String]"
+        );
+        assert_eq!(
+            Type::Map(Box::new(Type::String), Box::new(Type::Int32)).to_string(),
+            "Map[String, Int32]"
+        );
+        assert_eq!(
+            Type::Fn(Box::new(FnType {
+                params: vec![Type::Int32, Type::String],
+                return_type: Type::Bool,
+            }))
+            .to_string(),
+            "fn(Int32, String): Bool"
+        );
+    }
+
+    #[test]
+    fn test_assignability() {
+        assert!(Type::Int32.is_assignable_from(&Type::Int32));
+        assert!(!Type::Int32.is_assignable_from(&Type::String));
+    }
+
+    #[test]
+    fn test_numeric() {
+        assert!(Type::Int32.is_numeric());
+        assert!(Type::Float.is_numeric());
+        assert!(!Type::String.is_numeric());
+    }
+
+    #[test]
+    fn test_hashable() {
+        assert!(Type::Int32.is_hashable());
+        assert!(Type::String.is_hashable());
+        assert!(!Type::Float.is_hashable());
+        assert!(!Type::List(Box::new(Type::Int32)).is_hashable());
+    }
+}
@@ -0,0 +1,834 @@
+use std::collections::BTreeMap;
+use std::rc::Rc;
+
+use crate::bytecode::{self, Op, Place, Program, TOP_LEVEL};
+use crate::heap::Heap;
+use crate::interpreter::{
+    apply_binary_op, apply_unary_op, condition_holds, iterate_values, list_position, node_range,
+    InterpreterValue, MapKey, RangeValue, RuntimeError, RuntimeErrorKind, StructValue,
+};
+use crate::io::Io;
+use crate::limits::{Budget, Limits};
+use crate::mxir::{BinaryOp, MxirNodeRef};
+use crate::source_file::AnalyzedSourceFile;
+
+/// The state of a variable. Unlike the interpreter, which adds variables to a scope as their
+/// declarations run, every slot of a frame exists from the start of the call.
+#[derive(Debug, Clone)]
+enum Slot {
+    Unbound,
+    Uninitialized,
+    Value(InterpreterValue),
+}
+
+struct Frame {
+    function: u32,
+    ip: usize,
+    // The frame of the function this one is lexically nested in, or of the top-level code
+    parent: usize,
+    // The height of the stack when the call began
+    stack_base: usize,
+    slots: Vec<Slot>,
+    iterators: Vec<Box<dyn Iterator<Item = InterpreterValue>>>,
+}
+
+/// Runs a source file compiled to bytecode. It behaves like the [`Interpreter`], including the
+/// runtime errors it reports, but keeps variables in slots instead of looking them up by name.
+///
+/// [`Interpreter`]: crate::interpreter::Interpreter
+pub struct Vm<'a> {
+    file: &'a AnalyzedSourceFile,
+    program: Rc<Program>,
+    stack: Vec<Option<InterpreterValue>>,
+    frames: Vec<Frame>,
+    limits: Limits,
+    io: Io,
+    heap: Heap,
+}
+
+impl<'a> Vm<'a> {
+    pub fn new(file: &'a AnalyzedSourceFile) -> Self {
+        Self {
+            file,
+            program: Rc::new(bytecode::compile(file)),
+            stack: vec![],
+            frames: vec![],
+            limits: Limits::default(),
+            io: Io::std(),
+            heap: Heap::new(),
+        }
+    }
+
+    /// Runs the program with `io` instead of the standard streams of the process.
+    pub fn with_io(mut self, io: Io) -> Self {
+        self.io = io;
+        self
+    }
+
+    /// Bounds the resources the program may use when it is executed.
+    pub fn with_limits(mut self, limits: Limits) -> Self {
+        self.limits = limits;
+        self
+    }
+
+    /// The heap the values of the program are allocated on, e.g. to check how much of it is in use.
+    pub fn heap(&self) -> &Heap {
+        &self.heap
+    }
+
+    pub fn program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Runs the program and returns the value returned by its entry point.
+    pub fn execute(&mut self) -> Result<Option<InterpreterValue>, RuntimeError> {
+        self.stack.clear();
+        self.frames.clear();
+        self.push_frame(TOP_LEVEL, vec![]);
+
+        let mut budget = Budget::start(self.limits);
+        let program = Rc::clone(&self.program);
+        loop {
+            let frame = self
+                .frames
+                .last_mut()
+                .expect("The top-level frame is never popped");
+            let function = &program.functions[frame.function as usize];
+            let ip = frame.ip;
+            frame.ip += 1;
+            let span = function.spans[ip];
+
+            if let Err(limit) = budget.step() {
+                return Err(self.error(span, RuntimeErrorKind::LimitExceeded(limit)));
+            }
+            match &function.code[ip] {
+                Op::Constant(index) => {
+                    let value = program.constants[*index as usize].clone();
+                    self.stack.push(Some(value));
+                }
+                Op::Void => self.stack.push(None),
+                Op::Pop => {
+                    self.stack.pop();
+                }
+                Op::Require => {
+                    if matches!(self.stack.last(), Some(None)) {
+                        return Err(self.error(span, RuntimeErrorKind::MissingValue));
+                    }
+                }
+                Op::Load(place) => {
+                    let (frame_index, slot) = self.resolve(*place);
+                    let value = match self.frames[frame_index].slots.get(slot) {
+                        Some(Slot::Value(value)) => value.clone(),
+                        Some(Slot::Uninitialized) => {
+                            let name = self.slot_name(frame_index, slot);
+                            return Err(
+                                self.error(span, RuntimeErrorKind::UninitializedVariable(name))
+                            );
+                        }
+                        Some(Slot::Unbound) | None => {
+                            let name = self.slot_name(frame_index, slot);
+                            return Err(self.error(span, RuntimeErrorKind::UnboundVariable(name)));
+                        }
+                    };
+                    self.stack.push(Some(value));
+                }
+                Op::Declare(place) => {
+                    let value = self.pop();
+                    let (frame_index, slot) = self.resolve(*place);
+                    if let Some(value) = &value {
+                        let name = self.slot_name(frame_index, slot);
+                        self.io.trace(format_args!(
+                            "Variable '{}' initialized with value {:?}",
+                            name, value
+                        ));
+                    }
+                    if let Some(slot) = self.frames[frame_index].slots.get_mut(slot) {
+                        *slot = value.map_or(Slot::Uninitialized, Slot::Value);
+                    }
+                }
+                Op::Store(place) => {
+                    let value = self.stack.last().cloned().flatten();
+                    let (frame_index, slot) = self.resolve(*place);
+                    match (self.frames[frame_index].slots.get_mut(slot), value) {
+                        (Some(Slot::Unbound) | None, _) => {
+                            let name = self.slot_name(frame_index, slot);
+                            return Err(self.error(span, RuntimeErrorKind::UnboundVariable(name)));
+                        }
+                        (Some(slot), Some(value)) => *slot = Slot::Value(value),
+                        (Some(_), None) => {
+                            return Err(self.error(span, RuntimeErrorKind::MissingValue))
+                        }
+                    }
+                }
+                Op::Jump(target) => self.jump(*target),
+                Op::JumpIfFalse(target) => {
+                    let condition = self.pop();
+                    match condition_holds(condition) {
+                        Ok(true) => {}
+                        Ok(false) => self.jump(*target),
+                        Err(kind) => return Err(self.error(span, kind)),
+                    }
+                }
+                Op::ShortCircuit(op, target) => {
+                    // `and` and `or` only evaluate their right operand when it can change the
+                    // result
+                    if matches!(
+                        (op, self.stack.last()),
+                        (BinaryOp::And, Some(Some(InterpreterValue::Boolean(false))))
+                            | (BinaryOp::Or, Some(Some(InterpreterValue::Boolean(true))))
+                    ) {
+                        self.jump(*target);
+                    }
+                }
+                Op::Binary(op) => {
+                    let rhs = self.pop();
+                    let lhs = self.pop();
+                    let (Some(lhs), Some(rhs)) = (lhs, rhs) else {
+                        return Err(self.error(span, RuntimeErrorKind::MissingValue));
+                    };
+                    match apply_binary_op(*op, lhs, rhs) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(kind) => return Err(self.error(span, kind)),
+                    }
+                }
+                Op::Unary(op) => {
+                    let Some(operand) = self.pop() else {
+                        return Err(self.error(span, RuntimeErrorKind::MissingValue));
+                    };
+                    match apply_unary_op(*op, operand) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(kind) => return Err(self.error(span, kind)),
+                    }
+                }
+                Op::List(len) => {
+                    let elements = self.pop_n(*len).into_iter().flatten().collect();
+                    let list = self.heap.alloc(elements);
+                    self.stack.push(Some(InterpreterValue::List(list)));
+                }
+                Op::Map(len) => {
+                    let mut entries = BTreeMap::new();
+                    let mut values = self.pop_n(len * 2).into_iter();
+                    while let (Some(key), Some(value)) = (values.next(), values.next()) {
+                        if let (Some(Ok(key)), Some(value)) = (key.map(MapKey::from_value), value) {
+                            entries.insert(key, value);
+                        }
+                    }
+                    let map = self.heap.alloc(entries);
+                    self.stack.push(Some(InterpreterValue::Map(map)));
+                }
+                Op::CheckKey => {
+                    let error = match self.stack.last() {
+                        Some(Some(
+                            InterpreterValue::Integer(_)
+                            | InterpreterValue::Boolean(_)
+                            | InterpreterValue::String(_),
+                        )) => None,
+                        Some(Some(other)) => {
+                            Some(RuntimeErrorKind::UnhashableKey(other.type_name()))
+                        }
+                        Some(None) | None => Some(RuntimeErrorKind::MissingValue),
+                    };
+                    if let Some(kind) = error {
+                        return Err(self.error(span, kind));
+                    }
+                }
+                Op::KeyAccess { map } => {
+                    let key = self.pop_key();
+                    let value = match (self.pop(), key) {
+                        (Some(InterpreterValue::Map(map)), Some(key)) => {
+                            let value = map.borrow().get(&key).cloned();
+                            match value {
+                                Some(value) => value,
+                                None => {
+                                    return Err(self.error(span, RuntimeErrorKind::MissingKey(key)))
+                                }
+                            }
+                        }
+                        (Some(InterpreterValue::Map(_)), None) | (None, _) => {
+                            return Err(self.error(*map, RuntimeErrorKind::MissingValue))
+                        }
+                        (Some(other), _) => {
+                            return Err(
+                                self.error(span, RuntimeErrorKind::NotIndexable(other.type_name()))
+                            )
+                        }
+                    };
+                    self.stack.push(Some(value));
+                }
+                Op::Index { object, index } => {
+                    let index_value = self.pop();
+                    let object_value = self.pop();
+                    match self.index(span, *object, *index, object_value, index_value) {
+                        Ok(value) => self.stack.push(Some(value)),
+                        Err(error) => return Err(error),
+                    }
+                }
+                Op::Field { name, object } => {
+                    let name = &program.names[*name as usize];
+                    let value = match self.pop() {
+                        Some(InterpreterValue::Struct(shared)) => {
+                            let struct_value = shared.borrow();
+                            match struct_value.field(name) {
+                                Some(value) => value.clone(),
+                                None => {
+                                    return Err(self.error(
+                                        span,
+                                        RuntimeErrorKind::UnknownField(
+                                            struct_value.name.clone(),
+                                            name.clone(),
+                                        ),
+                                    ))
+                                }
+                            }
+                        }
+                        // Lists, maps and strings expose their length as a builtin property
+                        Some(InterpreterValue::List(list)) if name == "len" => {
+                            InterpreterValue::Integer(list.borrow().len() as i128)
+                        }
+                        Some(InterpreterValue::Map(map)) if name == "len" => {
+                            InterpreterValue::Integer(map.borrow().len() as i128)
+                        }
+                        Some(InterpreterValue::String(string)) if name == "len" => {
+                            InterpreterValue::Integer(string.chars().count() as i128)
+                        }
+                        Some(other) => {
+                            return Err(self
+                                .error(*object, RuntimeErrorKind::NotAStruct(other.type_name())))
+                        }
+                        None => return Err(self.error(*object, RuntimeErrorKind::MissingValue)),
+                    };
+                    self.stack.push(Some(value));
+                }
+                Op::Struct { shape, len } => {
+                    let shape = &program.shapes[*shape as usize];
+                    let values = self.pop_n(*len).into_iter().flatten();
+                    let fields = shape.fields.iter().cloned().zip(values).collect();
+                    let struct_value = self.heap.alloc(StructValue {
+                        name: shape.name.clone(),
+                        fields,
+                    });
+                    self.stack
+                        .push(Some(InterpreterValue::Struct(struct_value)));
+                }
+                Op::CheckRangeBound => {
+                    let error = match self.stack.last() {
+                        Some(Some(InterpreterValue::Integer(_))) => None,
+                        Some(Some(other)) => {
+                            Some(RuntimeErrorKind::NonIntegerRangeBound(other.type_name()))
+                        }
+                        Some(None) | None => Some(RuntimeErrorKind::MissingValue),
+                    };
+                    if let Some(kind) = error {
+                        return Err(self.error(span, kind));
+                    }
+                }
+                Op::Range { start, end } => {
+                    let mut bound = |present: bool| match present.then(|| self.stack.pop()) {
+                        Some(Some(Some(InterpreterValue::Integer(value)))) => Some(value),
+                        _ => None,
+                    };
+                    let end = bound(*end);
+                    let start = bound(*start);
+                    let range = RangeValue { start, end };
+                    self.stack.push(Some(InterpreterValue::Range(range)));
+                }
+                Op::Stringify => match self.pop() {
+                    Some(value) => self
+                        .stack
+                        .push(Some(InterpreterValue::String(value.to_string()))),
+                    None => return Err(self.error(span, RuntimeErrorKind::MissingValue)),
+                },
+                Op::SetField { name, object } => {
+                    let name = &program.names[*name as usize];
+                    let value = self.stack[self.stack.len() - 2].clone();
+                    let shared = match self.pop() {
+                        Some(InterpreterValue::Struct(shared)) => shared,
+                        Some(other) => {
+                            return Err(self
+                                .error(*object, RuntimeErrorKind::NotAStruct(other.type_name())))
+                        }
+                        None => return Err(self.error(*object, RuntimeErrorKind::MissingValue)),
+                    };
+                    let mut struct_value = shared.borrow_mut();
+                    let Some(field) = struct_value.field_mut(name) else {
+                        let struct_name = struct_value.name.clone();
+                        return Err(self.error(
+                            span,
+                            RuntimeErrorKind::UnknownField(struct_name, name.clone()),
+                        ));
+                    };
+                    if let Some(value) = value {
+                        *field = value;
+                    }
+                }
+                Op::SetIndex { object, index } => {
+                    let index_value = self.pop();
+                    let object_value = self.pop();
+                    let value = self.stack.last().cloned().flatten();
+                    let list = match object_value {
+                        Some(InterpreterValue::List(list)) => list,
+                        Some(other) => {
+                            return Err(self
+                                .error(*object, RuntimeErrorKind::NotIndexable(other.type_name())))
+                        }
+                        None => return Err(self.error(*object, RuntimeErrorKind::MissingValue)),
+                    };
+                    let mut elements = list.borrow_mut();
+                    let position = match list_position(index_value, elements.len()) {
+                        Ok(position) => position,
+                        Err(kind) => return Err(self.error(*index, kind)),
+                    };
+                    if let Some(value) = value {
+                        elements[position] = value;
+                    }
+                }
+                Op::SetKey { map } => {
+                    let key = self.pop_key();
+                    let map_value = self.pop();
+                    let value = self.stack.last().cloned().flatten();
+                    let shared = match map_value {
+                        Some(InterpreterValue::Map(shared)) => shared,
+                        Some(other) => {
+                            return Err(
+                                self.error(*map, RuntimeErrorKind::NotIndexable(other.type_name()))
+                            )
+                        }
+                        None => return Err(self.error(*map, RuntimeErrorKind::MissingValue)),
+                    };
+                    if let (Some(key), Some(value)) = (key, value) {
+                        shared.borrow_mut().insert(key, value);
+                    }
+                }
+                Op::Call { function, argc } => {
+                    // Every frame but the top-level code's is an active call
+                    if let Err(limit) = budget.enter_call(self.frames.len()) {
+                        return Err(self.error(span, RuntimeErrorKind::LimitExceeded(limit)));
+                    }
+                    let args = self.pop_n(*argc).into_iter().flatten().collect();
+                    self.push_frame(*function, args);
+                }
+                Op::CallBuiltin { f, argc } => {
+                    let args: Vec<_> = self.pop_n(*argc).into_iter().flatten().collect();
+                    match f.call(&mut self.io, &args) {
+                        Ok(value) => self.stack.push(value),
+                        Err(kind) => return Err(self.error(span, kind)),
+                    }
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    self.pop_frame();
+                    if self.frames.is_empty() {
+                        return Ok(value);
+                    }
+                    self.stack.push(value);
+                }
+                Op::EscapeLoop => {
+                    self.pop_frame();
+                    let Some(caller) = self.frames.last() else {
+                        // The top-level code simply stops
+                        return Ok(None);
+                    };
+                    let call_span =
+                        program.functions[caller.function as usize].spans[caller.ip - 1];
+                    return Err(self.error(call_span, RuntimeErrorKind::LoopControlOutsideLoop));
+                }
+                Op::IterStart => {
+                    let iterable = self.pop();
+                    match iterate_values(iterable) {
+                        Ok(values) => self.current_frame().iterators.push(values),
+                        Err(kind) => return Err(self.error(span, kind)),
+                    }
+                }
+                Op::IterNext { slot, exit } => {
+                    let frame = self.current_frame();
+                    match frame.iterators.last_mut().and_then(Iterator::next) {
+                        Some(value) => frame.slots[*slot as usize] = Slot::Value(value),
+                        None => frame.ip = *exit as usize,
+                    }
+                }
+                Op::IterEnd => {
+                    self.current_frame().iterators.pop();
+                }
+                Op::Fail(kind) => return Err(self.error(span, kind.as_ref().clone())),
+            }
+        }
+    }
+
+    fn index(
+        &mut self,
+        span: MxirNodeRef,
+        object: MxirNodeRef,
+        index: MxirNodeRef,
+        object_value: Option<InterpreterValue>,
+        index_value: Option<InterpreterValue>,
+    ) -> Result<InterpreterValue, RuntimeError> {
+        // Indexing with a range produces a slice of the list or string
+        if let Some(InterpreterValue::Range(range)) = index_value {
+            return match object_value {
+                Some(InterpreterValue::List(list)) => {
+                    let elements = list.borrow();
+                    match range.resolve(elements.len()) {
+                        Ok(range) => Ok(InterpreterValue::List(
+                            self.heap.alloc(elements[range].to_vec()),
+                        )),
+                        Err(kind) => Err(self.error(index, kind)),
+                    }
+                }
+                Some(InterpreterValue::String(string)) => {
+                    let chars: Vec<char> = string.chars().collect();
+                    range
+                        .resolve(chars.len())
+                        .map(|range| InterpreterValue::String(chars[range].iter().collect()))
+                        .map_err(|kind| self.error(index, kind))
+                }
+                Some(other) => {
+                    Err(self.error(span, RuntimeErrorKind::NotIndexable(other.type_name())))
+                }
+                None => Err(self.error(object, RuntimeErrorKind::MissingValue)),
+            };
+        }
+
+        match object_value {
+            Some(InterpreterValue::List(list)) => {
+                let elements = list.borrow();
+                list_position(index_value, elements.len())
+                    .map(|position| elements[position].clone())
+                    .map_err(|kind| self.error(index, kind))
+            }
+            Some(other) => Err(self.error(span, RuntimeErrorKind::NotIndexable(other.type_name()))),
+            None => Err(self.error(object, RuntimeErrorKind::MissingValue)),
+        }
+    }
+
+    /// Enters a call of `function`. Its parent is the latest call of the function it is nested
+    /// in, or the top-level code.
+    fn push_frame(&mut self, function: u32, args: Vec<InterpreterValue>) {
+        let declaration = &self.program.functions[function as usize];
+        let parent = declaration
+            .enclosing
+            .and_then(|enclosing| {
+                self.frames
+                    .iter()
+                    .rposition(|frame| frame.function == enclosing)
+            })
+            .unwrap_or(0);
+
+        let mut slots = vec![Slot::Unbound; declaration.slot_names.len()];
+        for (slot, arg) in slots.iter_mut().zip(args) {
+            *slot = Slot::Value(arg);
+        }
+
+        self.frames.push(Frame {
+            function,
+            ip: 0,
+            parent,
+            stack_base: self.stack.len(),
+            slots,
+            iterators: vec![],
+        });
+    }
+
+    fn pop_frame(&mut self) {
+        if let Some(frame) = self.frames.pop() {
+            self.stack.truncate(frame.stack_base);
+        }
+    }
+
+    fn current_frame(&mut self) -> &mut Frame {
+        self.frames
+            .last_mut()
+            .expect("The top-level frame is never popped")
+    }
+
+    fn jump(&mut self, target: u32) {
+        self.current_frame().ip = target as usize;
+    }
+
+    /// Finds the frame and slot a variable is stored in.
+    fn resolve(&self, place: Place) -> (usize, usize) {
+        let current = self.frames.len() - 1;
+        match place {
+            Place::Local(slot) => (current, slot as usize),
+            Place::Global(slot) => (0, slot as usize),
+            Place::Outer { depth, slot } => {
+                let mut frame_index = current;
+                for _ in 0..depth {
+                    frame_index = self.frames[frame_index].parent;
+                }
+                (frame_index, slot as usize)
+            }
+        }
+    }
+
+    fn slot_name(&self, frame_index: usize, slot: usize) -> String {
+        let function = self.frames[frame_index].function;
+        self.program.functions[function as usize]
+            .slot_names
+            .get(slot)
+            .cloned()
+            .unwrap_or_default()
+    }
+
+    fn pop(&mut self) -> Option<InterpreterValue> {
+        self.stack.pop().flatten()
+    }
+
+    fn pop_n(&mut self, n: u32) -> Vec<Option<InterpreterValue>> {
+        let len = self.stack.len();
+        self.stack.split_off(len - n as usize)
+    }
+
+    /// Pops a key that has been checked to be hashable.
+    fn pop_key(&mut self) -> Option<MapKey> {
+        self.pop().and_then(|key| MapKey::from_value(key).ok())
+    }
+
+    fn error(&self, node_ref: MxirNodeRef, kind: RuntimeErrorKind) -> RuntimeError {
+        RuntimeError {
+            range: node_range(self.file, node_ref),
+            kind: Box::new(kind),
+            call_stack: self
+                .frames
+                .iter()
+                .rev()
+                .filter(|frame| frame.function != TOP_LEVEL)
+                .map(|frame| self.program.functions[frame.function as usize].name.clone())
+                .collect(),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::interpreter::Interpreter;
+    use crate::limits::Limit;
+    use crate::source_file::UnparsedSourceFile;
+
+    /// Runs the source with both the interpreter and the VM, which must agree.
+    fn run(source: &str) -> Result<Option<InterpreterValue>, RuntimeError> {
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        assert!(
+            analyzed.file().diagnostics.is_empty(),
+            "Unexpected diagnostics: {:?}",
+            analyzed.file().diagnostics
+        );
+        let expected = Interpreter::new(&analyzed).execute();
+        let result = Vm::new(&analyzed).execute();
+        assert_eq!(result, expected);
+        result
+    }
+
+    #[test]
+    fn test_recursion_and_loops() {
+        let source = r#"
+            fn fib(n: Int32): Int32 {
+                if n < 2 {
+                    return n;
+                }
+                return fib(n - 1) + fib(n - 2);
+            }
+
+            fn main(): Int32 {
+                var total = 0;
+                loop i in 0 to 10 {
+                    if i == 3 {
+                        continue;
+                    }
+                    total = total + fib(i);
+                }
+                var i = 0;
+                loop {
+                    i = i + 1;
+                    if i > 5 and total > 0 {
+                        break;
+                    }
+                }
+                return total + i;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(92))));
+    }
+
+    #[test]
+    fn test_globals_and_captures() {
+        let source = r#"
+            var calls = 0;
+
+            fn count(): Int32 {
+                calls = calls + 1;
+                return calls;
+            }
+
+            fn main(): Int32 {
+                var base = 40;
+                fn add(n: Int32): Int32 {
+                    count();
+                    return base + n;
+                }
+                base = base - 1;
+                return add(2) + add(1) + calls;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(83))));
+    }
+
+    #[test]
+    fn test_collections_and_assignment() {
+        let source = r#"
+            struct Point {
+                var x: Int32;
+                var y: Int32;
+            }
+
+            fn main(): Int32 {
+                var points = [new Point { x: 1, y: 2 }, new Point { x: 3, y: 4 }];
+                points[1].y = 10;
+                var names = map{"a": 1};
+                names["b"] = points[1].y;
+                var digits = [0, 1, 2, 3, 4][1 to _];
+                var total = 0;
+                loop d in digits {
+                    total = total + d;
+                }
+                return total + names["b"] + names.len + "héllo"[1 to 3].len;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(24))));
+    }
+
+    #[test]
+    fn test_values_are_shared() {
+        let source = r#"
+            struct Point {
+                var x: Int32;
+            }
+
+            fn bump(p: Point): Int32 {
+                p.x = p.x + 1;
+                return p.x;
+            }
+
+            fn main(): Int32 {
+                var a = new Point { x: 1 };
+                var b = a;
+                b.x = 5;
+                bump(a);
+                var points = [a];
+                points[0].x = points[0].x * 10;
+                var named = map{"a": points};
+                named["a"][0].x = named["a"][0].x + 7;
+                return a.x;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(67))));
+    }
+
+    #[test]
+    fn test_runtime_errors_match() {
+        let source = r#"
+            fn divide(a: Int32, b: Int32): Int32 {
+                return a / b;
+            }
+
+            fn main(): Int32 {
+                var xs = [1, 2, 3];
+                loop i in 0 to 3 {
+                    xs[i] = divide(6, 2 - i);
+                }
+                return 0;
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(*error.kind, RuntimeErrorKind::DivisionByZero);
+        assert_eq!(error.call_stack, vec!["divide", "main"]);
+
+        let source = r#"
+            fn main(): Int32 {
+                var xs = [1, 2, 3];
+                return xs[5];
+            }
+        "#;
+        let error = run(source).unwrap_err();
+        assert_eq!(*error.kind, RuntimeErrorKind::IndexOutOfBounds(5, 3));
+    }
+
+    #[test]
+    fn test_strings_and_builtins() {
+        let source = r#"
+            fn main(): Int32 {
+                var name = "world";
+                var greeting = "Hello, ${name}! ${1 + 2}";
+                println(greeting);
+                return greeting.len;
+            }
+        "#;
+        assert_eq!(run(source), Ok(Some(InterpreterValue::Integer(15))));
+    }
+
+    #[test]
+    fn test_floats() {
+        let source = r#"
+            const PI = 3.14159;
+
+            fn main(): String {
+                var r = 2;
+                var area = PI * r * r;
+                var whole = 4.0 / 2;
+                return "${area} ${whole} ${-1.5 < 1} ${1 == 1.0}";
+            }
+        "#;
+        assert_eq!(
+            run(source),
+            Ok(Some(InterpreterValue::String(
+                "12.56636 2.0 true true".to_string()
+            )))
+        );
+    }
+
+    #[test]
+    fn test_limits() {
+        let source = r#"
+            fn down(n: Int32): Int32 {
+                return down(n + 1);
+            }
+
+            fn main(): Int32 {
+                loop {}
+                return 0;
+            }
+        "#;
+        let analyzed = UnparsedSourceFile::new("test.mx", source).parse().analyze();
+        let limits = Limits {
+            fuel: Some(1000),
+            ..Limits::default()
+        };
+        let error = Vm::new(&analyzed)
+            .with_limits(limits)
+            .execute()
+            .unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::LimitExceeded(Limit::Fuel(1000))
+        );
+
+        let source = source.replace("loop {}", "down(0);");
+        let analyzed = UnparsedSourceFile::new("test.mx", &source)
+            .parse()
+            .analyze();
+        let limits = Limits {
+            max_call_depth: 8,
+            ..Limits::default()
+        };
+        let expected = Interpreter::new(&analyzed).with_limits(limits).execute();
+        let error = Vm::new(&analyzed)
+            .with_limits(limits)
+            .execute()
+            .unwrap_err();
+        assert_eq!(
+            *error.kind,
+            RuntimeErrorKind::LimitExceeded(Limit::CallDepth(8))
+        );
+        assert_eq!(Err(error), expected);
+    }
+}